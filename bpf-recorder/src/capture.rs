@@ -0,0 +1,356 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    sniffer_event::{SnifferEvent, SnifferEventVariant},
+    DataTag,
+};
+
+/// The last byte is the version of the format
+const MAGIC: [u8; 8] = *b"MINACAP\x01";
+
+/// The records of version 0 have no cgroup
+const VERSION_NO_CGROUP: u8 = 0;
+
+/// The kernel sends at most `1 << 27` bytes of data in one event, see `send::dyn_sized`,
+/// the rest of the record is the metadata
+const MAX_RECORD_SIZE: usize = (1 << 27) + 0x100;
+
+/// Append-only file of sniffer events.
+/// The file starts with the magic and the boot time (seconds since UNIX epoch),
+/// which is the origin of `ts0` and `ts1`. Then records follow,
+/// each record is prefixed by its length.
+/// The events recorded after a reboot cannot be appended to the file.
+pub struct CaptureWriter {
+    inner: BufWriter<File>,
+}
+
+impl CaptureWriter {
+    pub fn open<P>(path: P, boot_time: Option<SystemTime>) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let secs = boot_time
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        if file.metadata()?.len() != 0 {
+            let mut header = [0; 16];
+            file.read_exact(&mut header)?;
+            if header[..8] != MAGIC {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "cannot append to the capture file of other version",
+                ));
+            }
+            if header[8..] != secs.to_le_bytes() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot append to the capture file recorded since other boot",
+                ));
+            }
+        }
+        let mut inner = BufWriter::new(file);
+        if inner.get_ref().metadata()?.len() == 0 {
+            inner.write_all(&MAGIC)?;
+            inner.write_all(&secs.to_le_bytes())?;
+        }
+
+        Ok(CaptureWriter { inner })
+    }
+
+    pub fn write(&mut self, event: &SnifferEvent) -> io::Result<()> {
+        let record = encode(event);
+        self.inner.write_all(&(record.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&record)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct CaptureReader {
+    inner: BufReader<File>,
+    version: u8,
+    boot_time: Option<SystemTime>,
+}
+
+impl CaptureReader {
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut inner = BufReader::new(File::open(path)?);
+        let mut header = [0; 16];
+        inner.read_exact(&mut header)?;
+        let version = header[7];
+        if header[..7] != MAGIC[..7] || version > MAGIC[7] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a capture file",
+            ));
+        }
+        let secs = u64::from_le_bytes(header[8..].try_into().expect("cannot fail"));
+        let boot_time = if secs == 0 {
+            None
+        } else {
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+        };
+
+        Ok(CaptureReader {
+            inner,
+            version,
+            boot_time,
+        })
+    }
+
+    /// The time the timestamps of the events refer to
+    pub fn boot_time(&self) -> Option<SystemTime> {
+        self.boot_time
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = io::Result<SnifferEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut len = [0; 4];
+        match self.inner.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+            Err(err) => return Some(Err(err)),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_SIZE {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("capture record of {len} bytes is too big"),
+            )));
+        }
+        let mut record = vec![0; len];
+        if let Err(err) = self.inner.read_exact(&mut record) {
+            return Some(Err(err));
+        }
+        Some(
+            decode(&record, self.version).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "malformed capture record")
+            }),
+        )
+    }
+}
+
+fn encode(event: &SnifferEvent) -> Vec<u8> {
+    fn addr(b: &mut Vec<u8>, addr: &SocketAddr) {
+        match addr.ip() {
+            IpAddr::V4(ip) => {
+                b.push(4);
+                b.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                b.push(6);
+                b.extend_from_slice(&ip.octets());
+            }
+        }
+        b.extend_from_slice(&addr.port().to_le_bytes());
+    }
+
+    let mut b = Vec::with_capacity(0x40);
+    b.extend_from_slice(&event.pid.to_le_bytes());
+    b.extend_from_slice(&event.tid.to_le_bytes());
+    b.extend_from_slice(&event.fd.to_le_bytes());
+    b.extend_from_slice(&event.ts0.to_le_bytes());
+    b.extend_from_slice(&event.ts1.to_le_bytes());
+    b.extend_from_slice(&event.cgroup.to_le_bytes());
+    match &event.variant {
+        SnifferEventVariant::NewApp(alias) => {
+            b.push(0);
+            b.extend_from_slice(alias.as_bytes());
+        }
+        SnifferEventVariant::NewSnarkWorkerApp => b.push(1),
+        SnifferEventVariant::Bind(a) => {
+            b.push(2);
+            addr(&mut b, a);
+        }
+        SnifferEventVariant::IncomingConnection(a) => {
+            b.push(3);
+            addr(&mut b, a);
+        }
+        SnifferEventVariant::OutgoingConnection(a) => {
+            b.push(4);
+            addr(&mut b, a);
+        }
+        SnifferEventVariant::Disconnected => b.push(5),
+        SnifferEventVariant::IncomingData(data) => {
+            b.push(6);
+            b.extend_from_slice(data);
+        }
+        SnifferEventVariant::OutgoingData(data) => {
+            b.push(7);
+            b.extend_from_slice(data);
+        }
+        SnifferEventVariant::Random(data) => {
+            b.push(8);
+            b.extend_from_slice(data);
+        }
+        SnifferEventVariant::GetSockOpt(data) => {
+            b.push(9);
+            b.extend_from_slice(data);
+        }
+        SnifferEventVariant::Error(tag, code) => {
+            b.push(10);
+            b.extend_from_slice(&(*tag as u32).to_le_bytes());
+            b.extend_from_slice(&code.to_le_bytes());
+        }
+        SnifferEventVariant::IncomingPlaintext(data) => {
+            b.push(11);
+            b.extend_from_slice(data);
+        }
+        SnifferEventVariant::OutgoingPlaintext(data) => {
+            b.push(12);
+            b.extend_from_slice(data);
+        }
+    }
+
+    b
+}
+
+fn decode(b: &[u8], version: u8) -> Option<SnifferEvent> {
+    fn addr(b: &[u8]) -> Option<SocketAddr> {
+        let (ip, port) = match b.first()? {
+            4 => {
+                let ip = <[u8; 4]>::try_from(b.get(1..5)?).ok()?;
+                (IpAddr::V4(ip.into()), b.get(5..7)?)
+            }
+            6 => {
+                let ip = <[u8; 16]>::try_from(b.get(1..17)?).ok()?;
+                (IpAddr::V6(ip.into()), b.get(17..19)?)
+            }
+            _ => return None,
+        };
+        let port = u16::from_le_bytes(port.try_into().ok()?);
+        Some(SocketAddr::new(ip, port))
+    }
+
+    let u32_at = |i: usize| Some(u32::from_le_bytes(b.get(i..(i + 4))?.try_into().ok()?));
+    let u64_at = |i: usize| Some(u64::from_le_bytes(b.get(i..(i + 8))?.try_into().ok()?));
+
+    let (cgroup, kind) = if version == VERSION_NO_CGROUP {
+        (0, 28)
+    } else {
+        (u64_at(28)?, 36)
+    };
+    let body = b.get((kind + 1)..)?;
+    let variant = match b.get(kind)? {
+        0 => SnifferEventVariant::NewApp(String::from_utf8(body.to_vec()).ok()?),
+        1 => SnifferEventVariant::NewSnarkWorkerApp,
+        2 => SnifferEventVariant::Bind(addr(body)?),
+        3 => SnifferEventVariant::IncomingConnection(addr(body)?),
+        4 => SnifferEventVariant::OutgoingConnection(addr(body)?),
+        5 => SnifferEventVariant::Disconnected,
+        6 => SnifferEventVariant::IncomingData(body.to_vec()),
+        7 => SnifferEventVariant::OutgoingData(body.to_vec()),
+        8 => SnifferEventVariant::Random(body.to_vec()),
+        9 => SnifferEventVariant::GetSockOpt(body.to_vec()),
+        10 => SnifferEventVariant::Error(
+            DataTag::from_u32(u32_at(kind + 1)?)?,
+            u32_at(kind + 5)? as i32,
+        ),
+        11 => SnifferEventVariant::IncomingPlaintext(body.to_vec()),
+        12 => SnifferEventVariant::OutgoingPlaintext(body.to_vec()),
+        _ => return None,
+    };
+
+    Some(SnifferEvent {
+        pid: u32_at(0)?,
+        tid: u32_at(4)?,
+        fd: u32_at(8)?,
+        ts0: u64_at(12)?,
+        ts1: u64_at(20)?,
+        cgroup,
+        variant,
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn capture_roundtrip() {
+    use std::fs;
+    use temp_dir::TempDir;
+
+    let d = TempDir::new().expect("cannot create temporary directory");
+    let path = d.path().join("capture");
+
+    let event = |fd, ts, variant| SnifferEvent {
+        pid: 1234,
+        tid: 1235,
+        fd,
+        ts0: ts,
+        ts1: ts + 10,
+        cgroup: 0x1234,
+        variant,
+    };
+    let events = vec![
+        event(0, 1, SnifferEventVariant::NewApp("devnet-node".to_owned())),
+        event(
+            7,
+            2,
+            SnifferEventVariant::OutgoingConnection("1.2.3.4:8302".parse().unwrap()),
+        ),
+        event(7, 3, SnifferEventVariant::GetSockOpt(vec![0, 0, 0, 0])),
+        event(7, 4, SnifferEventVariant::OutgoingData(b"hello".to_vec())),
+        event(
+            8,
+            5,
+            SnifferEventVariant::IncomingConnection("[::1]:1234".parse().unwrap()),
+        ),
+        event(8, 6, SnifferEventVariant::IncomingData(vec![])),
+        event(8, 7, SnifferEventVariant::Error(DataTag::Read, -90)),
+        event(
+            0,
+            8,
+            SnifferEventVariant::OutgoingPlaintext(b"plain".to_vec()),
+        ),
+        event(7, 9, SnifferEventVariant::Disconnected),
+    ];
+
+    // write in two sessions, the file is append only
+    let boot_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for chunk in events.chunks(5) {
+        let mut writer = CaptureWriter::open(&path, Some(boot_time)).unwrap();
+        for event in chunk {
+            writer.write(event).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+
+    // the timestamps of the other boot would be wrong
+    let other_boot_time = boot_time + Duration::from_secs(3600);
+    let err = CaptureWriter::open(&path, Some(other_boot_time))
+        .err()
+        .expect("must refuse other boot time");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let reader = CaptureReader::open(&path).unwrap();
+    assert_eq!(reader.boot_time(), Some(boot_time));
+    let decoded = reader.collect::<io::Result<Vec<_>>>().unwrap();
+    assert_eq!(format!("{events:?}"), format!("{decoded:?}"));
+
+    // the length of the corrupted record
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    fs::write(&path, bytes).unwrap();
+    let mut reader = CaptureReader::open(&path).unwrap();
+    let err = reader.nth(events.len()).unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
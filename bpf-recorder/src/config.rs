@@ -0,0 +1,483 @@
+use std::{env, fmt, fs, io, path::PathBuf, str::FromStr};
+
+use mina_recorder::application::ProcessMatching;
+use serde::{Serialize, Deserialize};
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// The kernel has `event_queue` and `event_queue_1` ... `event_queue_3`
+pub const EVENT_QUEUE_SHARDS_MAX: u32 = 4;
+
+/// Command line arguments. Every option can also be set by the environment variable named
+/// as the option in upper snake case, e.g. `SERVER_PORT` for `--server-port`,
+/// the command line has priority over the environment, the environment over the file.
+/// The flag is enabled by `--flag` or by any value except `0`, `false`, `no` and `off`.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "bpf-recorder")]
+pub struct Args {
+    /// Path to the configuration file in TOML format
+    #[structopt(long)]
+    pub config: Option<PathBuf>,
+    /// Port where debugger will listen http requests
+    #[structopt(long)]
+    pub server_port: Option<u16>,
+    #[structopt(long)]
+    pub db_path: Option<PathBuf>,
+    /// Do not capture anything, useful for inspecting the database
+    #[structopt(long)]
+    pub dry: Option<Option<String>>,
+    #[structopt(long)]
+    pub https_key_path: Option<PathBuf>,
+    #[structopt(long)]
+    pub https_cert_path: Option<PathBuf>,
+    /// Interface name where firewall will be attached
+    #[structopt(long)]
+    pub firewall_interface: Option<String>,
+    /// Do not decode the traffic, only check it, used by the tester
+    #[structopt(long)]
+    pub test: Option<Option<String>>,
+    /// Terminate when the debuggee process is terminated
+    #[structopt(long)]
+    pub terminate: Option<Option<String>>,
+    /// Url of the aggregator
+    #[structopt(long)]
+    pub aggregator: Option<String>,
+    /// The name reported to the aggregator
+    #[structopt(long)]
+    pub debugger_name: Option<String>,
+    /// Append every event received from the kernel to this file
+    #[structopt(long)]
+    pub record_capture: Option<PathBuf>,
+    /// Do not load BPF, process the events from this file instead
+    #[structopt(long)]
+    pub replay_capture: Option<PathBuf>,
+    /// Track processes whose first argument starts with one of these, comma separated
+    #[structopt(long, use_delimiter = true)]
+    pub process_names: Option<Vec<String>>,
+    /// Environment variable which holds the alias of the process
+    #[structopt(long)]
+    pub process_env_flag: Option<String>,
+    /// Track these processes regardless of their name, comma separated
+    #[structopt(long, use_delimiter = true)]
+    pub process_pids: Option<Vec<u32>>,
+    /// Track every process of these cgroups, comma separated
+    #[structopt(long, use_delimiter = true)]
+    pub process_cgroups: Option<Vec<u64>>,
+    /// Track matching processes which are already running and their connections
+    #[structopt(long)]
+    pub attach: Option<Option<String>>,
+    /// How often to sample the TCP state of the connections, in seconds, 0 disables
+    #[structopt(long)]
+    pub tcp_info_interval: Option<u64>,
+    /// Number of ring buffers the kernel sends the events to, chosen by cpu, from 1 to 4
+    #[structopt(long)]
+    pub event_queue_shards: Option<u32>,
+    /// How long to wait for the events from other ring buffers to order them, in milliseconds
+    #[structopt(long)]
+    pub reorder_window_ms: Option<u64>,
+    /// Capture the plaintext by uprobes on the Noise session of the Go libp2p helper
+    #[structopt(long)]
+    pub plaintext_uprobes: Option<Option<String>>,
+    /// Chain ids the pnet tries if the guessed one does not decrypt the connection, comma separated
+    #[structopt(long, use_delimiter = true)]
+    pub chain_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server_port: u16,
+    pub db_path: PathBuf,
+    pub dry: bool,
+    pub https_key_path: Option<PathBuf>,
+    pub https_cert_path: Option<PathBuf>,
+    pub firewall_interface: String,
+    pub test: bool,
+    pub terminate: bool,
+    pub aggregator: Option<String>,
+    pub debugger_name: String,
+    pub record_capture: Option<PathBuf>,
+    pub replay_capture: Option<PathBuf>,
+    pub attach: bool,
+    pub tcp_info_interval: u64,
+    pub event_queue_shards: u32,
+    pub reorder_window_ms: u64,
+    pub plaintext_uprobes: bool,
+    pub chain_ids: Vec<String>,
+    pub process_matching: ProcessMatching,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server_port: 8000,
+            db_path: PathBuf::from("target/db"),
+            dry: false,
+            https_key_path: None,
+            https_cert_path: None,
+            firewall_interface: "eth0".to_owned(),
+            test: false,
+            terminate: false,
+            aggregator: None,
+            debugger_name: "noname".to_owned(),
+            record_capture: None,
+            replay_capture: None,
+            attach: false,
+            tcp_info_interval: 5,
+            event_queue_shards: 1,
+            reorder_window_ms: 20,
+            plaintext_uprobes: false,
+            chain_ids: vec![],
+            process_matching: ProcessMatching::default(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("cannot read config file {path}: {err}")]
+    Read { path: PathBuf, err: io::Error },
+    #[error("cannot parse config file {path}: {err}")]
+    Parse { path: PathBuf, err: toml::de::Error },
+    #[error("invalid value {value} of environment variable {name}: {err}")]
+    Env {
+        name: &'static str,
+        value: String,
+        err: String,
+    },
+    #[error("invalid aggregator url {url}: {err}")]
+    AggregatorUrl { url: String, err: url::ParseError },
+    #[error("both https key and certificate paths must be set")]
+    HttpsIncomplete,
+    #[error("file {0} does not exist")]
+    NoSuchFile(PathBuf),
+    #[error("cannot record and replay capture simultaneously")]
+    RecordAndReplay,
+    #[error("number of event queue shards {0} must be from 1 to {EVENT_QUEUE_SHARDS_MAX}")]
+    EventQueueShards(u32),
+    #[error("invalid process matching: {0}")]
+    ProcessMatching(String),
+}
+
+/// Reads the environment variables, `std::env::var` outside of the tests
+struct Env<F>(F);
+
+impl<F> Env<F>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn parse<T>(&self, name: &'static str, value: String) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        value.parse().map_err(|err: T::Err| ConfigError::Env {
+            name,
+            err: err.to_string(),
+            value,
+        })
+    }
+
+    /// The value of the argument or of the environment variable
+    fn value<T>(&self, arg: Option<T>, name: &'static str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match arg {
+            Some(v) => Ok(Some(v)),
+            None => (self.0)(name).map(|v| self.parse(name, v)).transpose(),
+        }
+    }
+
+    /// The list of the argument or of the environment variable, comma separated
+    fn list<T>(
+        &self,
+        arg: Option<Vec<T>>,
+        name: &'static str,
+    ) -> Result<Option<Vec<T>>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match arg {
+            Some(v) => Ok(Some(v)),
+            None => (self.0)(name)
+                .map(|v| {
+                    v.split(',')
+                        .map(|item| self.parse(name, item.to_owned()))
+                        .collect()
+                })
+                .transpose(),
+        }
+    }
+
+    /// The flag of the command line, `Some(None)` if it has no value, or the environment variable
+    fn flag(&self, arg: Option<Option<String>>, name: &str) -> Option<bool> {
+        let v = arg
+            .map(Option::unwrap_or_default)
+            .or_else(|| (self.0)(name))?;
+        Some(!matches!(
+            v.to_lowercase().as_str(),
+            "0" | "false" | "no" | "off"
+        ))
+    }
+}
+
+impl Args {
+    /// Read the file if any, apply the environment and the arguments on top of it
+    /// and validate the result
+    pub fn into_config(self) -> Result<Config, ConfigError> {
+        self.into_config_with_env(|name| env::var(name).ok())
+    }
+
+    /// Same as `into_config`, but the environment variables are given by `env`
+    pub fn into_config_with_env<F>(self, env: F) -> Result<Config, ConfigError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let env = Env(env);
+        let mut config = match env.value(self.config, "CONFIG")? {
+            Some(path) => {
+                let s = fs::read_to_string(&path).map_err(|err| ConfigError::Read {
+                    path: path.clone(),
+                    err,
+                })?;
+                toml::from_str(&s).map_err(|err| ConfigError::Parse { path, err })?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(v) = env.value(self.server_port, "SERVER_PORT")? {
+            config.server_port = v;
+        }
+        if let Some(v) = env.value(self.db_path, "DB_PATH")? {
+            config.db_path = v;
+        }
+        if let Some(v) = env.flag(self.dry, "DRY") {
+            config.dry = v;
+        }
+        if let Some(v) = env.value(self.https_key_path, "HTTPS_KEY_PATH")? {
+            config.https_key_path = Some(v);
+        }
+        if let Some(v) = env.value(self.https_cert_path, "HTTPS_CERT_PATH")? {
+            config.https_cert_path = Some(v);
+        }
+        if let Some(v) = env.value(self.firewall_interface, "FIREWALL_INTERFACE")? {
+            config.firewall_interface = v;
+        }
+        if let Some(v) = env.flag(self.test, "TEST") {
+            config.test = v;
+        }
+        if let Some(v) = env.flag(self.terminate, "TERMINATE") {
+            config.terminate = v;
+        }
+        if let Some(v) = env.value(self.aggregator, "AGGREGATOR")? {
+            config.aggregator = Some(v);
+        }
+        if let Some(v) = env.value(self.debugger_name, "DEBUGGER_NAME")? {
+            config.debugger_name = v;
+        }
+        if let Some(v) = env.value(self.record_capture, "RECORD_CAPTURE")? {
+            config.record_capture = Some(v);
+        }
+        if let Some(v) = env.value(self.replay_capture, "REPLAY_CAPTURE")? {
+            config.replay_capture = Some(v);
+        }
+        if let Some(v) = env.list(self.process_names, "PROCESS_NAMES")? {
+            config.process_matching.names = v;
+        }
+        if let Some(v) = env.value(self.process_env_flag, "PROCESS_ENV_FLAG")? {
+            config.process_matching.env_flag = v;
+        }
+        if let Some(v) = env.list(self.process_pids, "PROCESS_PIDS")? {
+            config.process_matching.pids = v;
+        }
+        if let Some(v) = env.list(self.process_cgroups, "PROCESS_CGROUPS")? {
+            config.process_matching.cgroups = v;
+        }
+        if let Some(v) = env.flag(self.attach, "ATTACH") {
+            config.attach = v;
+        }
+        if let Some(v) = env.value(self.tcp_info_interval, "TCP_INFO_INTERVAL")? {
+            config.tcp_info_interval = v;
+        }
+        if let Some(v) = env.value(self.event_queue_shards, "EVENT_QUEUE_SHARDS")? {
+            config.event_queue_shards = v;
+        }
+        if let Some(v) = env.value(self.reorder_window_ms, "REORDER_WINDOW_MS")? {
+            config.reorder_window_ms = v;
+        }
+        if let Some(v) = env.flag(self.plaintext_uprobes, "PLAINTEXT_UPROBES") {
+            config.plaintext_uprobes = v;
+        }
+        if let Some(v) = env.list(self.chain_ids, "CHAIN_IDS")? {
+            config.chain_ids = v;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.aggregator_url()?;
+        match (&self.https_key_path, &self.https_cert_path) {
+            (Some(key_path), Some(cert_path)) => {
+                for path in [key_path, cert_path] {
+                    if !path.exists() {
+                        return Err(ConfigError::NoSuchFile(path.clone()));
+                    }
+                }
+            }
+            (None, None) => (),
+            _ => return Err(ConfigError::HttpsIncomplete),
+        }
+        if let Some(path) = &self.replay_capture {
+            if self.record_capture.is_some() {
+                return Err(ConfigError::RecordAndReplay);
+            }
+            if !path.exists() {
+                return Err(ConfigError::NoSuchFile(path.clone()));
+            }
+        }
+        if !(1..=EVENT_QUEUE_SHARDS_MAX).contains(&self.event_queue_shards) {
+            return Err(ConfigError::EventQueueShards(self.event_queue_shards));
+        }
+        self.process_matching
+            .validate()
+            .map_err(ConfigError::ProcessMatching)?;
+
+        Ok(())
+    }
+
+    /// The url where the events should be posted
+    pub fn aggregator_url(&self) -> Result<Option<reqwest::Url>, ConfigError> {
+        let Some(url) = &self.aggregator else {
+            return Ok(None);
+        };
+        let err = |err| ConfigError::AggregatorUrl {
+            url: url.clone(),
+            err,
+        };
+        url.parse::<reqwest::Url>()
+            .and_then(|base| base.join("new"))
+            .map(Some)
+            .map_err(err)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn config_file_and_overrides() {
+    use std::collections::BTreeMap;
+
+    let dir = temp_dir::TempDir::new().expect("cannot create temporary directory");
+    let path = dir.child("config.toml");
+    let path = path.to_str().unwrap();
+    fs::write(
+        path,
+        "server_port = 80\ndb_path = \"/tmp/db\"\naggregator = \"http://localhost:8000\"\n",
+    )
+    .unwrap();
+
+    let no_env = |_: &str| None;
+
+    let args = Args::from_iter(["bpf-recorder", "--config", path, "--server-port", "443"]);
+    let config = args.into_config_with_env(no_env).unwrap();
+    assert_eq!(config.server_port, 443);
+    assert_eq!(config.db_path, PathBuf::from("/tmp/db"));
+    assert_eq!(config.firewall_interface, "eth0");
+    assert_eq!(
+        config.aggregator_url().unwrap().unwrap().as_str(),
+        "http://localhost:8000/new"
+    );
+    assert_eq!(config.process_matching, ProcessMatching::default());
+    assert_eq!(config.tcp_info_interval, 5);
+
+    let args = Args::from_iter([
+        "bpf-recorder",
+        "--process-names",
+        "mina,openmina",
+        "--process-pids",
+        "10,20",
+        "--tcp-info-interval",
+        "0",
+        "--chain-ids",
+        "/coda/0.0.1/a,/coda/0.0.1/b",
+    ]);
+    let config = args.into_config_with_env(no_env).unwrap();
+    assert_eq!(config.process_matching.names, ["mina", "openmina"]);
+    assert_eq!(config.process_matching.pids, [10, 20]);
+    assert_eq!(config.process_matching.env_flag, "BPF_ALIAS");
+    assert_eq!(config.tcp_info_interval, 0);
+    assert!(!config.plaintext_uprobes);
+    assert_eq!(config.chain_ids, ["/coda/0.0.1/a", "/coda/0.0.1/b"]);
+
+    // the environment is over the file, the command line is over the environment
+    let env = BTreeMap::from([
+        ("CONFIG", path),
+        ("SERVER_PORT", "8080"),
+        ("DB_PATH", "/tmp/env-db"),
+        ("PROCESS_PIDS", "30,40"),
+        ("PLAINTEXT_UPROBES", "1"),
+    ]);
+    let env = |name: &str| env.get(name).map(|v| v.to_string());
+    let args = Args::from_iter(["bpf-recorder", "--db-path", "/tmp/arg-db"]);
+    let config = args.into_config_with_env(env).unwrap();
+    assert_eq!(config.server_port, 8080);
+    assert_eq!(config.db_path, PathBuf::from("/tmp/arg-db"));
+    assert_eq!(config.process_matching.pids, [30, 40]);
+    assert!(config.plaintext_uprobes);
+    assert!(config.aggregator.is_some());
+
+    let env = |name: &str| (name == "SERVER_PORT").then(|| "x".to_owned());
+    let args = Args::from_iter(["bpf-recorder"]);
+    assert!(matches!(
+        args.into_config_with_env(env),
+        Err(ConfigError::Env {
+            name: "SERVER_PORT",
+            ..
+        })
+    ));
+
+    let args = Args::from_iter(["bpf-recorder", "--process-env-flag", "A=B"]);
+    assert!(matches!(
+        args.into_config_with_env(no_env),
+        Err(ConfigError::ProcessMatching(_))
+    ));
+
+    let args = Args::from_iter(["bpf-recorder", "--event-queue-shards", "5"]);
+    assert!(matches!(
+        args.into_config_with_env(no_env),
+        Err(ConfigError::EventQueueShards(5))
+    ));
+
+    let args = Args::from_iter(["bpf-recorder", "--aggregator", "localhost:8000:x"]);
+    assert!(matches!(
+        args.into_config_with_env(no_env),
+        Err(ConfigError::AggregatorUrl { .. })
+    ));
+
+    // the command line disables the flag enabled by the file
+    fs::write(path, "dry = true\nattach = true\n").unwrap();
+    let args = Args::from_iter([
+        "bpf-recorder",
+        "--config",
+        path,
+        "--dry",
+        "false",
+        "--attach",
+    ]);
+    let config = args.into_config_with_env(no_env).unwrap();
+    assert!(!config.dry);
+    assert!(config.attach);
+
+    fs::write(path, "server_prot = 80\n").unwrap();
+    let args = Args::from_iter(["bpf-recorder", "--config", path]);
+    assert!(matches!(
+        args.into_config_with_env(no_env),
+        Err(ConfigError::Parse { .. })
+    ));
+}
@@ -48,6 +48,28 @@ pub enum Variant {
         data_ptr: u64,
         _pad: u64,
     },
+    // `iov_ptr` points to the array of `struct iovec`
+    WriteV {
+        fd: u32,
+        iov_ptr: u64,
+        iov_len: u64,
+    },
+    ReadV {
+        fd: u32,
+        iov_ptr: u64,
+        iov_len: u64,
+    },
+    // `msg_ptr` points to the `struct msghdr`
+    SendMsg {
+        fd: u32,
+        msg_ptr: u64,
+        _pad: u64,
+    },
+    RecvMsg {
+        fd: u32,
+        msg_ptr: u64,
+        _pad: u64,
+    },
 
     GetRandom {
         _fd: u32,
@@ -78,6 +100,10 @@ impl Variant {
             Variant::Read { data_ptr, .. } => *data_ptr as *const u8,
             Variant::Send { data_ptr, .. } => *data_ptr as *const u8,
             Variant::Recv { data_ptr, .. } => *data_ptr as *const u8,
+            Variant::WriteV { iov_ptr, .. } => *iov_ptr as *const u8,
+            Variant::ReadV { iov_ptr, .. } => *iov_ptr as *const u8,
+            Variant::SendMsg { msg_ptr, .. } => *msg_ptr as *const u8,
+            Variant::RecvMsg { msg_ptr, .. } => *msg_ptr as *const u8,
             Variant::GetRandom { data_ptr, .. } => *data_ptr as *const u8,
             Variant::GetSockOptL1O4 { val_ptr, .. } => *val_ptr as *const u8,
             Variant::GetSockOptIrrelevant { val_ptr, .. } => *val_ptr as *const u8,
@@ -3,6 +3,24 @@
 #[cfg(feature = "user")]
 pub mod proc;
 
+#[cfg(feature = "user")]
+pub mod config;
+
+#[cfg(feature = "user")]
+pub mod capture;
+
+#[cfg(feature = "user")]
+pub mod processor;
+
+#[cfg(feature = "user")]
+pub mod tcp_info;
+
+#[cfg(feature = "user")]
+pub mod reorder;
+
+#[cfg(feature = "user")]
+pub mod uprobe;
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct StatsBlocked {
@@ -10,6 +28,28 @@ pub struct StatsBlocked {
     pub bytes: u32,
 }
 
+/// Which rule of the firewall dropped the packet, the last byte of the key of `blocked`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FirewallRule {
+    Whitelist = 0,
+    Blacklist = 1,
+    RateLimit = 2,
+    PacketLoss = 3,
+}
+
+/// Token bucket in the form of the generic cell rate algorithm,
+/// the userspace sets the rate and the burst, the kernel updates the rest
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RateLimit {
+    pub bytes_per_second: u32,
+    pub burst: u32,
+    /// Nanoseconds, when the bucket is full again, shared among cpus,
+    /// so the kernel updates it by atomic addition
+    pub tat: u64,
+}
+
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Event {
@@ -20,6 +60,8 @@ pub struct Event {
     pub ts1: u64,
     pub tag: DataTag,
     pub size: i32,
+    /// The cgroup of the process, the kernel sets it when sends the event
+    pub cgroup: u64,
 }
 
 impl Event {
@@ -32,6 +74,7 @@ impl Event {
             ts1,
             tag: DataTag::Debug,
             size: 0,
+            cgroup: 0,
         }
     }
 
@@ -68,6 +111,9 @@ pub enum DataTag {
     Random,
     GetSockOpt,
     SnarkWorker,
+    // plaintext captured by uprobes on the Noise implementation of the Go libp2p helper
+    NoiseEncrypt,
+    NoiseDecrypt,
 }
 
 impl DataTag {
@@ -85,9 +131,48 @@ impl DataTag {
             DataTag::Random,
             DataTag::GetSockOpt,
             DataTag::SnarkWorker,
+            DataTag::NoiseEncrypt,
+            DataTag::NoiseDecrypt,
         ];
         values.into_iter().find(|&v| v as u32 == c)
     }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DataTag::Debug => "debug",
+            DataTag::Close => "close",
+            DataTag::Connect => "connect",
+            DataTag::Bind => "bind",
+            DataTag::Listen => "listen",
+            DataTag::Accept => "accept",
+            DataTag::Write => "write",
+            DataTag::Read => "read",
+            DataTag::Alias => "alias",
+            DataTag::Random => "random",
+            DataTag::GetSockOpt => "get_sock_opt",
+            DataTag::SnarkWorker => "snark_worker",
+            DataTag::NoiseEncrypt => "noise_encrypt",
+            DataTag::NoiseDecrypt => "noise_decrypt",
+        }
+    }
+
+    /// The ring buffer the kernel sends the event to, the events with the payload are spread
+    /// by cpu, the rest go to `event_queue`, so the connect and the close are never reordered
+    pub fn shard(self, cpu: u32, shards: u32) -> u32 {
+        let payload = matches!(
+            self,
+            DataTag::Write
+                | DataTag::Read
+                | DataTag::Random
+                | DataTag::NoiseEncrypt
+                | DataTag::NoiseDecrypt
+        );
+        if payload && shards > 1 {
+            cpu % shards
+        } else {
+            0
+        }
+    }
 }
 
 #[cfg(feature = "user")]
@@ -105,6 +190,7 @@ pub mod sniffer_event {
         pub fd: u32,
         pub ts0: u64,
         pub ts1: u64,
+        pub cgroup: u64,
         pub variant: SnifferEventVariant,
     }
 
@@ -118,6 +204,9 @@ pub mod sniffer_event {
         Disconnected,
         IncomingData(Vec<u8>),
         OutgoingData(Vec<u8>),
+        // the plaintext of the Noise transport message, `fd` is unknown
+        IncomingPlaintext(Vec<u8>),
+        OutgoingPlaintext(Vec<u8>),
         Random(Vec<u8>),
         GetSockOpt(Vec<u8>),
         Error(DataTag, i32),
@@ -148,6 +237,7 @@ pub mod sniffer_event {
                 ts1,
                 tag,
                 size,
+                cgroup,
             } = event;
             let ret = |variant| -> Result<Option<Self>, ErrorSliceTooShort> {
                 Ok(Some(SnifferEvent {
@@ -156,6 +246,7 @@ pub mod sniffer_event {
                     fd,
                     ts0,
                     ts1,
+                    cgroup,
                     variant,
                 }))
             };
@@ -199,14 +290,19 @@ pub mod sniffer_event {
             } else if let DataTag::Close = tag {
                 ret(SnifferEventVariant::Disconnected)
             } else if let DataTag::Alias = tag {
+                // the alias is empty if the process matched by its cgroup
+                let alias = data.strip_suffix(&[0]).unwrap_or(data);
                 ret(SnifferEventVariant::NewApp(
-                    String::from_utf8(data[..(data.len() - 1)].to_vec())
-                        .unwrap_or("invalid_uft8_alias".to_string()),
+                    String::from_utf8(alias.to_vec()).unwrap_or("invalid_uft8_alias".to_string()),
                 ))
             } else if let DataTag::Random = tag {
                 ret(SnifferEventVariant::Random(data.to_vec()))
             } else if let DataTag::GetSockOpt = tag {
                 ret(SnifferEventVariant::GetSockOpt(data.to_vec()))
+            } else if let DataTag::NoiseDecrypt = tag {
+                ret(SnifferEventVariant::IncomingPlaintext(data.to_vec()))
+            } else if let DataTag::NoiseEncrypt = tag {
+                ret(SnifferEventVariant::OutgoingPlaintext(data.to_vec()))
             } else if let DataTag::SnarkWorker = tag {
                 ret(SnifferEventVariant::NewSnarkWorkerApp)
             } else if let DataTag::Debug = tag {
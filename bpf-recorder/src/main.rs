@@ -14,12 +14,33 @@ pub struct App {
     // output channel
     #[ringbuf(size = 0x8000000)]
     pub event_queue: ebpf::RingBufferRef,
+    // additional output channels, the data events are sent to the one chosen by cpu,
+    // so the cpus do not contend for the single `event_queue`
+    #[ringbuf(size = 0x2000000)]
+    pub event_queue_1: ebpf::RingBufferRef,
+    #[ringbuf(size = 0x2000000)]
+    pub event_queue_2: ebpf::RingBufferRef,
+    #[ringbuf(size = 0x2000000)]
+    pub event_queue_3: ebpf::RingBufferRef,
+    // 0 -> number of used output channels, filled from userspace
+    #[hashmap(size = 0x1)]
+    pub event_queue_shards: ebpf::HashMapRef<4, 4>,
     // track relevant pids
     // 0x1000 processes maximum
     #[hashmap(size = 0x1000)]
     pub pid: ebpf::HashMapRef<4, 4>,
     #[hashmap(size = 0x1000)]
     pub pid_snark_worker: ebpf::HashMapRef<4, 4>,
+    // which processes to track, filled from userspace
+    // index -> length prefixed prefix of the first argument
+    #[hashmap(size = 0x8)]
+    pub match_names: ebpf::HashMapRef<4, 0x20>,
+    // 0 -> length prefixed name of the environment variable which holds the alias
+    #[hashmap(size = 0x1)]
+    pub match_env: ebpf::HashMapRef<4, 0x20>,
+    // cgroup id -> flags, every process of the cgroup is tracked
+    #[hashmap(size = 0x100)]
+    pub match_cgroups: ebpf::HashMapRef<8, 4>,
     #[prog("tracepoint/syscalls/sys_enter_execve")]
     pub execve: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_enter_execveat")]
@@ -59,9 +80,18 @@ pub struct App {
     pub whitelist: ebpf::HashMapRef<16, 4>,
     #[hashmap(size = 0x100)]
     pub whitelist_ports: ebpf::HashMapRef<2, 4>,
-    // (src_ip, src_port, dst_ip, dst_port) -> (packets_count, bytes_count)
+    // the packets from these ips are dropped regardless of the whitelist
+    #[hashmap(size = 0x4000)]
+    pub blacklist: ebpf::HashMapRef<16, 4>,
+    // src ip -> token bucket, see `RateLimit`
+    #[hashmap(size = 0x400)]
+    pub rate_limit: ebpf::HashMapRef<16, 16>,
+    // (ip, port) -> percent of packets to drop, zero ip or port matches any
+    #[hashmap(size = 0x400)]
+    pub packet_loss: ebpf::HashMapRef<18, 4>,
+    // (src_ip, src_port, dst_ip, dst_port, rule) -> (packets_count, bytes_count)
     #[hashmap(size = 0x4000)]
-    pub blocked: ebpf::HashMapRef<36, 8>,
+    pub blocked: ebpf::HashMapRef<37, 8>,
     #[prog("tracepoint/syscalls/sys_enter_write")]
     pub enter_write: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_exit_write")]
@@ -78,14 +108,42 @@ pub struct App {
     pub enter_recvfrom: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_exit_recvfrom")]
     pub exit_recvfrom: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_enter_sendmsg")]
+    pub enter_sendmsg: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_exit_sendmsg")]
+    pub exit_sendmsg: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_enter_recvmsg")]
+    pub enter_recvmsg: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_exit_recvmsg")]
+    pub exit_recvmsg: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_enter_writev")]
+    pub enter_writev: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_exit_writev")]
+    pub exit_writev: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_enter_readv")]
+    pub enter_readv: ebpf::ProgRef,
+    #[prog("tracepoint/syscalls/sys_exit_readv")]
+    pub exit_readv: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_enter_getrandom")]
     pub enter_getrandom: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_exit_getrandom")]
     pub exit_getrandom: ebpf::ProgRef,
     #[prog("tracepoint/syscalls/sys_enter_shutdown")]
     pub enter_shutdown: ebpf::ProgRef,
+    // attached from userspace to the Noise session of the Go libp2p helper,
+    // the entry of `encrypt` and every `RET` of `decrypt`, see `uprobe.rs`
+    #[prog("uprobe")]
+    pub noise_encrypt: ebpf::ProgRef,
+    #[prog("uprobe")]
+    pub noise_decrypt_return: ebpf::ProgRef,
     #[prog("xdp")]
     pub disable_connections: ebpf::ProgRef,
+    // tag -> number of events which did not fit in `event_queue`, per cpu
+    #[array_percpu(size = 0x20)]
+    pub drops: ebpf::ArrayPerCpuRef<8>,
+    // socket ids (see `connections`) which lost some data
+    #[hashmap(size = 0x4000)]
+    pub drops_connections: ebpf::HashMapRef<8, 4>,
 }
 
 #[cfg(feature = "kern")]
@@ -95,7 +153,7 @@ mod context;
 mod send;
 
 #[cfg(feature = "kern")]
-use bpf_recorder::{DataTag, Event, StatsBlocked};
+use bpf_recorder::{DataTag, Event, FirewallRule, RateLimit, StatsBlocked};
 
 #[cfg(feature = "kern")]
 #[no_mangle]
@@ -126,6 +184,32 @@ pub unsafe extern "C" fn memmove(dest: *mut u8, src: *mut u8, n: usize) {
     }
 }
 
+#[cfg(feature = "kern")]
+const MATCH_NAMES_MAX: u32 = 0x8;
+
+/// `App::firewall_rule` lets the packet pass
+#[cfg(feature = "kern")]
+const FIREWALL_PASS: u8 = 0xff;
+
+/// The `pattern` is length prefixed, the `s` is long enough
+#[cfg(feature = "kern")]
+#[inline(always)]
+fn prefix_matches(pattern: &[u8; 0x20], s: &[u8]) -> bool {
+    let len = pattern[0] as usize;
+    if len == 0 || len >= pattern.len() {
+        return false;
+    }
+    for i in 0..(pattern.len() - 1) {
+        if i >= len {
+            break;
+        }
+        if s[i] != pattern[i + 1] {
+            return false;
+        }
+    }
+    true
+}
+
 #[cfg(feature = "kern")]
 impl App {
     #[inline(always)]
@@ -146,6 +230,30 @@ impl App {
         Err(0)
     }
 
+    #[inline(never)]
+    fn check_cgroup(&mut self) -> Result<(), i32> {
+        use core::ptr;
+        use ebpf::helpers;
+
+        let cgroup_id = unsafe { helpers::get_current_cgroup_id() };
+        if self.match_cgroups.get(&cgroup_id.to_ne_bytes()).is_none() {
+            return Err(0);
+        }
+
+        let (pid, tid) = {
+            let x = unsafe { helpers::get_current_pid_tgid() };
+            ((x >> 32) as u32, (x & 0xffffffff) as u32)
+        };
+        self.pid
+            .insert(pid.to_ne_bytes(), 0x_ffff_ffff_u32.to_ne_bytes())?;
+
+        // the process did not pass through `execve`, report it with empty alias
+        let ts = unsafe { helpers::ktime_get_boot_ns() };
+        let event = Event::new(pid, tid, ts, ts);
+        let event = event.set_tag_fd(DataTag::Alias, 0).set_ok(0);
+        self.send(event, ptr::null())
+    }
+
     #[inline(always)]
     fn check_pid_snark_worker(&self) -> Result<(), i32> {
         use ebpf::helpers;
@@ -164,36 +272,35 @@ impl App {
         Err(0)
     }
 
-    #[allow(clippy::nonminimal_bool)]
     #[inline(never)]
-    fn check_env_entry(&mut self, entry: *const u8) -> Result<u32, i32> {
+    fn check_env_entry(&mut self, entry: *const u8) -> Result<(u32, u32), i32> {
         use ebpf::helpers;
 
+        let flag = match self.match_env.get(&0_u32.to_ne_bytes()) {
+            Some(&flag) => flag,
+            None => return Err(0),
+        };
+        let len = flag[0] as usize;
+        if len == 0 || len >= flag.len() - 1 {
+            return Err(0);
+        }
+
         let mut str_bytes = self.event_queue.reserve(0x200)?;
         let c = unsafe {
             helpers::probe_read_user_str(str_bytes.as_mut().as_mut_ptr() as _, 0x200, entry as _)
         };
 
-        // Too short or too long
-        if !(9..=0x200).contains(&c) {
+        // Too short or too long, must contain the name, '=' and the terminating zero
+        if c <= (len + 1) as _ || c > 0x200 {
             str_bytes.discard();
             return Err(c as _);
         }
-        // Prefix is 'BPF_ALIAS'
-        let prefix = true
-            && str_bytes.as_ref()[0] == b'B'
-            && str_bytes.as_ref()[1] == b'P'
-            && str_bytes.as_ref()[2] == b'F'
-            && str_bytes.as_ref()[3] == b'_'
-            && str_bytes.as_ref()[4] == b'A'
-            && str_bytes.as_ref()[5] == b'L'
-            && str_bytes.as_ref()[6] == b'I'
-            && str_bytes.as_ref()[7] == b'A'
-            && str_bytes.as_ref()[8] == b'S';
+        // Prefix is the name followed by '='
+        let prefix = prefix_matches(&flag, str_bytes.as_ref()) && str_bytes.as_ref()[len] == b'=';
 
         str_bytes.discard();
         if prefix {
-            Ok((c - 10) as u32)
+            Ok(((c as usize - len - 1) as u32, (len + 1) as u32))
         } else {
             Err(0)
         }
@@ -223,7 +330,7 @@ impl App {
                 break;
             }
 
-            if let Ok(len) = self.check_env_entry(entry) {
+            if let Ok((len, offset)) = self.check_env_entry(entry) {
                 env_str.discard();
                 let (pid, tid) = {
                     let x = unsafe { helpers::get_current_pid_tgid() };
@@ -233,8 +340,8 @@ impl App {
                 let ts = unsafe { helpers::ktime_get_boot_ns() };
                 let event = Event::new(pid, tid, ts, ts);
                 let event = event.set_tag_fd(DataTag::Alias, 0).set_ok(len as u64);
-                let name = unsafe { entry.offset(10) };
-                send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, name)?;
+                let name = unsafe { entry.offset(offset as isize) };
+                self.send(event, name)?;
 
                 return self
                     .pid
@@ -259,44 +366,23 @@ impl App {
         };
 
         // Too short or too long
-        if !(8..=0x200).contains(&c) {
+        if !(2..=0x200).contains(&c) {
             str_bytes.discard();
             return Err(c as _);
         }
-        // Prefix is 'coda-libp2p_helper'
-        let prefix_coda = true
-            && str_bytes.as_ref()[0] == b'c'
-            && str_bytes.as_ref()[1] == b'o'
-            && str_bytes.as_ref()[2] == b'd'
-            && str_bytes.as_ref()[3] == b'a'
-            && str_bytes.as_ref()[4] == b'-'
-            && str_bytes.as_ref()[5] == b'l'
-            && str_bytes.as_ref()[6] == b'i'
-            && str_bytes.as_ref()[7] == b'b'
-            && str_bytes.as_ref()[8] == b'p'
-            && str_bytes.as_ref()[9] == b'2'
-            && str_bytes.as_ref()[10] == b'p'
-            && str_bytes.as_ref()[11] == b'_'
-            && str_bytes.as_ref()[12] == b'h'
-            && str_bytes.as_ref()[13] == b'e'
-            && str_bytes.as_ref()[14] == b'l'
-            && str_bytes.as_ref()[15] == b'p'
-            && str_bytes.as_ref()[16] == b'e'
-            && str_bytes.as_ref()[17] == b'r';
-
-        // Prefix is 'openmina'
-        let prefix_openmina = true
-            && str_bytes.as_ref()[0] == b'o'
-            && str_bytes.as_ref()[1] == b'p'
-            && str_bytes.as_ref()[2] == b'e'
-            && str_bytes.as_ref()[3] == b'n'
-            && str_bytes.as_ref()[4] == b'm'
-            && str_bytes.as_ref()[5] == b'i'
-            && str_bytes.as_ref()[6] == b'n'
-            && str_bytes.as_ref()[7] == b'a';
+        // Prefix is one of configured names
+        let mut prefix = false;
+        for i in 0..MATCH_NAMES_MAX {
+            if let Some(pattern) = self.match_names.get(&i.to_ne_bytes()) {
+                if prefix_matches(pattern, str_bytes.as_ref()) {
+                    prefix = true;
+                    break;
+                }
+            }
+        }
 
         str_bytes.discard();
-        if prefix_coda || prefix_openmina {
+        if prefix {
             Ok(())
         } else {
             Err(0)
@@ -405,7 +491,7 @@ impl App {
                 let ts = unsafe { helpers::ktime_get_boot_ns() };
                 let event = Event::new(pid, tid, ts, ts);
                 let event = event.set_tag_fd(DataTag::SnarkWorker, 0).set_ok(0);
-                send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())?;
+                self.send(event, ptr::null())?;
 
                 self.pid_snark_worker
                     .insert(pid.to_ne_bytes(), 0x_ffff_ffff_u32.to_ne_bytes())?;
@@ -442,6 +528,50 @@ impl App {
         self.check_env_flag(env)
     }
 
+    #[inline(always)]
+    fn send(&mut self, mut event: Event, data: *const u8) -> Result<(), i32> {
+        use ebpf::helpers;
+
+        event.cgroup = unsafe { helpers::get_current_cgroup_id() };
+        let shards = match self.event_queue_shards.get(&0_u32.to_ne_bytes()) {
+            Some(v) => u32::from_ne_bytes(*v),
+            None => 1,
+        };
+        // there are at most 4 ring buffers, so the cpus share them round-robin
+        let cpu = unsafe { helpers::get_smp_processor_id() } as u32;
+        let rb = match event.tag.shard(cpu, shards) {
+            1 => &mut self.event_queue_1,
+            2 => &mut self.event_queue_2,
+            3 => &mut self.event_queue_3,
+            _ => &mut self.event_queue,
+        };
+        match send::dyn_sized::<typenum::B0>(rb, event, data) {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                self.count_drop(event);
+                Ok(())
+            }
+            Err(code) => {
+                self.count_drop(event);
+                Err(code)
+            }
+        }
+    }
+
+    #[inline(never)]
+    fn count_drop(&mut self, event: Event) {
+        if let Some(count) = self.drops.get_mut(event.tag as u32) {
+            *count = (u64::from_ne_bytes(*count) + 1).to_ne_bytes();
+        }
+
+        if event.fd > 2 {
+            let socket_id = ((event.fd as u64) << 32) + (event.pid as u64);
+            self.drops_connections
+                .insert(socket_id.to_ne_bytes(), 0x1_u32.to_ne_bytes())
+                .unwrap_or_default();
+        }
+    }
+
     #[inline(always)]
     fn enter(&mut self, snark_worker: bool, data: context::Variant) -> Result<(), i32> {
         use core::{mem, ptr};
@@ -449,9 +579,10 @@ impl App {
 
         if snark_worker {
             self.check_pid()
+                .or_else(|_| self.check_cgroup())
                 .or_else(|_| self.check_pid_snark_worker())?;
         } else {
-            self.check_pid()?;
+            self.check_pid().or_else(|_| self.check_cgroup())?;
         }
 
         let (_, thread_id) = {
@@ -526,10 +657,20 @@ impl App {
         let event = match data {
             context::Variant::Write { fd, .. } => event.set_tag_fd(DataTag::Write, fd),
             context::Variant::Read { fd, .. } => event.set_tag_fd(DataTag::Read, fd),
+            context::Variant::WriteV { fd, .. } | context::Variant::ReadV { fd, .. } => {
+                if ret < 0 {
+                    return Ok(());
+                }
+                let tag = match data {
+                    context::Variant::WriteV { .. } => DataTag::Write,
+                    _ => DataTag::Read,
+                };
+                return self.send_vectored(event.set_tag_fd(tag, fd), data, ret as _);
+            }
             _ => return Ok(()),
         };
         if ret >= 0 {
-            send::dyn_sized::<typenum::B0>(&mut self.event_queue, event.set_ok(ret as _), ptr)
+            self.send(event.set_ok(ret as _), ptr)
         } else {
             return Ok(());
         }
@@ -698,7 +839,7 @@ impl App {
                         }
                         let close_ev = event.set_tag_fd(DataTag::Close, fd);
                         let event = event.set_err(ret);
-                        send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())?;
+                        self.send(event, ptr::null())?;
                         close_ev
                     } else {
                         event.set_ok(ret as _)
@@ -724,18 +865,124 @@ impl App {
                         }
                         let close_ev = event.set_tag_fd(DataTag::Close, fd);
                         let event = event.set_err(ret);
-                        send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())?;
+                        self.send(event, ptr::null())?;
                         close_ev
                     } else {
                         event.set_ok(ret as _)
                     }
                 }
             }
+            context::Variant::SendMsg { fd, .. } | context::Variant::WriteV { fd, .. } => {
+                let event = event.set_tag_fd(DataTag::Write, fd);
+                return self.on_ret_vectored(ret, data, event);
+            }
+            context::Variant::RecvMsg { fd, .. } | context::Variant::ReadV { fd, .. } => {
+                let event = event.set_tag_fd(DataTag::Read, fd);
+                return self.on_ret_vectored(ret, data, event);
+            }
             context::Variant::GetRandom { data_len, .. } => {
                 event.set_tag_fd(DataTag::Random, 0).set_ok(data_len)
             }
         };
-        send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr)
+        self.send(event, ptr)
+    }
+
+    #[inline(never)]
+    fn on_ret_vectored(
+        &mut self,
+        ret: i64,
+        data: context::Variant,
+        event: Event,
+    ) -> Result<(), i32> {
+        use core::ptr;
+
+        let fd = event.fd;
+        if fd == 0 || fd == 1 || fd == 2 {
+            if ret < 0 {
+                return Ok(());
+            }
+        } else {
+            let socket_id = ((fd as u64) << 32) + (event.pid as u64);
+            if self.connections.get(&socket_id.to_ne_bytes()).is_none() {
+                return Ok(());
+            }
+            if ret < 0 {
+                if self.connections.remove(&socket_id.to_ne_bytes())?.is_none() {
+                    return Ok(());
+                }
+                let close_ev = event.set_tag_fd(DataTag::Close, fd);
+                let event = event.set_err(ret);
+                self.send(event, ptr::null())?;
+                return self.send(close_ev, ptr::null());
+            }
+        }
+
+        self.send_vectored(event, data, ret as _)
+    }
+
+    // emit a separate event for each `struct iovec`, the kernel fills them in order,
+    // so the total length is distributed among them until `ret` bytes are consumed
+    #[inline(never)]
+    fn send_vectored(&mut self, event: Event, data: context::Variant, ret: u64) -> Result<(), i32> {
+        use core::cmp;
+        use ebpf::helpers;
+
+        // IOV_MAX is 1024, but the verifier needs a small bound,
+        // the data beyond it is counted as dropped
+        const MAX_IOV: u64 = 0x40;
+
+        let (iov_ptr, iov_len) = match data {
+            context::Variant::WriteV {
+                iov_ptr, iov_len, ..
+            }
+            | context::Variant::ReadV {
+                iov_ptr, iov_len, ..
+            } => (iov_ptr, iov_len),
+            context::Variant::SendMsg { msg_ptr, .. }
+            | context::Variant::RecvMsg { msg_ptr, .. } => {
+                // struct msghdr { msg_name, msg_namelen, msg_iov, msg_iovlen, ... }
+                let mut msg_iov = [0_u64; 2];
+                let c = unsafe {
+                    let p = msg_iov.as_mut_ptr() as *mut _;
+                    helpers::probe_read_user(p, 16, (msg_ptr + 0x10) as _)
+                };
+                if c != 0 {
+                    return Err(0);
+                }
+                (msg_iov[0], msg_iov[1])
+            }
+            _ => return Ok(()),
+        };
+
+        let mut remaining = ret;
+        let mut i = 0;
+        while i < MAX_IOV && i < iov_len && remaining > 0 {
+            // struct iovec { iov_base, iov_len }
+            let mut iov = [0_u64; 2];
+            let c = unsafe {
+                let p = iov.as_mut_ptr() as *mut _;
+                helpers::probe_read_user(p, 16, (iov_ptr + i * 16) as _)
+            };
+            if c != 0 {
+                return Err(0);
+            }
+            i += 1;
+
+            let len = cmp::min(iov[1], remaining);
+            remaining -= len;
+            if len == 0 {
+                continue;
+            }
+            let event = event.set_ok(len);
+            self.send(event, iov[0] as *const u8)?;
+        }
+
+        if remaining > 0 {
+            // the connection is marked incomplete, so the gap is visible
+            self.count_drop(event.set_ok(remaining));
+        }
+
+        Ok(())
     }
 
     #[inline(always)]
@@ -840,7 +1087,7 @@ impl App {
 
         let event = Event::new(pid, tid, ts, ts);
         let event = event.set_tag_fd(DataTag::Close, fd);
-        send::dyn_sized::<typenum::B0>(&mut self.event_queue, event, ptr::null())
+        self.send(event, ptr::null())
     }
 
     // #[inline(always)]
@@ -921,6 +1168,74 @@ impl App {
         self.exit(ctx)
     }
 
+    #[inline(always)]
+    pub fn enter_sendmsg(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.enter(
+            false,
+            context::Variant::SendMsg {
+                fd: ctx.read_here::<u64>(0x10) as u32,
+                msg_ptr: ctx.read_here::<u64>(0x18),
+                _pad: 0,
+            },
+        )
+    }
+
+    #[inline(always)]
+    pub fn exit_sendmsg(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.exit(ctx)
+    }
+
+    #[inline(always)]
+    pub fn enter_recvmsg(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.enter(
+            false,
+            context::Variant::RecvMsg {
+                fd: ctx.read_here::<u64>(0x10) as u32,
+                msg_ptr: ctx.read_here::<u64>(0x18),
+                _pad: 0,
+            },
+        )
+    }
+
+    #[inline(always)]
+    pub fn exit_recvmsg(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.exit(ctx)
+    }
+
+    #[inline(always)]
+    pub fn enter_writev(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.enter(
+            true,
+            context::Variant::WriteV {
+                fd: ctx.read_here::<u64>(0x10) as u32,
+                iov_ptr: ctx.read_here::<u64>(0x18),
+                iov_len: ctx.read_here::<u64>(0x20),
+            },
+        )
+    }
+
+    #[inline(always)]
+    pub fn exit_writev(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.exit(ctx)
+    }
+
+    #[inline(always)]
+    pub fn enter_readv(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.enter(
+            true,
+            context::Variant::ReadV {
+                fd: ctx.read_here::<u64>(0x10) as u32,
+                iov_ptr: ctx.read_here::<u64>(0x18),
+                iov_len: ctx.read_here::<u64>(0x20),
+            },
+        )
+    }
+
+    #[inline(always)]
+    pub fn exit_readv(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        self.exit(ctx)
+    }
+
     #[inline(always)]
     pub fn enter_getrandom(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
         let len = ctx.read_here::<u64>(0x18);
@@ -944,6 +1259,47 @@ impl App {
         self.enter_close(ctx)
     }
 
+    #[inline(always)]
+    fn send_plaintext(&mut self, tag: DataTag, ptr: u64, len: u64) -> Result<(), i32> {
+        use ebpf::helpers;
+
+        self.check_pid()?;
+
+        let (pid, tid) = {
+            let x = unsafe { helpers::get_current_pid_tgid() };
+            ((x >> 32) as u32, (x & 0xffffffff) as u32)
+        };
+        let ts = unsafe { helpers::ktime_get_boot_ns() };
+        // the socket is unknown here, userspace finds it by the thread
+        let event = Event::new(pid, tid, ts, ts);
+        let event = event.set_tag_fd(tag, 0).set_ok(len);
+        self.send(event, ptr as *const u8)
+    }
+
+    // the context is `struct pt_regs`, Go register ABI, the receiver is in `rax`
+    // `func (s *secureSession) encrypt(out, plaintext []byte) ([]byte, error)`
+
+    #[inline(always)]
+    pub fn noise_encrypt(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        // `plaintext` is in `rsi`, `r8`, `r9`
+        let ptr = ctx.read_here::<u64>(104);
+        let len = ctx.read_here::<u64>(72);
+        self.send_plaintext(DataTag::NoiseEncrypt, ptr, len)
+    }
+
+    // `func (s *secureSession) decrypt(out, ciphertext []byte) ([]byte, error)`
+
+    #[inline(always)]
+    pub fn noise_decrypt_return(&mut self, ctx: ebpf::Context) -> Result<(), i32> {
+        // the result is in `rax`, `rbx`, `rcx`, the error interface is in `rdi`, `rsi`
+        if ctx.read_here::<u64>(112) != 0 {
+            return Ok(());
+        }
+        let ptr = ctx.read_here::<u64>(80);
+        let len = ctx.read_here::<u64>(40);
+        self.send_plaintext(DataTag::NoiseDecrypt, ptr, len)
+    }
+
     #[inline(always)]
     fn disable_connections(&mut self, ctx: ebpf::xdp::Context) -> Result<ebpf::xdp::Action, i32> {
         use network_types::{
@@ -969,11 +1325,6 @@ impl App {
         //     }
         // }
 
-        // whitelist is disabled
-        if self.whitelist.get(&[0; 16]).is_some() {
-            return Ok(Action::Pass);
-        }
-
         let packet_ptr = ctx.data as usize as *const u8;
 
         let ethhdr = packet_ptr as *const EthHdr;
@@ -983,7 +1334,7 @@ impl App {
         }
         let ethhdr = unsafe { &*(packet_ptr as *const EthHdr) };
 
-        match ethhdr.ether_type {
+        let (src_ip, dst_ip, tcphdr) = match ethhdr.ether_type {
             EtherType::Ipv4 => {
                 let ipv4hdr = unsafe { packet_ptr.add(EthHdr::LEN) } as *const Ipv4Hdr;
                 if ipv4hdr as usize + Ipv4Hdr::LEN >= ctx.data_end as usize {
@@ -996,23 +1347,6 @@ impl App {
                     return Ok(Action::Pass);
                 };
 
-                let tcphdr = unsafe { packet_ptr.add(EthHdr::LEN + Ipv4Hdr::LEN) } as *const TcpHdr;
-                if tcphdr as usize + TcpHdr::LEN > ctx.data_end as usize {
-                    return Ok(Action::Aborted);
-                }
-                let packet_size = (ctx.data_end as usize) - tcphdr as usize + TcpHdr::LEN;
-                let tcphdr = unsafe { &*tcphdr };
-
-                let src_port = u16::from_be(tcphdr.source);
-                let dst_port = u16::from_be(tcphdr.dest);
-
-                // do not block such packet
-                if self.whitelist_ports.get(&src_port.to_be_bytes()).is_none()
-                    && self.whitelist_ports.get(&dst_port.to_be_bytes()).is_none()
-                {
-                    return Ok(Action::Pass);
-                }
-
                 let src_ip = {
                     let mut b = [0; 16];
                     b[10] = 0xff;
@@ -1020,10 +1354,6 @@ impl App {
                     b[12..].clone_from_slice(&u32::from_be(ipv4hdr.src_addr).to_be_bytes());
                     b
                 };
-                if self.whitelist.get(&src_ip).is_some() {
-                    return Ok(Action::Pass);
-                }
-
                 let dst_ip = {
                     let mut b = [0; 16];
                     b[10] = 0xff;
@@ -1031,27 +1361,9 @@ impl App {
                     b[12..].clone_from_slice(&u32::from_be(ipv4hdr.dst_addr).to_be_bytes());
                     b
                 };
+                let tcphdr = unsafe { packet_ptr.add(EthHdr::LEN + Ipv4Hdr::LEN) } as *const TcpHdr;
 
-                let key = {
-                    let mut b = [0; 36];
-                    b[0..16].clone_from_slice(&src_ip);
-                    b[16..18].clone_from_slice(&src_port.to_be_bytes());
-                    b[18..34].clone_from_slice(&dst_ip);
-                    b[34..36].clone_from_slice(&dst_port.to_be_bytes());
-                    b
-                };
-                if let Some(value) = self.blocked.get_mut_unsafe::<StatsBlocked>(&key) {
-                    value.packets += 1;
-                    value.bytes += packet_size as u32;
-                } else {
-                    let value = StatsBlocked {
-                        packets: 1,
-                        bytes: packet_size as u32,
-                    };
-                    self.blocked.insert_unsafe(key, value)?;
-                }
-
-                Ok(Action::Drop)
+                (src_ip, dst_ip, tcphdr)
             }
             EtherType::Ipv6 => {
                 let ipv6hdr = unsafe { packet_ptr.add(EthHdr::LEN) } as *const Ipv6Hdr;
@@ -1065,90 +1377,156 @@ impl App {
                     return Ok(Action::Pass);
                 };
 
+                let src_ip = unsafe { ipv6hdr.src_addr.in6_u.u6_addr8 };
+                let dst_ip = unsafe { ipv6hdr.dst_addr.in6_u.u6_addr8 };
                 let tcphdr = unsafe { packet_ptr.add(EthHdr::LEN + Ipv6Hdr::LEN) } as *const TcpHdr;
-                if tcphdr as usize + TcpHdr::LEN > ctx.data_end as usize {
-                    return Ok(Action::Aborted);
-                }
-                let packet_size = (ctx.data_end as usize) - tcphdr as usize + TcpHdr::LEN;
-                let tcphdr = unsafe { &*tcphdr };
-
-                let src_port = u16::from_be(tcphdr.source);
-                let dst_port = u16::from_be(tcphdr.dest);
-                // do not block such packet
-                if self.whitelist_ports.get(&src_port.to_be_bytes()).is_none()
-                    && self.whitelist_ports.get(&dst_port.to_be_bytes()).is_none()
-                {
-                    return Ok(Action::Pass);
-                }
 
-                let src_ip = unsafe { ipv6hdr.src_addr.in6_u.u6_addr8 };
-                if self.whitelist.get(&src_ip).is_some() {
-                    return Ok(Action::Pass);
-                }
+                (src_ip, dst_ip, tcphdr)
+            }
+            _ => return Ok(Action::Pass),
+        };
 
-                let dst_ip = unsafe { ipv6hdr.dst_addr.in6_u.u6_addr8 };
+        if tcphdr as usize + TcpHdr::LEN > ctx.data_end as usize {
+            return Ok(Action::Aborted);
+        }
+        let packet_size = (ctx.data_end as usize) - tcphdr as usize + TcpHdr::LEN;
+        let tcphdr = unsafe { &*tcphdr };
+
+        let src_port = u16::from_be(tcphdr.source);
+        let dst_port = u16::from_be(tcphdr.dest);
+
+        let key = {
+            let mut b = [0; 37];
+            b[0..16].clone_from_slice(&src_ip);
+            b[16..18].clone_from_slice(&src_port.to_be_bytes());
+            b[18..34].clone_from_slice(&dst_ip);
+            b[34..36].clone_from_slice(&dst_port.to_be_bytes());
+            b
+        };
 
-                let key = {
-                    let mut b = [0; 36];
-                    b[0..16].clone_from_slice(&src_ip);
-                    b[16..18].clone_from_slice(&src_port.to_be_bytes());
-                    b[18..34].clone_from_slice(&dst_ip);
-                    b[34..36].clone_from_slice(&dst_port.to_be_bytes());
-                    b
-                };
-                if let Some(value) = self.blocked.get_mut_unsafe::<StatsBlocked>(&key) {
-                    value.packets += 1;
-                    value.bytes += packet_size as u32;
-                } else {
-                    let value = StatsBlocked {
-                        packets: 1,
-                        bytes: packet_size as u32,
-                    };
-                    self.blocked.insert_unsafe(key, value)?;
-                }
+        let rule = self.firewall_rule(&key, packet_size as u32);
+        if rule == FIREWALL_PASS {
+            return Ok(Action::Pass);
+        }
+
+        let mut key = key;
+        key[36] = rule;
+        if let Some(value) = self.blocked.get_mut_unsafe::<StatsBlocked>(&key) {
+            value.packets += 1;
+            value.bytes += packet_size as u32;
+        } else {
+            let value = StatsBlocked {
+                packets: 1,
+                bytes: packet_size as u32,
+            };
+            self.blocked.insert_unsafe(key, value)?;
+        }
+
+        Ok(Action::Drop)
+    }
+
+    /// Which rule drops the packet, `key` is the key of `blocked` without the rule
+    #[inline(always)]
+    fn firewall_rule(&mut self, key: &[u8; 37], packet_size: u32) -> u8 {
+        use core::{
+            ptr,
+            sync::atomic::{AtomicU64, Ordering},
+        };
+        use ebpf::helpers;
 
-                Ok(Action::Drop)
+        let mut src_ip = [0; 16];
+        src_ip.clone_from_slice(&key[0..16]);
+        let mut src_port = [0; 2];
+        src_port.clone_from_slice(&key[16..18]);
+        let mut dst_port = [0; 2];
+        dst_port.clone_from_slice(&key[34..36]);
+
+        if self.blacklist.get(&src_ip).is_some() {
+            return FirewallRule::Blacklist as u8;
+        }
+
+        // the whitelist is enabled and the packet is subject to it
+        if self.whitelist.get(&[0; 16]).is_none()
+            && (self.whitelist_ports.get(&src_port).is_some()
+                || self.whitelist_ports.get(&dst_port).is_some())
+            && self.whitelist.get(&src_ip).is_none()
+        {
+            return FirewallRule::Whitelist as u8;
+        }
+
+        // the most specific entry wins, zero ip or port matches any
+        let loss_key = |ip: &[u8; 16], port: &[u8; 2]| {
+            let mut b = [0; 18];
+            b[0..16].clone_from_slice(ip);
+            b[16..].clone_from_slice(port);
+            b
+        };
+        let percent = [
+            loss_key(&src_ip, &src_port),
+            loss_key(&src_ip, &dst_port),
+            loss_key(&src_ip, &[0; 2]),
+            loss_key(&[0; 16], &src_port),
+            loss_key(&[0; 16], &dst_port),
+        ]
+        .iter()
+        .find_map(|key| self.packet_loss.get(key).map(|v| u32::from_ne_bytes(*v)));
+        if let Some(percent) = percent {
+            if unsafe { helpers::get_prandom_u32() } % 100 < percent {
+                return FirewallRule::PacketLoss as u8;
+            }
+        }
+
+        if let Some(limit) = self.rate_limit.get_mut_unsafe::<RateLimit>(&src_ip) {
+            const NS: u64 = 1_000_000_000;
+
+            let now = unsafe { helpers::ktime_get_ns() };
+            let rate = limit.bytes_per_second as u64;
+            // how long the bucket refills the packet and the whole burst
+            let cost = packet_size as u64 * NS / rate;
+            let tolerance = limit.burst as u64 * NS / rate;
+            let tat = unsafe { &*(ptr::addr_of_mut!(limit.tat) as *const AtomicU64) };
+            let current = tat.load(Ordering::Relaxed);
+            if current.max(now) + cost > now + tolerance {
+                return FirewallRule::RateLimit as u8;
+            }
+            if current < now {
+                // the bucket is full, the cpus racing here may pass one packet each uncharged
+                tat.store(now + cost, Ordering::Relaxed);
+            } else {
+                // the result is unused, so it is the `xadd` instruction, no update is lost
+                tat.fetch_add(cost, Ordering::Relaxed);
             }
-            _ => Ok(Action::Pass),
         }
+
+        FIREWALL_PASS
     }
 }
 
 #[cfg(feature = "user")]
 fn main() {
     use std::{
-        collections::{BTreeMap, BTreeSet},
         sync::{
             atomic::{AtomicBool, Ordering},
             Arc, mpsc,
         },
-        time::{SystemTime, Duration},
+        time::Duration,
         env, thread,
-        path::PathBuf,
     };
 
+    use structopt::StructOpt;
+
     use bpf_recorder::{
-        sniffer_event::{SnifferEventVariant, SnifferEvent},
-        proc,
+        sniffer_event::SnifferEvent,
+        config::Args,
+        capture::{CaptureReader, CaptureWriter},
+        processor::SnifferEventProcessor,
+        reorder::Reorder,
+        proc, DataTag,
     };
-    use simulator::registry::messages::{DebuggerReport, ConnectionMetadata};
     use bpf_ring_buffer::RingBuffer;
-    use mina_recorder::{
-        EventMetadata, ConnectionInfo, server, P2pRecorder, libp2p_helper::CapnpReader,
-        SnarkWorkerState, application,
-    };
+    use mina_recorder::{server, application};
     use ebpf::{kind::AppItem, Skeleton};
 
-    fn watch_pid(pid: u32, terminating: Arc<AtomicBool>) {
-        thread::spawn(move || loop {
-            thread::sleep(Duration::from_secs(5));
-            if !proc::cmd_prefix_matches(pid, "coda-libp2p_helper").unwrap_or_default() {
-                terminating.store(true, Ordering::SeqCst);
-                break;
-            }
-        });
-    }
-
     // let env = env_logger::Env::default().default_filter_or("warn");
     // env_logger::init_from_env(env);
     // if let Err(err) = sudo::escalate_if_needed() {
@@ -1156,16 +1534,7 @@ fn main() {
     //     return;
     // }
 
-    let port = env::var("SERVER_PORT")
-        .unwrap_or_else(|_| 8000.to_string())
-        .parse()
-        .unwrap_or(8000);
-    let db_path = env::var("DB_PATH").unwrap_or_else(|_| "target/db".to_string());
-    let db_path = PathBuf::from(db_path);
-    let dry = env::var("DRY").is_ok();
-
-    let key_path = env::var("HTTPS_KEY_PATH").ok();
-    let cert_path = env::var("HTTPS_CERT_PATH").ok();
+    let args = Args::from_args();
 
     // TODO: fix logging in file
     // let log = File::create(db_path.join("log")).expect("cannot create log file");
@@ -1174,487 +1543,296 @@ fn main() {
     // builder.try_init().expect("cannot setup logging");
     env_logger::init();
 
-    let terminating = Arc::new(AtomicBool::new(dry));
-
-    let mut interface = env::var("FIREWALL_INTERFACE").unwrap_or("eth0".to_string());
-
-    static CODE: &[u8] = include_bytes!(concat!("../", env!("BPF_CODE_RECORDER")));
-
-    let mut skeleton = Skeleton::<App>::open("bpf-recorder\0", CODE)
-        .unwrap_or_else(|code| panic!("failed to open bpf: {}", code));
-    skeleton
-        .load()
-        .unwrap_or_else(|code| panic!("failed to load bpf: {}", code));
-
-    skeleton
-        .app
-        .whitelist
-        .insert([0; 16], [0, 0, 0, 1])
-        .unwrap();
-
-    interface.push('\0');
-    let if_index = unsafe { libc::if_nametoindex(interface.as_ptr() as _) };
-
-    const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
-    skeleton
-        .attach_xdp("disable_connections", if_index as i32, XDP_FLAGS_SKB_MODE)
-        .unwrap();
-
-    let (skeleton, mut app) = skeleton
-        .attach()
-        .unwrap_or_else(|code| panic!("failed to attach bpf: {}", code));
-    log::info!("attached bpf module");
-
-    let fd = match app.event_queue.kind_mut() {
-        ebpf::kind::AppItemKindMut::Map(map) => map.fd(),
-        _ => unreachable!(),
-    };
-
-    let mut info = libbpf_sys::bpf_map_info::default();
-    let mut len = std::mem::size_of::<libbpf_sys::bpf_map_info>() as u32;
-    unsafe {
-        libbpf_sys::bpf_obj_get_info_by_fd(
-            fd,
-            &mut info as *mut libbpf_sys::bpf_map_info as *mut _,
-            &mut len as _,
-        )
-    };
-    let mut rb = match RingBuffer::new(fd, info.max_entries as usize) {
+    let config = match args.into_config() {
         Ok(v) => v,
         Err(err) => {
-            log::error!("failed to create userspace part of the ring buffer: {err}");
+            log::error!("fatal: {err}");
             std::process::exit(1);
         }
     };
+    match toml::to_string(&config) {
+        Ok(s) => log::info!("config:\n{s}"),
+        Err(_) => log::info!("config: {config:?}"),
+    }
 
-    let (app_client, app_server) = application::new(
-        app.whitelist.clone(),
-        app.whitelist_ports.clone(),
-        app.blocked.clone(),
-    );
+    let terminating = Arc::new(AtomicBool::new(config.dry));
 
     let (main_tx, main_rx) = mpsc::channel();
-    let main_thread = thread::spawn({
-        let terminating = terminating.clone();
-        move || {
-            while let Ok(event) = rb.read_blocking::<SnifferEvent>(&terminating) {
-                main_tx.send(event).unwrap_or_default();
+    let replay_reader = match &config.replay_capture {
+        Some(path) => match CaptureReader::open(path) {
+            Ok(v) => Some(v),
+            Err(err) => {
+                log::error!("cannot open capture file {}: {err}", path.display());
+                std::process::exit(1);
             }
-        }
-    });
-
-    let consumer_thread = thread::spawn(move || {
-        let (db, callback, server_thread) =
-            server::spawn(port, db_path, Some(app_client.clone()), key_path, cert_path);
-        {
+        },
+        None => None,
+    };
+    // the timestamps in the capture refer to the boot time of the recording machine
+    let replay_origin = replay_reader.as_ref().and_then(CaptureReader::boot_time);
+    let (bpf, app_client, app_server, main_thread, uprobe_progs) = if let Some(reader) =
+        replay_reader
+    {
+        log::info!("replay capture, bpf is not loaded");
+        let main_thread = thread::spawn({
             let terminating = terminating.clone();
-            let mut callback = Some(callback);
-            let user_handler = move || {
-                log::info!("ctrlc");
-                if let Some(cb) = callback.take() {
-                    cb();
+            move || {
+                for event in reader {
+                    if terminating.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match event {
+                        Ok(event) => main_tx.send((Some(event), 0)).unwrap_or_default(),
+                        Err(err) => {
+                            log::error!("cannot read capture file: {err}");
+                            break;
+                        }
+                    }
                 }
-                terminating.store(true, Ordering::SeqCst);
-            };
-            if let Err(err) = ctrlc::set_handler(user_handler) {
-                log::error!("failed to set ctrlc handler {err}");
-                return;
             }
-        }
-        let db_capnp = db.core();
-
-        let test = env::var("TEST").is_ok();
-
-        let mut origin = proc::S::read().ok().and_then(|s| s.b_time);
-        if let Some(boot_time) = &origin {
-            log::info!("boot time: {boot_time:?}");
-        }
-
-        let mut p2p_cns = BTreeMap::new();
-        let counter = db.messages.clone();
-        let mut pending_out_cns = BTreeMap::new();
-        let mut recorder = P2pRecorder::new(db, test);
-        let mut watching = BTreeMap::new();
-        let mut capnp_readers = BTreeMap::<_, CapnpReader>::new();
-        let mut capnp_blacklist = BTreeSet::new();
-        let mut max_buffered = 0;
-        let mut max_unordered_ns = BTreeMap::new();
-        let mut last_ts = BTreeMap::new();
-        let mut subscriptions = BTreeMap::new();
-        let mut chain_id = BTreeMap::new();
-        let mut max_lag = Duration::ZERO;
-
-        let mut snark_workers = BTreeMap::new();
-
-        while let Ok((event, buffered)) = main_rx.recv() {
-            let Some(event) = event else {
-                continue;
+        });
+        (None, None, None, main_thread, None)
+    } else {
+        let mut interface = config.firewall_interface.clone();
+
+        static CODE: &[u8] = include_bytes!(concat!("../", env!("BPF_CODE_RECORDER")));
+
+        let mut skeleton = Skeleton::<App>::open("bpf-recorder\0", CODE)
+            .unwrap_or_else(|code| panic!("failed to open bpf: {}", code));
+        skeleton
+            .load()
+            .unwrap_or_else(|code| panic!("failed to load bpf: {}", code));
+
+        skeleton
+            .app
+            .whitelist
+            .insert([0; 16], [0, 0, 0, 1])
+            .unwrap();
+
+        interface.push('\0');
+        let if_index = unsafe { libc::if_nametoindex(interface.as_ptr() as _) };
+
+        const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+        skeleton
+            .attach_xdp("disable_connections", if_index as i32, XDP_FLAGS_SKB_MODE)
+            .unwrap();
+
+        let (skeleton, mut app) = skeleton
+            .attach()
+            .unwrap_or_else(|code| panic!("failed to attach bpf: {}", code));
+        log::info!("attached bpf module");
+
+        let shards = config.event_queue_shards as usize;
+        app.event_queue_shards
+            .insert(0_u32.to_ne_bytes(), (shards as u32).to_ne_bytes())
+            .unwrap();
+        let rbs = [
+            &mut app.event_queue,
+            &mut app.event_queue_1,
+            &mut app.event_queue_2,
+            &mut app.event_queue_3,
+        ]
+        .into_iter()
+        .take(shards)
+        .map(|event_queue| {
+            let fd = match event_queue.kind_mut() {
+                ebpf::kind::AppItemKindMut::Map(map) => map.fd(),
+                _ => unreachable!(),
             };
 
-            if buffered > max_buffered {
-                max_buffered = buffered;
-                log::info!("buffered data update maximum: {buffered}");
-            }
-
-            let last = last_ts.get(&event.tid).cloned().unwrap_or_default();
-            if event.ts1 < last {
-                let unordered = last - event.ts1;
-                log::warn!(
-                    "unordered {unordered}, {} < {last}, message id {}",
-                    event.ts1,
-                    counter.load(Ordering::Relaxed)
-                );
-                let max_unordered_ns = max_unordered_ns.entry(event.tid).or_default();
-                if unordered > *max_unordered_ns {
-                    *max_unordered_ns = unordered;
-                }
-            }
-            last_ts.insert(event.tid, event.ts1);
-            let time = match &origin {
-                None => {
-                    let now = SystemTime::now();
-                    origin = Some(now - Duration::from_nanos(event.ts1));
-                    now
-                }
-                Some(origin) => *origin + Duration::from_nanos(event.ts1),
+            let mut info = libbpf_sys::bpf_map_info::default();
+            let mut len = std::mem::size_of::<libbpf_sys::bpf_map_info>() as u32;
+            unsafe {
+                libbpf_sys::bpf_obj_get_info_by_fd(
+                    fd,
+                    &mut info as *mut libbpf_sys::bpf_map_info as *mut _,
+                    &mut len as _,
+                )
             };
-            let better_time = {
-                let instant_there = Duration::from_nanos(event.ts1);
-                let mut tp = libc::timespec {
-                    tv_sec: 0,
-                    tv_nsec: 0,
-                };
-                unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut tp) };
-                let instant_here = Duration::new(tp.tv_sec as _, tp.tv_nsec as _);
-                let delta = instant_here.checked_sub(instant_there).unwrap_or_default();
-                if delta >= max_lag + Duration::from_secs(60) {
-                    max_lag = delta;
-                    log::warn!("lagging: {delta:?}");
+            match RingBuffer::new(fd, info.max_entries as usize) {
+                Ok(v) => v,
+                Err(err) => {
+                    log::error!("failed to create userspace part of the ring buffer: {err}");
+                    std::process::exit(1);
                 }
-                SystemTime::now() - delta
+            }
+        })
+        .collect::<Vec<_>>();
+
+        // the uprobes are attached when the helper is known, see `SnifferEventProcessor`
+        let uprobe_progs = if config.plaintext_uprobes {
+            let prog_fd = |item: &mut ebpf::ProgRef| match item.kind_mut() {
+                ebpf::kind::AppItemKindMut::Prog(prog) => prog.fd(),
+                _ => unreachable!(),
             };
-            let duration = Duration::from_nanos(event.ts1 - event.ts0);
-            match event.variant {
-                SnifferEventVariant::NewSnarkWorkerApp => {
-                    snark_workers.insert(event.pid, SnarkWorkerState::default());
-                }
-                SnifferEventVariant::NewApp(alias) => {
-                    log::info!("exec {alias} pid: {}", event.pid);
-                    recorder.on_alias(event.pid, alias);
-                    if !watching.contains_key(&event.pid) {
-                        let version = env!("GIT_HASH");
-                        watching.insert(
-                            event.pid,
-                            DebuggerReport {
-                                version: version.to_owned(),
-                                ipc: Default::default(),
-                                network: vec![],
-                            },
-                        );
-                        if env::var("TERMINATE").is_ok() {
-                            watch_pid(event.pid, terminating.clone());
+            Some((
+                prog_fd(&mut app.noise_encrypt),
+                prog_fd(&mut app.noise_decrypt_return),
+            ))
+        } else {
+            None
+        };
+
+        let mut process_matching = config.process_matching.clone();
+        if config.attach {
+            // the processes started before the debugger did not pass through `execve`
+            match proc::find_processes(&process_matching.names) {
+                Ok(pids) => {
+                    log::info!("attach to running processes {pids:?}");
+                    for pid in pids {
+                        if !process_matching.pids.contains(&pid) {
+                            process_matching.pids.push(pid);
                         }
                     }
                 }
-                SnifferEventVariant::Bind(addr) => {
-                    recorder.set_port(event.pid, addr.port());
-                }
-                SnifferEventVariant::OutgoingConnection(addr) => {
-                    let metadata = EventMetadata {
-                        id: ConnectionInfo {
-                            addr,
-                            pid: event.pid,
-                            fd: event.fd,
-                        },
-                        time,
-                        better_time,
-                        duration,
-                    };
+                Err(err) => log::error!("cannot find running processes: {err}"),
+            }
+        }
 
-                    log::info!("new unconfirmed {metadata}");
-                    pending_out_cns.insert((event.pid, event.fd), addr);
-                }
-                SnifferEventVariant::GetSockOpt(value) => {
-                    if value.len() != 4 {
-                        continue;
-                    }
-                    let Some(addr) = pending_out_cns.remove(&(event.pid, event.fd)) else {
-                        continue;
-                    };
-                    let metadata = EventMetadata {
-                        id: ConnectionInfo {
-                            addr,
-                            pid: event.pid,
-                            fd: event.fd,
-                        },
-                        time,
-                        better_time,
-                        duration,
-                    };
-                    let value = u32::from_ne_bytes(
-                        value
-                            .as_slice()
-                            .try_into()
-                            .expect("must be checked above `value.len() != 4`"),
-                    );
-                    log::info!("getsockopt {value}, {metadata}");
-                    if value != 0 {
-                        continue;
+        let (app_client, app_server) = application::new(
+            |tag| DataTag::from_u32(tag).map(DataTag::name),
+            app.whitelist.clone(),
+            app.whitelist_ports.clone(),
+            app.blacklist.clone(),
+            app.rate_limit.clone(),
+            app.packet_loss.clone(),
+            app.blocked.clone(),
+            app.drops.clone(),
+            app.drops_connections.clone(),
+            app.pid.clone(),
+            app.match_names.clone(),
+            app.match_env.clone(),
+            app.match_cgroups.clone(),
+            process_matching,
+            app.connections.clone(),
+        );
+
+        // every ring buffer is read in its own thread, the main thread merges them
+        let (shard_tx, shard_rx) = mpsc::channel();
+        for (shard, mut rb) in rbs.into_iter().enumerate() {
+            let terminating = terminating.clone();
+            let shard_tx = shard_tx.clone();
+            thread::spawn(move || {
+                while let Ok((event, buffered)) = rb.read_blocking::<SnifferEvent>(&terminating) {
+                    if let Some(event) = event {
+                        shard_tx.send((shard, event, buffered)).unwrap_or_default();
                     }
-                    if let Some(report) = watching.get_mut(&event.pid) {
-                        let counter = report
-                            .network
-                            .iter()
-                            .filter(|cn| cn.ip == addr.ip())
-                            .count();
-                        report.network.push(ConnectionMetadata {
-                            ip: addr.ip(),
-                            counter,
-                            incoming: false,
-                            fd: event.fd as i32,
-                            checksum: Default::default(),
-                            timestamp: better_time,
-                        });
+                }
+            });
+        }
+        drop(shard_tx);
+
+        let main_thread = thread::spawn({
+            let record_path = config.record_capture.clone();
+            let window = Duration::from_millis(config.reorder_window_ms);
+            let app_client = app_client.clone();
+            move || {
+                let mut capture = record_path.and_then(|path| {
+                    let boot_time = proc::S::read().ok().and_then(|s| s.b_time);
+                    match CaptureWriter::open(&path, boot_time) {
+                        Ok(v) => {
+                            log::info!("record capture into {}", path.display());
+                            Some(v)
+                        }
+                        Err(err) => {
+                            log::error!("cannot open capture file {}: {err}", path.display());
+                            None
+                        }
                     }
-
-                    if let Some(old_addr) = p2p_cns.insert((event.pid, event.fd), addr) {
-                        log::warn!("new outgoing connection on already allocated fd");
-                        let mut metadata = metadata.clone();
-                        metadata.id.addr = old_addr;
-                        recorder.on_disconnect(metadata, buffered);
+                });
+                let mut send = |(event, buffered): (SnifferEvent, usize)| {
+                    if let Some(capture) = &mut capture {
+                        if let Err(err) = capture.write(&event) {
+                            log::error!("cannot write capture file: {err}");
+                        }
                     }
-                    log::info!("new outgoing connection {}", metadata);
-                    recorder.on_connect::<true>(
-                        false,
-                        metadata,
-                        buffered,
-                        chain_id.get(&event.pid).cloned().unwrap_or_default(),
-                    );
-                }
-                SnifferEventVariant::IncomingConnection(addr) => {
-                    if let Some(report) = watching.get_mut(&event.pid) {
-                        let counter = report
-                            .network
-                            .iter()
-                            .filter(|cn| cn.ip == addr.ip())
-                            .count();
-                        report.network.push(ConnectionMetadata {
-                            ip: addr.ip(),
-                            counter,
-                            incoming: true,
-                            fd: event.fd as i32,
-                            checksum: Default::default(),
-                            timestamp: better_time,
-                        });
+                    main_tx.send((Some(event), buffered)).unwrap_or_default();
+                };
+                let mut reorder = Reorder::new(shards, window);
+                loop {
+                    match shard_rx.recv_timeout(window.max(Duration::from_millis(1))) {
+                        Ok((shard, event, buffered)) => {
+                            reorder.push(shard, event.ts1, (event, buffered));
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => (),
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
                     }
-
-                    let metadata = EventMetadata {
-                        id: ConnectionInfo {
-                            addr,
-                            pid: event.pid,
-                            fd: event.fd,
-                        },
-                        time,
-                        better_time,
-                        duration,
+                    // the kernel timestamps are `ktime_get_boot_ns`
+                    let mut tp = libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: 0,
                     };
-                    if let Some(old_addr) = p2p_cns.insert((event.pid, event.fd), addr) {
-                        log::warn!("new incoming connection on already allocated fd");
-                        let mut metadata = metadata.clone();
-                        metadata.id.addr = old_addr;
-                        recorder.on_disconnect(metadata, buffered);
-                    }
-                    log::info!("new incoming connection {}", metadata);
-                    recorder.on_connect::<true>(
-                        true,
-                        metadata,
-                        buffered,
-                        chain_id.get(&event.pid).cloned().unwrap_or_default(),
-                    );
-                }
-                SnifferEventVariant::Disconnected => {
-                    let key = (event.pid, event.fd);
-                    if let Some(addr) = p2p_cns.remove(&key) {
-                        let metadata = EventMetadata {
-                            id: ConnectionInfo {
-                                addr,
-                                pid: event.pid,
-                                fd: event.fd,
-                            },
-                            time,
-                            better_time,
-                            duration,
-                        };
-                        log::info!("disconnected {}", metadata);
-                        recorder.on_disconnect(metadata, buffered);
-                    } else {
-                        // `close` means close socket, not necessarily it was connected
-                        // so it is ok
-                        log::debug!(
-                            "{} cannot process disconnect {}, not connected",
-                            event.pid,
-                            event.fd
-                        );
+                    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut tp) };
+                    let now = Duration::new(tp.tv_sec as _, tp.tv_nsec as _).as_nanos() as u64;
+                    while let Some(item) = reorder.pop(now) {
+                        send(item);
                     }
+                    app_client.set_reorder_stats(reorder.stats());
                 }
-                SnifferEventVariant::Error(_, -104) => {}
-                SnifferEventVariant::Error(tag, code) => {
-                    let key = (event.pid, event.fd);
-                    if let Some(addr) = p2p_cns.get(&key) {
-                        let metadata = EventMetadata {
-                            id: ConnectionInfo {
-                                addr: *addr,
-                                pid: event.pid,
-                                fd: event.fd,
-                            },
-                            time,
-                            better_time,
-                            duration,
-                        };
-
-                        log::error!("{metadata},  tag: {tag:?}, code: {code}");
-                    }
+                reorder.drain().for_each(&mut send);
+                log::info!("reorder: {:?}", reorder.stats());
+                if let Some(capture) = &mut capture {
+                    capture.flush().unwrap_or_default();
                 }
-                SnifferEventVariant::IncomingData(data) => {
-                    if let Some(snark_worker_state) = snark_workers.get_mut(&event.pid) {
-                        snark_worker_state.handle_data(true, event.fd, data);
-                        continue;
-                    }
-                    if event.fd == 0 || event.fd == 1 {
-                        watching
-                            .get_mut(&event.pid)
-                            .map(|report| report.ipc.0 += &data);
+            }
+        });
 
-                        let key = (event.pid, true);
-                        if capnp_blacklist.contains(&key) {
-                            continue;
-                        }
-                        let reader = capnp_readers.entry(key).or_default();
-                        reader.extend_from_slice(&data);
-                        let local_node_address = recorder.cx.pid_to_addr(event.pid);
-                        if !reader.process(
-                            event.pid,
-                            true,
-                            local_node_address,
-                            time,
-                            better_time,
-                            &db_capnp,
-                            &mut subscriptions,
-                            chain_id.entry(event.pid).or_default(),
-                        ) {
-                            capnp_readers.remove(&key);
-                            capnp_blacklist.insert(key);
-                        }
-                        continue;
-                    }
-                    if event.fd == 2 {
-                        // TODO:
-                        continue;
-                    }
-                    let key = (event.pid, event.fd);
-                    if let Some(addr) = p2p_cns.get(&key) {
-                        watching
-                            .get_mut(&event.pid)
-                            .and_then(|report| {
-                                report
-                                    .network
-                                    .iter_mut()
-                                    .rev()
-                                    .find(|cn| addr.ip() == cn.ip && event.fd == cn.fd as u32)
-                            })
-                            .map(|connection| connection.checksum.0 += &data);
-
-                        let metadata = EventMetadata {
-                            id: ConnectionInfo {
-                                addr: *addr,
-                                pid: event.pid,
-                                fd: event.fd,
-                            },
-                            time,
-                            better_time,
-                            duration,
-                        };
-                        recorder.on_data(true, metadata, buffered, data);
-                    } else {
-                        log::warn!(
-                            "{} cannot handle data on {}, not connected, {}",
-                            event.pid,
-                            event.fd,
-                            hex::encode(data),
-                        );
-                    }
+        (
+            Some((skeleton, app)),
+            Some(app_client),
+            Some(app_server),
+            main_thread,
+            uprobe_progs,
+        )
+    };
+
+    let consumer_thread = thread::spawn(move || {
+        let config_json = serde_json::to_value(&config).unwrap_or_default();
+        let (db, callback, server_thread) = server::spawn(
+            config.server_port,
+            &config.db_path,
+            app_client.clone(),
+            config.https_key_path.as_ref(),
+            config.https_cert_path.as_ref(),
+            config_json,
+        );
+        {
+            let terminating = terminating.clone();
+            let mut callback = Some(callback);
+            let user_handler = move || {
+                log::info!("ctrlc");
+                if let Some(cb) = callback.take() {
+                    cb();
                 }
-                SnifferEventVariant::OutgoingData(data) => {
-                    if let Some(snark_worker_state) = snark_workers.get_mut(&event.pid) {
-                        snark_worker_state.handle_data(false, event.fd, data);
-                        continue;
-                    }
-                    if event.fd == 0 || event.fd == 1 {
-                        watching
-                            .get_mut(&event.pid)
-                            .map(|report| report.ipc.1 += &data);
+                terminating.store(true, Ordering::SeqCst);
+            };
+            if let Err(err) = ctrlc::set_handler(user_handler) {
+                log::error!("failed to set ctrlc handler {err}");
+                return;
+            }
+        }
 
-                        let key = (event.pid, false);
-                        if capnp_blacklist.contains(&key) {
-                            continue;
-                        }
-                        let reader = capnp_readers.entry(key).or_default();
-                        reader.extend_from_slice(&data);
-                        let local_node_address = recorder.cx.pid_to_addr(event.pid);
-                        if !reader.process(
-                            event.pid,
-                            false,
-                            local_node_address,
-                            time,
-                            better_time,
-                            &db_capnp,
-                            &mut subscriptions,
-                            chain_id.entry(event.pid).or_default(),
-                        ) {
-                            capnp_readers.remove(&key);
-                            capnp_blacklist.insert(key);
-                        }
-                        continue;
-                    }
-                    if event.fd == 2 {
-                        // TODO:
-                        continue;
-                    }
-                    let key = (event.pid, event.fd);
-                    if let Some(addr) = p2p_cns.get(&key) {
-                        watching
-                            .get_mut(&event.pid)
-                            .and_then(|report| {
-                                report
-                                    .network
-                                    .iter_mut()
-                                    .rev()
-                                    .find(|cn| addr.ip() == cn.ip && event.fd == cn.fd as u32)
-                            })
-                            .map(|connection| connection.checksum.1 += &data);
-                        let metadata = EventMetadata {
-                            id: ConnectionInfo {
-                                addr: *addr,
-                                pid: event.pid,
-                                fd: event.fd,
-                            },
-                            time,
-                            better_time,
-                            duration,
-                        };
-                        recorder.on_data(false, metadata, buffered, data);
-                    } else {
-                        log::warn!(
-                            "{} cannot handle data on {}, not connected, {}",
-                            event.pid,
-                            event.fd,
-                            hex::encode(data),
-                        );
+        let mut processor =
+            SnifferEventProcessor::new(db, &config, app_client.clone(), terminating.clone());
+        if config.replay_capture.is_some() {
+            processor.set_replay(replay_origin);
+        }
+        if let Some(progs) = uprobe_progs {
+            processor.set_uprobes(progs);
+        }
+
+        // the processes tracked by pid at start have no events until their connections are known
+        processor.poll();
+        loop {
+            match main_rx.recv_timeout(Duration::from_secs(1)) {
+                Ok((event, buffered)) => {
+                    processor.set_buffered(buffered);
+                    if let Some(event) = event {
+                        processor.handle(event);
                     }
                 }
-                SnifferEventVariant::Random(random) => {
-                    recorder.on_randomness(event.pid, random, time);
-                }
+                Err(mpsc::RecvTimeoutError::Timeout) => processor.poll(),
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
         }
 
@@ -1667,7 +1845,7 @@ fn main() {
                     .ok()
                     .and_then(|s| s.parse::<u32>().ok())
                     .unwrap_or_default();
-                for (pid, report) in &watching {
+                for (pid, report) in processor.reports() {
                     let summary_json = match serde_json::to_string(report) {
                         Ok(v) => v,
                         Err(err) => {
@@ -1727,13 +1905,17 @@ fn main() {
             };
             log::error!("join main thread error {msg}");
         }
-        app_client.terminate();
+        if let Some(app_client) = app_client {
+            app_client.terminate();
+        }
 
         log::info!("terminated");
     });
 
     // blocking
-    app_server.run();
+    if let Some(app_server) = app_server {
+        app_server.run();
+    }
     if let Err(err) = consumer_thread.join() {
         let msg = match err.downcast_ref::<&'static str>() {
             Some(s) => *s,
@@ -1745,5 +1927,5 @@ fn main() {
         log::error!("join consumer thread error {msg}");
     }
 
-    drop((skeleton, app));
+    drop(bpf);
 }
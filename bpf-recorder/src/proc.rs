@@ -2,7 +2,9 @@ use core::time::Duration;
 use std::{
     time::SystemTime,
     io::{self, BufRead, Read},
-    fs::File,
+    fs::{self, File},
+    collections::{BTreeMap, BTreeSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 
 /// Check whether the first command line argument matches the pattern
@@ -21,6 +23,204 @@ pub fn cmd_prefix_matches(pid: u32, pattern: &str) -> io::Result<bool> {
     Ok(false)
 }
 
+/// The first command line argument
+pub fn cmd(pid: u32) -> io::Result<String> {
+    let mut f = File::open(format!("/proc/{pid}/cmdline"))?;
+    let mut s = String::new();
+    f.read_to_string(&mut s)?;
+    Ok(s.split('\0').next().unwrap_or_default().to_owned())
+}
+
+/// Processes whose first command line argument starts with one of the names
+pub fn find_processes(names: &[String]) -> io::Result<Vec<u32>> {
+    let mut pids = vec![];
+    for entry in fs::read_dir("/proc")? {
+        let name = entry?.file_name();
+        let Some(pid) = name.to_str().and_then(|s| s.parse::<u32>().ok()) else {
+            continue;
+        };
+        // the process may terminate meanwhile
+        let Ok(cmd) = cmd(pid) else {
+            continue;
+        };
+        if names.iter().any(|name| cmd.starts_with(name.as_str())) {
+            pids.push(pid);
+        }
+    }
+
+    Ok(pids)
+}
+
+pub struct TcpSocket {
+    pub fd: u32,
+    pub local: SocketAddr,
+    pub remote: SocketAddr,
+    /// The local port is the port the process listens on
+    pub incoming: bool,
+}
+
+const TCP_ESTABLISHED: u8 = 0x01;
+const TCP_LISTEN: u8 = 0x0a;
+
+/// Established TCP connections of the process
+pub fn tcp_sockets(pid: u32) -> io::Result<Vec<TcpSocket>> {
+    let mut inodes = BTreeMap::new();
+    for entry in fs::read_dir(format!("/proc/{pid}/fd"))? {
+        let entry = entry?;
+        let Some(fd) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let Ok(link) = fs::read_link(entry.path()) else {
+            continue;
+        };
+        let inode = link
+            .to_str()
+            .and_then(|s| s.strip_prefix("socket:["))
+            .and_then(|s| s.strip_suffix(']'))
+            .and_then(|s| s.parse::<u64>().ok());
+        if let Some(inode) = inode {
+            inodes.insert(inode, fd);
+        }
+    }
+
+    let mut established = vec![];
+    let mut listening = BTreeSet::new();
+    for table in ["tcp", "tcp6"] {
+        // the table of the network namespace of the process
+        let file = match File::open(format!("/proc/{pid}/net/{table}")) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        // skip the header
+        for line in io::BufReader::new(file).lines().skip(1) {
+            let Some(entry) = TcpEntry::parse(&line?) else {
+                continue;
+            };
+            let Some(&fd) = inodes.get(&entry.inode) else {
+                continue;
+            };
+            match entry.state {
+                TCP_ESTABLISHED => established.push((fd, entry.local, entry.remote)),
+                TCP_LISTEN => {
+                    listening.insert(entry.local.port());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(established
+        .into_iter()
+        .map(|(fd, local, remote)| TcpSocket {
+            fd,
+            local,
+            remote,
+            incoming: listening.contains(&local.port()),
+        })
+        .collect())
+}
+
+/// A line of `/proc/net/tcp` or `/proc/net/tcp6`
+#[derive(Debug, PartialEq, Eq)]
+struct TcpEntry {
+    local: SocketAddr,
+    remote: SocketAddr,
+    state: u8,
+    inode: u64,
+}
+
+impl TcpEntry {
+    fn parse(line: &str) -> Option<Self> {
+        // the address is printed as native endian 32 bit words, the port is big endian
+        fn addr(s: &str) -> Option<SocketAddr> {
+            let (ip, port) = s.split_once(':')?;
+            let port = u16::from_str_radix(port, 16).ok()?;
+            let word = |i: usize| {
+                let word = ip.get((i * 8)..((i + 1) * 8))?;
+                u32::from_str_radix(word, 16).ok().map(u32::to_ne_bytes)
+            };
+            let ip = match ip.len() {
+                8 => IpAddr::V4(Ipv4Addr::from(word(0)?)),
+                32 => {
+                    let mut octets = [0; 16];
+                    for i in 0..4 {
+                        octets[(i * 4)..((i + 1) * 4)].clone_from_slice(&word(i)?);
+                    }
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }
+                _ => return None,
+            };
+            Some(SocketAddr::new(ip, port))
+        }
+
+        let mut it = line.split_whitespace();
+        let _sl = it.next()?;
+        let local = addr(it.next()?)?;
+        let remote = addr(it.next()?)?;
+        let state = u8::from_str_radix(it.next()?, 16).ok()?;
+        // tx_queue:rx_queue, tr:tm->when, retrnsmt, uid, timeout
+        let inode = it.nth(5)?.parse().ok()?;
+
+        Some(TcpEntry {
+            local,
+            remote,
+            state,
+            inode,
+        })
+    }
+}
+
+/// Name of the node the process belongs to. It is the hostname of the container,
+/// which is the pod name in kubernetes, or the name of the cgroup of the process.
+pub fn node_name(pid: u32, cgroup: u64) -> String {
+    let hostname = |path: &str| {
+        fs::read_to_string(path)
+            .ok()
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+    };
+    if let Some(name) = hostname(&format!("/proc/{pid}/root/etc/hostname")) {
+        // the process has its own hostname
+        if hostname("/etc/hostname").as_ref() != Some(&name) {
+            return name;
+        }
+    }
+
+    // cgroup v2 has a single line `0::/path`
+    let path = fs::read_to_string(format!("/proc/{pid}/cgroup"))
+        .ok()
+        .and_then(|s| {
+            s.lines()
+                .find_map(|line| line.strip_prefix("0::").map(str::to_owned))
+        });
+    match path.as_deref() {
+        Some("/") => "host".to_owned(),
+        Some(path) => cgroup_name(path),
+        None => format!("cgroup-{cgroup}"),
+    }
+}
+
+/// The container id is the last component of the path, like `docker-<id>.scope`
+fn cgroup_name(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let name = name.strip_suffix(".scope").unwrap_or(name);
+    let name = ["docker-", "cri-containerd-", "crio-", "libpod-"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name);
+    if name.len() == 64 && name.bytes().all(|c| c.is_ascii_hexdigit()) {
+        // short form, as docker prints it
+        name[..12].to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
 #[derive(Default)]
 pub struct S {
     pub b_time: Option<SystemTime>,
@@ -50,3 +250,30 @@ impl S {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+#[test]
+fn parse_tcp_entry() {
+    let line = "   1: 0100007F:1F90 0200A8C0:206E 01 00000000:00000000 02:000A7B2E 00000000  1000        0 123456 2 0000000000000000 20 4 30 10 -1";
+    let entry = TcpEntry::parse(line).unwrap();
+    assert_eq!(entry.local, "127.0.0.1:8080".parse().unwrap());
+    assert_eq!(entry.remote, "192.168.0.2:8302".parse().unwrap());
+    assert_eq!(entry.state, TCP_ESTABLISHED);
+    assert_eq!(entry.inode, 123456);
+
+    let line = "   0: 00000000000000000000000001000000:206E 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 654321 1 0000000000000000 100 0 0 10 0";
+    let entry = TcpEntry::parse(line).unwrap();
+    assert_eq!(entry.local, "[::1]:8302".parse().unwrap());
+    assert_eq!(entry.state, TCP_LISTEN);
+    assert_eq!(entry.inode, 654321);
+}
+
+#[cfg(test)]
+#[test]
+fn cgroup_name_of_container() {
+    let path = "/system.slice/docker-4f1b4a7a6a0b6e1f0e2f3f9d8d1c2b3a4f5e6d7c8b9a0f1e2d3c4b5a6f7e8d9c.scope";
+    assert_eq!(cgroup_name(path), "4f1b4a7a6a0b");
+    let path = "/kubepods/besteffort/pod1234/cri-containerd-abcdef.scope";
+    assert_eq!(cgroup_name(path), "abcdef");
+    assert_eq!(cgroup_name("/user.slice/mina-node-1"), "mina-node-1");
+}
@@ -0,0 +1,940 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::{SocketAddr, IpAddr},
+    os::fd::RawFd,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use mina_recorder::{
+    application::Application,
+    database::{DbCore, DbFacade},
+    libp2p_helper::{CapnpReader, PendingValidation},
+    Aggregator, ConnectionInfo, EventMetadata, HelperLogState, P2pRecorder, SnarkWorkerState,
+};
+use simulator::registry::messages::{ConnectionMetadata, DebuggerReport};
+
+use super::{
+    config::Config,
+    proc, tcp_info,
+    uprobe::{self, Probe, UprobeError},
+    sniffer_event::{SnifferEvent, SnifferEventVariant},
+};
+
+/// The plaintext the thread encrypted but did not write is dropped above this size,
+/// the thread probably writes to the socket the debugger does not track
+const MAX_PENDING_PLAINTEXT: usize = 0x100000;
+/// How often the plaintext state of the exited threads is released
+const THREADS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tracks debuggee processes and their connections,
+/// turns the events from the kernel into calls of `P2pRecorder` and `CapnpReader`.
+/// The events may come from the ring buffer, from a capture file or from a test.
+pub struct SnifferEventProcessor {
+    recorder: P2pRecorder,
+    db_capnp: DbCore,
+    counter: Arc<AtomicU64>,
+    app_client: Option<Application>,
+    terminating: Arc<AtomicBool>,
+    terminate: bool,
+
+    origin: Option<SystemTime>,
+    replay: bool,
+    buffered: usize,
+    max_buffered: usize,
+    max_unordered_ns: BTreeMap<u32, u64>,
+    last_ts: BTreeMap<u32, u64>,
+    max_lag: Duration,
+    tcp_info_interval: Duration,
+    tcp_info_last: Instant,
+    // programs `(encrypt, decrypt_return)` to attach to the Go libp2p helper
+    uprobe_progs: Option<(RawFd, RawFd)>,
+    uprobes: BTreeMap<u32, Vec<Probe>>,
+    // the uprobes are attached in the background, the executable is large
+    uprobes_tx: mpsc::Sender<(u32, Result<Vec<Probe>, UprobeError>)>,
+    uprobes_rx: mpsc::Receiver<(u32, Result<Vec<Probe>, UprobeError>)>,
+    // (pid, tid) -> the latest socket the thread read, the decrypted plaintext belongs to it
+    last_read: BTreeMap<(u32, u32), u32>,
+    // (pid, tid) -> the plaintext encrypted by the thread, belongs to the next socket it writes
+    pending_plaintext: BTreeMap<(u32, u32), Vec<Vec<u8>>>,
+    threads_check_last: Instant,
+
+    p2p_cns: BTreeMap<(u32, u32), SocketAddr>,
+    pending_out_cns: BTreeMap<(u32, u32), SocketAddr>,
+    watching: BTreeMap<u32, DebuggerReport>,
+    capnp_readers: BTreeMap<(u32, bool), CapnpReader>,
+    capnp_blacklist: BTreeSet<(u32, bool)>,
+    subscriptions: BTreeMap<u64, String>,
+    chain_id: BTreeMap<u32, String>,
+    validations: BTreeMap<u32, BTreeMap<u64, PendingValidation>>,
+    snark_workers: BTreeMap<u32, SnarkWorkerState>,
+    helper_logs: BTreeMap<u32, HelperLogState>,
+    cgroups: BTreeMap<u32, u64>,
+    nodes: BTreeMap<u64, String>,
+}
+
+fn watch_pid(pid: u32, terminating: Arc<AtomicBool>) {
+    // the process is considered terminated when its command changes
+    let Ok(cmd) = proc::cmd(pid) else {
+        terminating.store(true, Ordering::SeqCst);
+        return;
+    };
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+        if !proc::cmd_prefix_matches(pid, &cmd).unwrap_or_default() {
+            terminating.store(true, Ordering::SeqCst);
+            break;
+        }
+    });
+}
+
+impl SnifferEventProcessor {
+    pub fn new(
+        db: DbFacade,
+        config: &Config,
+        app_client: Option<Application>,
+        terminating: Arc<AtomicBool>,
+    ) -> Self {
+        // the config is validated, so the url is correct if any
+        let aggregator = config
+            .aggregator_url()
+            .ok()
+            .flatten()
+            .map(|url| Aggregator::new(url, config.debugger_name.clone()));
+
+        let origin = proc::S::read().ok().and_then(|s| s.b_time);
+        if let Some(boot_time) = &origin {
+            log::info!("boot time: {boot_time:?}");
+        }
+
+        let db_capnp = db.core();
+        let counter = db.messages.clone();
+        let mut recorder = P2pRecorder::new(db, config.test, aggregator);
+        recorder.set_chain_ids(config.chain_ids.clone());
+        let (uprobes_tx, uprobes_rx) = mpsc::channel();
+
+        SnifferEventProcessor {
+            db_capnp,
+            counter,
+            recorder,
+            app_client,
+            terminating,
+            terminate: config.terminate,
+            origin,
+            replay: false,
+            buffered: 0,
+            max_buffered: 0,
+            max_unordered_ns: BTreeMap::new(),
+            last_ts: BTreeMap::new(),
+            max_lag: Duration::ZERO,
+            tcp_info_interval: Duration::from_secs(config.tcp_info_interval),
+            tcp_info_last: Instant::now(),
+            uprobe_progs: None,
+            uprobes: BTreeMap::new(),
+            uprobes_tx,
+            uprobes_rx,
+            last_read: BTreeMap::new(),
+            pending_plaintext: BTreeMap::new(),
+            threads_check_last: Instant::now(),
+            p2p_cns: BTreeMap::new(),
+            pending_out_cns: BTreeMap::new(),
+            watching: BTreeMap::new(),
+            capnp_readers: BTreeMap::new(),
+            capnp_blacklist: BTreeSet::new(),
+            subscriptions: BTreeMap::new(),
+            chain_id: BTreeMap::new(),
+            validations: BTreeMap::new(),
+            snark_workers: BTreeMap::new(),
+            helper_logs: BTreeMap::new(),
+            cgroups: BTreeMap::new(),
+            nodes: BTreeMap::new(),
+        }
+    }
+
+    /// The events are not live, `origin` is the time their timestamps refer to,
+    /// if it is `None` the time of the first event is considered as now.
+    pub fn set_replay(&mut self, origin: Option<SystemTime>) {
+        self.replay = true;
+        self.origin = origin;
+    }
+
+    /// Attach the programs `(encrypt, decrypt_return)` to the Noise session
+    /// of every tracked process which has network connections
+    pub fn set_uprobes(&mut self, progs: (RawFd, RawFd)) {
+        self.uprobe_progs = Some(progs);
+    }
+
+    /// How many bytes are in the ring buffer at the moment of the next event
+    pub fn set_buffered(&mut self, buffered: usize) {
+        if buffered > self.max_buffered {
+            self.max_buffered = buffered;
+            log::info!("buffered data update maximum: {buffered}");
+        }
+        self.buffered = buffered;
+    }
+
+    /// Summary per debuggee process
+    pub fn reports(&self) -> &BTreeMap<u32, DebuggerReport> {
+        &self.watching
+    }
+
+    fn on_new_app(&mut self, pid: u32, alias: String) {
+        self.recorder.on_alias(pid, alias);
+        if !self.watching.contains_key(&pid) {
+            let version = env!("GIT_HASH");
+            self.watching.insert(
+                pid,
+                DebuggerReport {
+                    version: version.to_owned(),
+                    ipc: Default::default(),
+                    network: vec![],
+                },
+            );
+            if self.terminate {
+                watch_pid(pid, self.terminating.clone());
+            }
+        }
+    }
+
+    /// The tracked process opened the network connection, so it is the libp2p helper,
+    /// and the `execve` is certainly done, try once
+    fn attach_uprobes(&mut self, pid: u32) {
+        let Some(progs) = self.uprobe_progs else {
+            return;
+        };
+        if self.replay || self.uprobes.contains_key(&pid) {
+            return;
+        }
+        self.uprobes.insert(pid, vec![]);
+        let tx = self.uprobes_tx.clone();
+        thread::spawn(move || {
+            tx.send((pid, uprobe::attach_noise(pid, progs)))
+                .unwrap_or_default()
+        });
+    }
+
+    fn collect_uprobes(&mut self) {
+        while let Ok((pid, result)) = self.uprobes_rx.try_recv() {
+            // the process did `execve` meanwhile, the probes are dropped
+            let Some(probes) = self.uprobes.get_mut(&pid) else {
+                continue;
+            };
+            match result {
+                Ok(v) => {
+                    log::info!("{pid} capture plaintext by {} uprobes", v.len());
+                    *probes = v;
+                }
+                Err(err) => log::warn!("{pid} cannot attach uprobes: {err}"),
+            }
+        }
+    }
+
+    /// The process did `execve`, its threads and the executable are gone
+    fn forget_process(&mut self, pid: u32) {
+        self.uprobes.remove(&pid);
+        self.last_read.retain(|&(p, _), _| p != pid);
+        self.pending_plaintext.retain(|&(p, _), _| p != pid);
+    }
+
+    /// The tid of the exited thread may be reused, so the plaintext state is released
+    fn forget_exited_threads(&mut self) {
+        if self.replay || self.threads_check_last.elapsed() < THREADS_CHECK_INTERVAL {
+            return;
+        }
+        self.threads_check_last = Instant::now();
+        let alive =
+            |&(pid, tid): &(u32, u32)| Path::new(&format!("/proc/{pid}/task/{tid}")).exists();
+        self.last_read.retain(|key, _| alive(key));
+        self.pending_plaintext.retain(|key, _| alive(key));
+    }
+
+    /// The process was running before it was tracked, its connections are joined mid-stream,
+    /// they cannot be decrypted
+    fn on_existing_connections(&mut self, pid: u32, time: SystemTime, better_time: SystemTime) {
+        let sockets = match proc::tcp_sockets(pid) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("cannot list connections of {pid}: {err}");
+                return;
+            }
+        };
+        let mut fds = vec![];
+        for socket in sockets {
+            let key = (pid, socket.fd);
+            if self.p2p_cns.contains_key(&key) || self.pending_out_cns.contains_key(&key) {
+                continue;
+            }
+            let metadata = EventMetadata {
+                id: ConnectionInfo {
+                    addr: socket.remote,
+                    pid,
+                    fd: socket.fd,
+                },
+                time,
+                better_time,
+                duration: Duration::ZERO,
+            };
+            log::info!("existing connection {} -> {metadata}", socket.local);
+            self.p2p_cns.insert(key, socket.remote);
+            self.recorder
+                .on_connect_mid_stream(socket.incoming, metadata);
+            fds.push(socket.fd);
+        }
+        if let Some(app_client) = &self.app_client {
+            app_client.track_connections(pid, fds);
+        }
+    }
+
+    /// The processes added by pid, at start or at runtime, the kernel reports nothing about them
+    fn on_new_apps(&mut self, time: SystemTime, better_time: SystemTime) {
+        let new_apps = self
+            .app_client
+            .as_ref()
+            .map(|app_client| app_client.new_apps())
+            .unwrap_or_default();
+        for (pid, alias) in new_apps {
+            log::info!("track {alias} pid: {pid}");
+            self.on_new_app(pid, alias);
+            self.on_existing_connections(pid, time, better_time);
+        }
+    }
+
+    /// Should be called when there are no events, the connections of the process added by pid
+    /// are not reported by the kernel until they are enumerated
+    pub fn poll(&mut self) {
+        let now = SystemTime::now();
+        self.on_new_apps(now, now);
+        self.sample_tcp_info();
+        self.collect_uprobes();
+        self.forget_exited_threads();
+    }
+
+    /// Sample the TCP state of every connection, only makes sense for live events
+    fn sample_tcp_info(&mut self) {
+        if self.replay
+            || self.tcp_info_interval.is_zero()
+            || self.tcp_info_last.elapsed() < self.tcp_info_interval
+        {
+            return;
+        }
+        self.tcp_info_last = Instant::now();
+        for (&(pid, fd), &addr) in &self.p2p_cns {
+            match tcp_info::sample(pid, fd) {
+                Ok(sample) => {
+                    let info = ConnectionInfo { addr, pid, fd };
+                    self.recorder.on_tcp_info(&info, sample);
+                }
+                Err(err) => log::debug!("cannot sample tcp info of {pid} {fd}: {err}"),
+            }
+        }
+    }
+
+    pub fn handle(&mut self, event: SnifferEvent) {
+        self.sample_tcp_info();
+        self.collect_uprobes();
+        self.forget_exited_threads();
+        let buffered = self.buffered;
+
+        let last = self.last_ts.get(&event.tid).cloned().unwrap_or_default();
+        if event.ts1 < last {
+            let unordered = last - event.ts1;
+            log::warn!(
+                "unordered {unordered}, {} < {last}, message id {}",
+                event.ts1,
+                self.counter.load(Ordering::Relaxed)
+            );
+            let max_unordered_ns = self.max_unordered_ns.entry(event.tid).or_default();
+            if unordered > *max_unordered_ns {
+                *max_unordered_ns = unordered;
+            }
+        }
+        self.last_ts.insert(event.tid, event.ts1);
+        let time = match &self.origin {
+            None => {
+                let now = SystemTime::now();
+                self.origin = Some(now - Duration::from_nanos(event.ts1));
+                now
+            }
+            Some(origin) => *origin + Duration::from_nanos(event.ts1),
+        };
+        let better_time = if self.replay {
+            time
+        } else {
+            let instant_there = Duration::from_nanos(event.ts1);
+            let mut tp = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut tp) };
+            let instant_here = Duration::new(tp.tv_sec as _, tp.tv_nsec as _);
+            let delta = instant_here.checked_sub(instant_there).unwrap_or_default();
+            if delta >= self.max_lag + Duration::from_secs(60) {
+                self.max_lag = delta;
+                log::warn!("lagging: {delta:?}");
+            }
+            SystemTime::now() - delta
+        };
+        let duration = Duration::from_nanos(event.ts1 - event.ts0);
+        let capture_lost = self
+            .app_client
+            .as_ref()
+            .map(|app_client| app_client.capture_lost())
+            .unwrap_or_default();
+        // the capture of old version has no cgroup
+        if event.cgroup != 0 && self.cgroups.get(&event.pid) != Some(&event.cgroup) {
+            self.cgroups.insert(event.pid, event.cgroup);
+            let replay = self.replay;
+            let node = self
+                .nodes
+                .entry(event.cgroup)
+                .or_insert_with(|| {
+                    // the pid may belong to another process now, the capture has only the cgroup
+                    if replay {
+                        format!("cgroup-{}", event.cgroup)
+                    } else {
+                        proc::node_name(event.pid, event.cgroup)
+                    }
+                })
+                .clone();
+            log::info!("pid {} belongs to node {node}", event.pid);
+            self.recorder.set_node(event.pid, node);
+        }
+        self.on_new_apps(time, better_time);
+        for (pid, fd) in capture_lost {
+            if let Some(addr) = self.p2p_cns.get(&(pid, fd)) {
+                let info = ConnectionInfo {
+                    addr: *addr,
+                    pid,
+                    fd,
+                };
+                self.recorder.on_capture_incomplete(&info, time);
+            }
+        }
+        match event.variant {
+            SnifferEventVariant::NewSnarkWorkerApp => {
+                self.snark_workers
+                    .insert(event.pid, SnarkWorkerState::default());
+            }
+            SnifferEventVariant::NewApp(alias) => {
+                log::info!("exec {alias} pid: {}", event.pid);
+                self.forget_process(event.pid);
+                self.on_new_app(event.pid, alias);
+            }
+            SnifferEventVariant::Bind(addr) => {
+                self.recorder.set_port(event.pid, addr.port());
+            }
+            SnifferEventVariant::OutgoingConnection(addr) => {
+                let metadata = EventMetadata {
+                    id: ConnectionInfo {
+                        addr,
+                        pid: event.pid,
+                        fd: event.fd,
+                    },
+                    time,
+                    better_time,
+                    duration,
+                };
+
+                if let Some(snark_worker_state) = self.snark_workers.get_mut(&event.pid) {
+                    snark_worker_state.on_close(event.fd);
+                }
+                log::info!("new unconfirmed {metadata}");
+                self.pending_out_cns.insert((event.pid, event.fd), addr);
+            }
+            SnifferEventVariant::GetSockOpt(value) => {
+                if value.len() != 4 {
+                    return;
+                }
+                let Some(addr) = self.pending_out_cns.remove(&(event.pid, event.fd)) else {
+                    return;
+                };
+                let metadata = EventMetadata {
+                    id: ConnectionInfo {
+                        addr,
+                        pid: event.pid,
+                        fd: event.fd,
+                    },
+                    time,
+                    better_time,
+                    duration,
+                };
+                let value = u32::from_ne_bytes(
+                    value
+                        .as_slice()
+                        .try_into()
+                        .expect("must be checked above `value.len() != 4`"),
+                );
+                log::info!("getsockopt {value}, {metadata}");
+                if value != 0 {
+                    return;
+                }
+                if let Some(report) = self.watching.get_mut(&event.pid) {
+                    let counter = report
+                        .network
+                        .iter()
+                        .filter(|cn| cn.ip == addr.ip())
+                        .count();
+                    report.network.push(ConnectionMetadata {
+                        ip: addr.ip(),
+                        counter,
+                        incoming: false,
+                        fd: event.fd as i32,
+                        checksum: Default::default(),
+                        timestamp: better_time,
+                    });
+                }
+
+                if let Some(old_addr) = self.p2p_cns.insert((event.pid, event.fd), addr) {
+                    log::warn!("new outgoing connection on already allocated fd");
+                    let mut metadata = metadata.clone();
+                    metadata.id.addr = old_addr;
+                    self.recorder.on_disconnect(metadata, buffered);
+                }
+                log::info!("new outgoing connection {}", metadata);
+                self.attach_uprobes(event.pid);
+                self.recorder.on_connect::<true>(
+                    false,
+                    metadata,
+                    buffered,
+                    self.chain_id.get(&event.pid).cloned().unwrap_or_default(),
+                );
+            }
+            SnifferEventVariant::IncomingConnection(addr) => {
+                if let Some(report) = self.watching.get_mut(&event.pid) {
+                    let counter = report
+                        .network
+                        .iter()
+                        .filter(|cn| cn.ip == addr.ip())
+                        .count();
+                    report.network.push(ConnectionMetadata {
+                        ip: addr.ip(),
+                        counter,
+                        incoming: true,
+                        fd: event.fd as i32,
+                        checksum: Default::default(),
+                        timestamp: better_time,
+                    });
+                }
+
+                let metadata = EventMetadata {
+                    id: ConnectionInfo {
+                        addr,
+                        pid: event.pid,
+                        fd: event.fd,
+                    },
+                    time,
+                    better_time,
+                    duration,
+                };
+                if let Some(old_addr) = self.p2p_cns.insert((event.pid, event.fd), addr) {
+                    log::warn!("new incoming connection on already allocated fd");
+                    let mut metadata = metadata.clone();
+                    metadata.id.addr = old_addr;
+                    self.recorder.on_disconnect(metadata, buffered);
+                }
+                log::info!("new incoming connection {}", metadata);
+                self.attach_uprobes(event.pid);
+                self.recorder.on_connect::<true>(
+                    true,
+                    metadata,
+                    buffered,
+                    self.chain_id.get(&event.pid).cloned().unwrap_or_default(),
+                );
+            }
+            SnifferEventVariant::Disconnected => {
+                if let Some(snark_worker_state) = self.snark_workers.get_mut(&event.pid) {
+                    snark_worker_state.on_close(event.fd);
+                }
+                let key = (event.pid, event.fd);
+                if let Some(addr) = self.p2p_cns.remove(&key) {
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr,
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    log::info!("disconnected {}", metadata);
+                    self.recorder.on_disconnect(metadata, buffered);
+                } else {
+                    // `close` means close socket, not necessarily it was connected
+                    // so it is ok
+                    log::debug!(
+                        "{} cannot process disconnect {}, not connected",
+                        event.pid,
+                        event.fd
+                    );
+                }
+            }
+            SnifferEventVariant::Error(_, -104) => {}
+            SnifferEventVariant::Error(tag, code) => {
+                let key = (event.pid, event.fd);
+                if let Some(addr) = self.p2p_cns.get(&key) {
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: *addr,
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+
+                    log::error!("{metadata},  tag: {tag:?}, code: {code}");
+                    // the ring buffer has no space for the data
+                    if code == -90 {
+                        self.recorder.on_capture_incomplete(&metadata.id, time);
+                    }
+                }
+            }
+            SnifferEventVariant::IncomingData(data) => {
+                if let Some(snark_worker_state) = self.snark_workers.get_mut(&event.pid) {
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: SocketAddr::new(IpAddr::V4(0.into()), 0),
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    let node = self.recorder.cx.pid_to_node(event.pid);
+                    let db = &self.recorder.cx.db;
+                    snark_worker_state.handle_data(db, node, metadata, true, data);
+                    return;
+                }
+                if event.fd == 0 || event.fd == 1 {
+                    self.watching
+                        .get_mut(&event.pid)
+                        .map(|report| report.ipc.0 += &data);
+
+                    let key = (event.pid, true);
+                    if self.capnp_blacklist.contains(&key) {
+                        return;
+                    }
+                    let reader = self.capnp_readers.entry(key).or_default();
+                    reader.extend_from_slice(&data);
+                    let local_node_address = self.recorder.cx.pid_to_addr(event.pid);
+                    if !reader.process(
+                        event.pid,
+                        true,
+                        local_node_address,
+                        time,
+                        better_time,
+                        &self.db_capnp,
+                        &mut self.subscriptions,
+                        self.chain_id.entry(event.pid).or_default(),
+                        self.validations.entry(event.pid).or_default(),
+                    ) {
+                        self.capnp_readers.remove(&key);
+                        self.capnp_blacklist.insert(key);
+                    }
+                    return;
+                }
+                if event.fd == 2 {
+                    // the helper does not read its stderr
+                    return;
+                }
+                let key = (event.pid, event.fd);
+                if let Some(addr) = self.p2p_cns.get(&key) {
+                    self.watching
+                        .get_mut(&event.pid)
+                        .and_then(|report| {
+                            report
+                                .network
+                                .iter_mut()
+                                .rev()
+                                .find(|cn| addr.ip() == cn.ip && event.fd == cn.fd as u32)
+                        })
+                        .map(|connection| connection.checksum.0 += &data);
+
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: *addr,
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    self.last_read.insert((event.pid, event.tid), event.fd);
+                    self.recorder.on_data(true, metadata, buffered, data);
+                } else {
+                    log::warn!(
+                        "{} cannot handle data on {}, not connected, {}",
+                        event.pid,
+                        event.fd,
+                        hex::encode(data),
+                    );
+                }
+            }
+            SnifferEventVariant::OutgoingData(data) => {
+                if let Some(snark_worker_state) = self.snark_workers.get_mut(&event.pid) {
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: SocketAddr::new(IpAddr::V4(0.into()), 0),
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    let node = self.recorder.cx.pid_to_node(event.pid);
+                    let db = &self.recorder.cx.db;
+                    snark_worker_state.handle_data(db, node, metadata, false, data);
+                    return;
+                }
+                if event.fd == 0 || event.fd == 1 {
+                    self.watching
+                        .get_mut(&event.pid)
+                        .map(|report| report.ipc.1 += &data);
+
+                    let key = (event.pid, false);
+                    if self.capnp_blacklist.contains(&key) {
+                        return;
+                    }
+                    let reader = self.capnp_readers.entry(key).or_default();
+                    reader.extend_from_slice(&data);
+                    let local_node_address = self.recorder.cx.pid_to_addr(event.pid);
+                    if !reader.process(
+                        event.pid,
+                        false,
+                        local_node_address,
+                        time,
+                        better_time,
+                        &self.db_capnp,
+                        &mut self.subscriptions,
+                        self.chain_id.entry(event.pid).or_default(),
+                        self.validations.entry(event.pid).or_default(),
+                    ) {
+                        self.capnp_readers.remove(&key);
+                        self.capnp_blacklist.insert(key);
+                    }
+                    return;
+                }
+                if event.fd == 2 {
+                    let node = self.recorder.cx.pid_to_node(event.pid);
+                    self.helper_logs.entry(event.pid).or_default().handle_data(
+                        &self.db_capnp,
+                        event.pid,
+                        node,
+                        time,
+                        &data,
+                    );
+                    return;
+                }
+                let key = (event.pid, event.fd);
+                // the thread encrypts right before it writes
+                let pending = self.pending_plaintext.remove(&(event.pid, event.tid));
+                if let Some(addr) = self.p2p_cns.get(&key) {
+                    self.watching
+                        .get_mut(&event.pid)
+                        .and_then(|report| {
+                            report
+                                .network
+                                .iter_mut()
+                                .rev()
+                                .find(|cn| addr.ip() == cn.ip && event.fd == cn.fd as u32)
+                        })
+                        .map(|connection| connection.checksum.1 += &data);
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: *addr,
+                            pid: event.pid,
+                            fd: event.fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    for plaintext in pending.into_iter().flatten() {
+                        self.recorder
+                            .on_plaintext(false, metadata.clone(), buffered, plaintext);
+                    }
+                    self.recorder.on_data(false, metadata, buffered, data);
+                } else {
+                    log::warn!(
+                        "{} cannot handle data on {}, not connected, {}",
+                        event.pid,
+                        event.fd,
+                        hex::encode(data),
+                    );
+                }
+            }
+            SnifferEventVariant::Random(random) => {
+                self.recorder.on_randomness(event.pid, random, time);
+            }
+            SnifferEventVariant::IncomingPlaintext(data) => {
+                // the thread decrypts right after it reads
+                let Some(&fd) = self.last_read.get(&(event.pid, event.tid)) else {
+                    log::debug!("{} cannot find the socket of plaintext", event.pid);
+                    return;
+                };
+                if let Some(addr) = self.p2p_cns.get(&(event.pid, fd)) {
+                    let metadata = EventMetadata {
+                        id: ConnectionInfo {
+                            addr: *addr,
+                            pid: event.pid,
+                            fd,
+                        },
+                        time,
+                        better_time,
+                        duration,
+                    };
+                    self.recorder.on_plaintext(true, metadata, buffered, data);
+                }
+            }
+            SnifferEventVariant::OutgoingPlaintext(data) => {
+                let pending = self
+                    .pending_plaintext
+                    .entry((event.pid, event.tid))
+                    .or_default();
+                pending.push(data);
+                let size = pending.iter().map(Vec::len).sum::<usize>();
+                if size > MAX_PENDING_PLAINTEXT {
+                    log::warn!(
+                        "{} thread {} did not write {size} bytes of plaintext",
+                        event.pid,
+                        event.tid,
+                    );
+                    pending.drain(..(pending.len() - 1));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn connect_getsockopt_data_close() {
+    use std::time::UNIX_EPOCH;
+
+    let path = "/tmp/test_sniffer_event_processor";
+    std::fs::remove_dir_all(path).unwrap_or_default();
+    let db = DbFacade::open(path).unwrap();
+    let db_core = db.core();
+
+    let origin = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let config = Config::default();
+    let mut processor =
+        SnifferEventProcessor::new(db, &config, None, Arc::new(AtomicBool::new(false)));
+    processor.set_replay(Some(origin));
+
+    let mut ts = 0;
+    let mut handle = |fd, variant| {
+        ts += 1_000;
+        processor.handle(SnifferEvent {
+            pid: 10,
+            tid: 11,
+            fd,
+            ts0: ts,
+            ts1: ts + 10,
+            cgroup: 0,
+            variant,
+        });
+    };
+
+    let addr = "5.6.7.8:8302".parse().unwrap();
+    handle(0, SnifferEventVariant::NewApp("devnet-1.2.3.4".to_owned()));
+    handle(20, SnifferEventVariant::OutgoingConnection(addr));
+    // connection is not established until `getsockopt` reports no error
+    handle(
+        20,
+        SnifferEventVariant::GetSockOpt(0_u32.to_ne_bytes().to_vec()),
+    );
+    handle(20, SnifferEventVariant::OutgoingData(vec![0; 0x20]));
+    handle(20, SnifferEventVariant::Disconnected);
+
+    // connection refused
+    handle(21, SnifferEventVariant::OutgoingConnection(addr));
+    handle(
+        21,
+        SnifferEventVariant::GetSockOpt(111_u32.to_ne_bytes().to_vec()),
+    );
+
+    let incoming_addr = "9.9.9.9:12345".parse().unwrap();
+    handle(22, SnifferEventVariant::IncomingConnection(incoming_addr));
+
+    let cn = db_core.fetch_connection(0).unwrap();
+    assert_eq!(cn.info.addr, addr);
+    assert_eq!(cn.info.fd, 20);
+    assert!(!cn.incoming);
+    assert_eq!(cn.alias, "devnet-1.2.3.4");
+    assert_eq!(cn.timestamp, origin + Duration::from_nanos(3_010));
+    assert_ne!(cn.timestamp_close, UNIX_EPOCH);
+
+    let cn = db_core.fetch_connection(1).unwrap();
+    assert_eq!(cn.info.addr, incoming_addr);
+    assert_eq!(cn.info.fd, 22);
+    assert!(cn.incoming);
+    assert_eq!(cn.timestamp_close, UNIX_EPOCH);
+
+    assert!(db_core.fetch_connection(2).is_err());
+    assert!(processor.reports().contains_key(&10));
+}
+
+#[cfg(test)]
+#[test]
+fn plaintext_follows_the_thread() {
+    use std::time::UNIX_EPOCH;
+    use SnifferEventVariant::*;
+
+    let path = "/tmp/test_sniffer_event_processor_plaintext";
+    std::fs::remove_dir_all(path).unwrap_or_default();
+    let db = DbFacade::open(path).unwrap();
+
+    let config = Config::default();
+    let mut processor =
+        SnifferEventProcessor::new(db, &config, None, Arc::new(AtomicBool::new(false)));
+    processor.set_replay(Some(UNIX_EPOCH));
+
+    let mut ts = 0;
+    let mut event = |tid, fd, variant| {
+        ts += 1_000;
+        SnifferEvent {
+            pid: 10,
+            tid,
+            fd,
+            ts0: ts,
+            ts1: ts + 10,
+            cgroup: 0,
+            variant,
+        }
+    };
+
+    let addr = "9.9.9.9:12345".parse().unwrap();
+    processor.handle(event(11, 0, NewApp("helper".to_owned())));
+    processor.handle(event(11, 20, IncomingConnection(addr)));
+    processor.handle(event(11, 20, IncomingData(vec![0; 0x20])));
+    assert_eq!(processor.last_read.get(&(10, 11)), Some(&20));
+    // another thread did not read anything
+    processor.handle(event(12, 20, IncomingPlaintext(vec![1; 0x10])));
+
+    // the thread encrypts, but never writes, only the latest plaintext is kept
+    let chunk = vec![2; MAX_PENDING_PLAINTEXT / 4 + 1];
+    for _ in 0..4 {
+        processor.handle(event(12, 0, OutgoingPlaintext(chunk.clone())));
+    }
+    assert_eq!(processor.pending_plaintext[&(10, 12)], [chunk]);
+    // the write takes the plaintext
+    processor.handle(event(12, 20, OutgoingData(vec![0; 0x20])));
+    assert!(processor.pending_plaintext.is_empty());
+
+    processor.handle(event(13, 0, OutgoingPlaintext(vec![3; 0x10])));
+    // `execve` replaces every thread
+    processor.handle(event(11, 0, NewApp("helper".to_owned())));
+    assert!(processor.last_read.is_empty());
+    assert!(processor.pending_plaintext.is_empty());
+}
@@ -0,0 +1,178 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    time::Duration,
+};
+
+pub use mina_recorder::application::ReorderStats;
+
+/// Merges the events of several ring buffers by timestamp. Every ring buffer is mostly ordered,
+/// so the earliest event is released as soon as every ring buffer delivered something later,
+/// or when it is older than the window, so the idle ring buffer does not stall the others.
+pub struct Reorder<T> {
+    window_ns: u64,
+    // the latest timestamp delivered by each ring buffer
+    heads: Vec<u64>,
+    heap: BinaryHeap<Reverse<Entry<T>>>,
+    seq: u64,
+    last: u64,
+    stats: ReorderStats,
+}
+
+struct Entry<T> {
+    ts: u64,
+    seq: u64,
+    value: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.ts, self.seq).cmp(&(other.ts, other.seq))
+    }
+}
+
+impl<T> Reorder<T> {
+    pub fn new(shards: usize, window: Duration) -> Self {
+        Reorder {
+            window_ns: window.as_nanos() as u64,
+            heads: vec![0; shards],
+            heap: BinaryHeap::new(),
+            seq: 0,
+            last: 0,
+            stats: ReorderStats::default(),
+        }
+    }
+
+    pub fn push(&mut self, shard: usize, ts: u64, value: T) {
+        if let Some(head) = self.heads.get_mut(shard) {
+            *head = (*head).max(ts);
+        }
+        self.heap.push(Reverse(Entry {
+            ts,
+            seq: self.seq,
+            value,
+        }));
+        self.seq += 1;
+        if self.heap.len() > self.stats.max_buffered {
+            self.stats.max_buffered = self.heap.len();
+            log::info!("reorder buffer update maximum: {}", self.heap.len());
+        }
+    }
+
+    /// The earliest event if it is ready, `now` is in the same clock as the timestamps
+    pub fn pop(&mut self, now: u64) -> Option<T> {
+        let Reverse(earliest) = self.heap.peek()?;
+        let ts = earliest.ts;
+        let all_later = self.heads.iter().all(|&head| head >= ts);
+        if !all_later && ts.saturating_add(self.window_ns) > now {
+            return None;
+        }
+        self.release()
+    }
+
+    /// Release everything regardless of the window
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.release())
+    }
+
+    fn release(&mut self) -> Option<T> {
+        let Reverse(Entry { ts, value, .. }) = self.heap.pop()?;
+        self.stats.released += 1;
+        if ts < self.last {
+            let disorder = self.last - ts;
+            self.stats.disordered += 1;
+            if disorder > self.stats.max_disorder_ns {
+                self.stats.max_disorder_ns = disorder;
+                log::warn!("event is {disorder} ns late for the reorder window");
+            }
+        } else {
+            self.last = ts;
+        }
+        Some(value)
+    }
+
+    pub fn stats(&self) -> &ReorderStats {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn merge_shards() {
+    let mut reorder = Reorder::new(2, Duration::from_nanos(100));
+
+    reorder.push(0, 10, "a");
+    reorder.push(0, 30, "c");
+    // the second shard is silent, wait
+    assert_eq!(reorder.pop(50), None);
+    reorder.push(1, 20, "b");
+    assert_eq!(reorder.pop(50), Some("a"));
+    assert_eq!(reorder.pop(50), Some("b"));
+    // the second shard did not deliver anything later than 30, wait for the window
+    assert_eq!(reorder.pop(50), None);
+    assert_eq!(reorder.pop(130), Some("c"));
+
+    // too late
+    reorder.push(1, 25, "d");
+    assert_eq!(reorder.pop(130), Some("d"));
+    assert_eq!(
+        reorder.stats(),
+        &ReorderStats {
+            released: 4,
+            max_buffered: 3,
+            disordered: 1,
+            max_disorder_ns: 5,
+        }
+    );
+
+    reorder.push(0, 200, "e");
+    assert_eq!(reorder.drain().collect::<Vec<_>>(), ["e"]);
+}
+
+#[cfg(test)]
+#[test]
+fn connection_over_shards() {
+    use crate::DataTag;
+
+    const SHARDS: u32 = 4;
+    let window = Duration::from_millis(20);
+
+    // tag, cpu and timestamp of the events of one connection, the process migrates among cpus
+    let events = [
+        (DataTag::Connect, 1, 10),
+        (DataTag::Write, 1, 20),
+        (DataTag::Read, 2, 30),
+        (DataTag::Close, 3, 40),
+    ];
+    let shard = |&(tag, cpu, _): &(DataTag, u32, u64)| tag.shard(cpu, SHARDS) as usize;
+    // the data is in other ring buffers than the connect and the close
+    assert_eq!(events.iter().map(shard).collect::<Vec<_>>(), [0, 1, 2, 0]);
+
+    let mut reorder = Reorder::new(SHARDS as usize, window);
+    // the first ring buffer is read before the others, so the close comes before the data
+    for event in [events[0], events[3], events[2], events[1]] {
+        reorder.push(shard(&event), event.2, event.0);
+    }
+    // the last ring buffer delivered nothing
+    assert!(reorder.pop(45).is_none());
+    let now = 40 + window.as_nanos() as u64;
+    let released = std::iter::from_fn(|| reorder.pop(now))
+        .map(DataTag::name)
+        .collect::<Vec<_>>();
+    assert_eq!(released, ["connect", "write", "read", "close"]);
+    assert_eq!(reorder.stats().disordered, 0);
+}
@@ -6,14 +6,17 @@ use bpf_recorder::Event;
 
 use ebpf_kern::RingBufferRef;
 
+/// Returns `Ok(false)` if the payload did not fit in the ring buffer,
+/// the event is sent anyway with size `-90`
 #[inline(always)]
-pub fn sized<S, K>(rb: &mut RingBufferRef, mut event: Event, data: *const u8) -> Result<(), i32>
+pub fn sized<S, K>(rb: &mut RingBufferRef, mut event: Event, data: *const u8) -> Result<bool, i32>
 where
     S: Unsigned,
     K: Bit,
 {
     use ebpf_kern::helpers;
 
+    let mut fit = true;
     if event.size > 0 && event.size <= S::U64 as i32 {
         if let Ok(mut buffer) = rb.reserve(S::U64 as usize + mem::size_of::<Event>()) {
             let p_buffer = buffer.as_mut().as_mut_ptr() as *mut Event;
@@ -43,9 +46,10 @@ where
             }
 
             buffer.submit();
-            return Ok(());
+            return Ok(true);
         } else {
             event.size = -90;
+            fit = false;
         }
     }
 
@@ -54,11 +58,11 @@ where
         ptr::write(buffer.as_mut().as_mut_ptr() as *mut _, event);
     }
     buffer.submit();
-    Ok(())
+    Ok(fit)
 }
 
 #[inline(always)]
-pub fn dyn_sized<K>(rb: &mut RingBufferRef, event: Event, data: *const u8) -> Result<(), i32>
+pub fn dyn_sized<K>(rb: &mut RingBufferRef, event: Event, data: *const u8) -> Result<bool, i32>
 where
     K: Bit,
 {
@@ -0,0 +1,106 @@
+use std::{
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    time::SystemTime,
+};
+
+use mina_recorder::database::TcpInfoSample;
+
+/// Size of `struct tcp_info` we are interested in, older kernels report less
+const TCP_INFO_SIZE: usize = 0xe8;
+
+fn syscall_fd(r: libc::c_long) -> io::Result<OwnedFd> {
+    if r < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(r as RawFd) })
+    }
+}
+
+/// Duplicate the socket `fd` of the process `pid`, requires the same permissions as ptrace
+pub fn socket_of(pid: u32, fd: u32) -> io::Result<OwnedFd> {
+    let pidfd = syscall_fd(unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) })?;
+    syscall_fd(unsafe { libc::syscall(libc::SYS_pidfd_getfd, pidfd.as_raw_fd(), fd as RawFd, 0) })
+}
+
+/// Ask the kernel about the state of the TCP socket
+pub fn read(socket: &impl AsRawFd, time: SystemTime) -> io::Result<TcpInfoSample> {
+    let mut buf = [0; TCP_INFO_SIZE];
+    let mut len = TCP_INFO_SIZE as libc::socklen_t;
+    let r = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(parse(&buf[..(len as usize)], time))
+}
+
+/// Sample the socket `fd` of the process `pid`
+pub fn sample(pid: u32, fd: u32) -> io::Result<TcpInfoSample> {
+    read(&socket_of(pid, fd)?, SystemTime::now())
+}
+
+/// Parse `struct tcp_info` from `linux/tcp.h`, the fields absent in the buffer are zero
+fn parse(buf: &[u8], time: SystemTime) -> TcpInfoSample {
+    let u8_at = |offset: usize| buf.get(offset).copied().unwrap_or_default();
+    let u32_at = |offset: usize| {
+        buf.get(offset..(offset + mem::size_of::<u32>()))
+            .map(|b| u32::from_ne_bytes(b.try_into().expect("cannot fail")))
+            .unwrap_or_default()
+    };
+    let u64_at = |offset: usize| {
+        buf.get(offset..(offset + mem::size_of::<u64>()))
+            .map(|b| u64::from_ne_bytes(b.try_into().expect("cannot fail")))
+            .unwrap_or_default()
+    };
+
+    TcpInfoSample {
+        time,
+        state: u8_at(0),
+        retransmits: u8_at(2),
+        rto: u32_at(8),
+        snd_mss: u32_at(16),
+        unacked: u32_at(24),
+        lost: u32_at(32),
+        rtt: u32_at(68),
+        rttvar: u32_at(72),
+        snd_ssthresh: u32_at(76),
+        snd_cwnd: u32_at(80),
+        total_retrans: u32_at(100),
+        bytes_acked: u64_at(120),
+        bytes_received: u64_at(128),
+        min_rtt: u32_at(148),
+        delivery_rate: u64_at(160),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn sample_own_connection() {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    client.write_all(&[1; 0x1000]).unwrap();
+    server.read_exact(&mut [0; 0x1000]).unwrap();
+
+    let sample = read(&client, SystemTime::now()).unwrap();
+    // TCP_ESTABLISHED
+    assert_eq!(sample.state, 1);
+    assert!(sample.snd_mss > 0);
+    assert!(sample.snd_cwnd > 0);
+
+    let sample = read(&server, SystemTime::now()).unwrap();
+    assert_eq!(sample.bytes_received, 0x1000);
+}
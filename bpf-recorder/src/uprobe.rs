@@ -0,0 +1,287 @@
+//! Uprobes on the Noise session of the Go libp2p helper, so the plaintext is captured
+//! before encryption and after decryption, no need to follow the handshake.
+//!
+//! Go moves the goroutine stacks, so `uretprobe` corrupts the process,
+//! instead the return is probed at every `RET` instruction of the function.
+//! The Go register ABI (Go 1.17 and newer, amd64) is expected.
+
+use std::{
+    fs, io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+};
+
+use iced_x86::{Decoder, DecoderOptions, Mnemonic};
+use object::{Object, ObjectSection, ObjectSymbol};
+use thiserror::Error;
+
+pub const ENCRYPT_SYMBOL: &str =
+    "github.com/libp2p/go-libp2p/p2p/security/noise.(*secureSession).encrypt";
+pub const DECRYPT_SYMBOL: &str =
+    "github.com/libp2p/go-libp2p/p2p/security/noise.(*secureSession).decrypt";
+
+const UPROBE_TYPE_PATH: &str = "/sys/bus/event_source/devices/uprobe/type";
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_SET_BPF: libc::c_ulong = 0x40042408;
+
+#[derive(Debug, Error)]
+pub enum UprobeError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("cannot parse executable: {0}")]
+    Object(#[from] object::Error),
+    #[error("symbol {0} is not found")]
+    NoSymbol(&'static str),
+    #[error("symbol {0} has no return")]
+    NoReturn(&'static str),
+}
+
+/// File offsets of the function in the executable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionOffsets {
+    pub entry: u64,
+    pub returns: Vec<u64>,
+}
+
+/// Find the function by its symbol, and its `RET` instructions
+pub fn resolve(file: &object::File, symbol: &'static str) -> Result<FunctionOffsets, UprobeError> {
+    let sym = file
+        .symbols()
+        .find(|s| s.name() == Ok(symbol))
+        .ok_or(UprobeError::NoSymbol(symbol))?;
+    let index = sym.section_index().ok_or(UprobeError::NoSymbol(symbol))?;
+    let section = file.section_by_index(index)?;
+    let (section_offset, _) = section.file_range().ok_or(UprobeError::NoSymbol(symbol))?;
+
+    let start = sym.address() - section.address();
+    let entry = section_offset + start;
+    let code = section
+        .data()?
+        .get((start as usize)..((start + sym.size()) as usize))
+        .ok_or(UprobeError::NoSymbol(symbol))?;
+    let returns = Decoder::with_ip(64, code, 0, DecoderOptions::NONE)
+        .into_iter()
+        .filter(|instruction| instruction.mnemonic() == Mnemonic::Ret)
+        .map(|instruction| entry + instruction.ip())
+        .collect::<Vec<_>>();
+    if returns.is_empty() {
+        return Err(UprobeError::NoReturn(symbol));
+    }
+
+    Ok(FunctionOffsets { entry, returns })
+}
+
+/// The prefix of `struct perf_event_attr`, `PERF_ATTR_SIZE_VER5`
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    ty: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    // `uprobe_path`
+    config1: u64,
+    // `probe_offset`
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+/// The probe is detached when dropped
+pub struct Probe(OwnedFd);
+
+/// Attach the program `prog_fd` at the `offset` of the executable `path`,
+/// the probe is only installed in the process `pid`,
+/// but the program runs in every thread of the process
+pub fn attach(prog_fd: RawFd, path: &Path, offset: u64, pid: u32) -> io::Result<Probe> {
+    let ty = fs::read_to_string(UPROBE_TYPE_PATH)?
+        .trim()
+        .parse::<u32>()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut path = path.as_os_str().as_bytes().to_vec();
+    path.push(0);
+
+    let attr = PerfEventAttr {
+        ty,
+        size: mem::size_of::<PerfEventAttr>() as u32,
+        config1: path.as_ptr() as u64,
+        config2: offset,
+        ..Default::default()
+    };
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr as *const PerfEventAttr,
+            pid as libc::pid_t,
+            -1,
+            -1,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let probe = Probe(unsafe { OwnedFd::from_raw_fd(fd as RawFd) });
+
+    if unsafe { libc::ioctl(probe.0.as_raw_fd(), PERF_EVENT_IOC_SET_BPF, prog_fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::ioctl(probe.0.as_raw_fd(), PERF_EVENT_IOC_ENABLE, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(probe)
+}
+
+/// Probe the Noise session of the process `pid`, programs are `(encrypt, decrypt_return)`,
+/// reads the whole executable, so it is slow
+pub fn attach_noise(pid: u32, progs: (RawFd, RawFd)) -> Result<Vec<Probe>, UprobeError> {
+    let (encrypt_prog, decrypt_prog) = progs;
+    // refers to the executable even if it is in another mount namespace
+    let path = Path::new("/proc").join(pid.to_string()).join("exe");
+    let data = fs::read(&path)?;
+    let file = object::File::parse(&*data)?;
+    let encrypt = resolve(&file, ENCRYPT_SYMBOL)?;
+    let decrypt = resolve(&file, DECRYPT_SYMBOL)?;
+
+    let mut probes = vec![attach(encrypt_prog, &path, encrypt.entry, pid)?];
+    for offset in decrypt.returns {
+        probes.push(attach(decrypt_prog, &path, offset, pid)?);
+    }
+    Ok(probes)
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+#[no_mangle]
+#[inline(never)]
+extern "C" fn bpf_recorder_uprobe_test_function(x: u64) -> u64 {
+    if x & 1 == 0 {
+        x / 2
+    } else {
+        x * 3 + 1
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+#[test]
+fn resolve_own_function() {
+    // the test executable itself has the symbol table, no toolchain is needed
+    let data = fs::read("/proc/self/exe").unwrap();
+    let file = object::File::parse(&*data).unwrap();
+    let function = resolve(&file, "bpf_recorder_uprobe_test_function").unwrap();
+    assert_eq!(bpf_recorder_uprobe_test_function(3), 10);
+
+    // the offset in the file points to the code which runs
+    let code = bpf_recorder_uprobe_test_function as *const u8;
+    let code = unsafe { std::slice::from_raw_parts(code, 8) };
+    assert_eq!(&data[(function.entry as usize)..][..8], code);
+    for ret in function.returns {
+        assert!(ret > function.entry);
+        assert_eq!(data[ret as usize], 0xc3);
+    }
+
+    assert!(matches!(
+        resolve(&file, "bpf_recorder_uprobe_no_such_function"),
+        Err(UprobeError::NoSymbol(_)),
+    ));
+}
+
+#[cfg(test)]
+#[test]
+#[ignore = "needs the go toolchain"]
+fn resolve_go_stand_in() {
+    use std::process::Command;
+
+    // the module path is the same as go-libp2p, so the symbols are the same
+    let dir = std::env::temp_dir().join("bpf-recorder-uprobe-stand-in");
+    let src = dir.join("p2p/security/noise");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(
+        dir.join("go.mod"),
+        "module github.com/libp2p/go-libp2p\n\ngo 1.18\n",
+    )
+    .unwrap();
+    fs::write(
+        src.join("session.go"),
+        r#"package noise
+
+type secureSession struct{ key byte }
+
+//go:noinline
+func (s *secureSession) encrypt(out, plaintext []byte) ([]byte, error) {
+	for _, b := range plaintext {
+		out = append(out, b^s.key)
+	}
+	return out, nil
+}
+
+//go:noinline
+func (s *secureSession) decrypt(out, ciphertext []byte) ([]byte, error) {
+	return s.encrypt(out, ciphertext)
+}
+
+func RoundTrip(data []byte) []byte {
+	s := &secureSession{key: 0x5a}
+	c, _ := s.encrypt(nil, data)
+	p, _ := s.decrypt(nil, c)
+	return p
+}
+"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("main.go"),
+        r#"package main
+
+import (
+	"os"
+
+	"github.com/libp2p/go-libp2p/p2p/security/noise"
+)
+
+func main() {
+	os.Stdout.Write(noise.RoundTrip([]byte("hello")))
+}
+"#,
+    )
+    .unwrap();
+
+    let exe = dir.join("stand-in");
+    let build = Command::new("go")
+        .current_dir(&dir)
+        .args(["build", "-o"])
+        .arg(&exe)
+        .arg(".")
+        .status();
+    assert!(build.expect("go toolchain is not found").success());
+
+    let data = fs::read(&exe).unwrap();
+    let file = object::File::parse(&*data).unwrap();
+    let encrypt = resolve(&file, ENCRYPT_SYMBOL).unwrap();
+    let decrypt = resolve(&file, DECRYPT_SYMBOL).unwrap();
+    assert_ne!(encrypt.entry, decrypt.entry);
+    assert!(decrypt.returns.iter().all(|&ret| ret > decrypt.entry));
+
+    // the offsets point to the same code in the file
+    for ret in decrypt.returns {
+        assert_eq!(data[ret as usize], 0xc3);
+    }
+
+    fs::remove_dir_all(dir).unwrap_or_default();
+}
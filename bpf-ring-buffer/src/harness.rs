@@ -0,0 +1,303 @@
+//! Emulates the kernel side of BPF ring buffer on a memfd, so the consumer is tested without BPF.
+//! The kernel maps the data pages twice in a row, so the record crossing the end of the buffer
+//! is contiguous for the consumer, here the producer writes every byte into both copies.
+
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use proptest::prelude::*;
+
+use super::{Error, RingBuffer, RingBufferData};
+
+const HEADER_SIZE: usize = 8;
+const BUSY_BIT: u32 = 1 << 31;
+const DISCARD_BIT: u32 = 1 << 30;
+
+struct Producer {
+    _memfd: OwnedFd,
+    eventfd: OwnedFd,
+    page_size: usize,
+    max_length: usize,
+    // [consumer's page] [producer's page] [data] [data again]
+    memory: *mut u8,
+    memory_len: usize,
+    producer_pos: usize,
+}
+
+impl Producer {
+    fn new(max_length: usize) -> io::Result<(Self, RingBuffer)> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let memory_len = page_size * 2 + max_length * 2;
+
+        let name = b"ring_buffer\0";
+        let memfd = unsafe { libc::memfd_create(name.as_ptr() as _, libc::MFD_CLOEXEC) };
+        if memfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let memfd = unsafe { OwnedFd::from_raw_fd(memfd) };
+        if unsafe { libc::ftruncate(memfd.as_raw_fd(), memory_len as _) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if eventfd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let eventfd = unsafe { OwnedFd::from_raw_fd(eventfd) };
+
+        let memory = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                memory_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                memfd.as_raw_fd(),
+                0,
+            )
+        };
+        if memory == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let rb = RingBuffer::with_wakeup_fd(memfd.as_raw_fd(), eventfd.as_raw_fd(), max_length)?;
+        let producer = Producer {
+            _memfd: memfd,
+            eventfd,
+            page_size,
+            max_length,
+            memory: memory as *mut u8,
+            memory_len,
+            producer_pos: 0,
+        };
+        Ok((producer, rb))
+    }
+
+    fn position(&self, page: usize) -> &AtomicUsize {
+        unsafe { &*(self.memory.add(self.page_size * page) as *const AtomicUsize) }
+    }
+
+    fn consumer_pos(&self) -> usize {
+        self.position(0).load(Ordering::Acquire)
+    }
+
+    fn set_producer_pos(&mut self, pos: usize) {
+        self.producer_pos = pos;
+        self.position(1).store(pos, Ordering::Release);
+    }
+
+    /// Write into both copies of the data
+    fn write(&mut self, pos: usize, bytes: &[u8]) {
+        let data = unsafe {
+            slice::from_raw_parts_mut(self.memory.add(self.page_size * 2), self.max_length * 2)
+        };
+        for (i, b) in bytes.iter().enumerate() {
+            let offset = (pos + i) % self.max_length;
+            data[offset] = *b;
+            data[offset + self.max_length] = *b;
+        }
+    }
+
+    fn write_header(&mut self, pos: usize, header: u32) {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].clone_from_slice(&header.to_ne_bytes());
+        self.write(pos, &bytes);
+    }
+
+    /// Like `bpf_ringbuf_reserve`, returns the position of the record,
+    /// the consumer cannot read it until it is committed
+    fn reserve(&mut self, payload: &[u8]) -> Option<usize> {
+        let pos = self.producer_pos;
+        let new_pos = pos + HEADER_SIZE + (payload.len() + 7) / 8 * 8;
+        if new_pos - self.consumer_pos() > self.max_length {
+            return None;
+        }
+        self.write_header(pos, payload.len() as u32 | BUSY_BIT);
+        self.write(pos + HEADER_SIZE, payload);
+        self.set_producer_pos(new_pos);
+        Some(pos)
+    }
+
+    /// Like `bpf_ringbuf_submit` or `bpf_ringbuf_discard`
+    fn commit(&mut self, pos: usize, len: usize, discard: bool) {
+        let header = if discard {
+            len as u32 | DISCARD_BIT
+        } else {
+            len as u32
+        };
+        self.write_header(pos, header);
+        let one = 1_u64.to_ne_bytes();
+        unsafe { libc::write(self.eventfd.as_raw_fd(), one.as_ptr() as _, one.len()) };
+    }
+
+    /// Like `bpf_ringbuf_output`
+    fn output(&mut self, payload: &[u8], discard: bool) -> bool {
+        let Some(pos) = self.reserve(payload) else {
+            return false;
+        };
+        self.commit(pos, payload.len(), discard);
+        true
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.memory as *mut _, self.memory_len) };
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Record(Vec<u8>);
+
+impl RingBufferData for Record {
+    type Error = ();
+
+    fn from_rb_slice(slice: &[u8]) -> Result<Option<Self>, Self::Error> {
+        Ok(Some(Record(slice.to_vec())))
+    }
+}
+
+/// Content of the record is unique, so the reordering or the loss is visible
+fn record(index: usize, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (index * 31 + i) as u8).collect()
+}
+
+/// Read everything available, skipping the discarded records
+fn read_all(rb: &mut RingBuffer) -> Result<Vec<Record>, &'static str> {
+    let mut records = vec![];
+    loop {
+        match rb.read_value::<Record>() {
+            Ok((Some(record), _)) => records.push(record),
+            Ok((None, _)) => (),
+            Err(Error::WouldBlock) => break Ok(records),
+            Err(Error::Overflown) => break Err("overflown"),
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn records_in_order(
+        ops in prop::collection::vec((1..0x300_usize, any::<bool>(), 0..4_u8), 1..0x200),
+    ) {
+        let (mut producer, mut rb) = Producer::new(0x1000).unwrap();
+
+        let mut expected = vec![];
+        let mut actual = vec![];
+        for (index, (len, discard, reads)) in ops.into_iter().enumerate() {
+            let payload = record(index, len);
+            if !producer.output(&payload, discard) {
+                // full, the kernel drops the record, consume and try again
+                actual.extend(read_all(&mut rb).unwrap());
+                prop_assert!(producer.output(&payload, discard));
+            }
+            if !discard {
+                expected.push(Record(payload));
+            }
+            // interleave reads, so the consumer is sometimes behind
+            for _ in 0..reads {
+                match rb.read_value::<Record>() {
+                    Ok((Some(record), _)) => actual.push(record),
+                    Ok((None, _)) | Err(Error::WouldBlock) => (),
+                    Err(Error::Overflown) => prop_assert!(false, "overflown"),
+                }
+            }
+        }
+        actual.extend(read_all(&mut rb).unwrap());
+
+        prop_assert_eq!(actual, expected);
+        prop_assert_eq!(producer.consumer_pos(), producer.producer_pos);
+    }
+
+    #[test]
+    fn wraparound(offset in 0..0x200_usize, len in 1..0x400_usize) {
+        let (mut producer, mut rb) = Producer::new(0x1000).unwrap();
+
+        // move both positions close to the end, so the record crosses it
+        let start = 0x1000 - offset / 8 * 8;
+        producer.set_producer_pos(start);
+        rb.consumer_pos_value = start;
+        rb.read_finish();
+
+        let payload = record(0, len);
+        prop_assert!(producer.output(&payload, false));
+        prop_assert_eq!(read_all(&mut rb).unwrap(), vec![Record(payload)]);
+    }
+}
+
+#[test]
+fn busy_record_blocks() {
+    let (mut producer, mut rb) = Producer::new(0x1000).unwrap();
+
+    let first = record(0, 0x10);
+    let second = record(1, 0x20);
+    let pos = producer.reserve(&first).unwrap();
+    assert!(producer.output(&second, false));
+
+    // the first record is not committed, so the second is not visible too
+    assert!(matches!(rb.read_value::<Record>(), Err(Error::WouldBlock)));
+    producer.commit(pos, first.len(), false);
+    assert_eq!(read_all(&mut rb), Ok(vec![Record(first), Record(second)]));
+}
+
+#[test]
+fn full_buffer_rejects_and_overflow_detected() {
+    let (mut producer, mut rb) = Producer::new(0x1000).unwrap();
+
+    let payload = record(0, 0x100 - HEADER_SIZE);
+    for _ in 0..0x10 {
+        assert!(producer.output(&payload, false));
+    }
+    // the consumer did not read anything, no space
+    assert!(!producer.output(&payload, false));
+
+    // the producer went too far, the data is lost
+    producer.set_producer_pos(0x1000 + 0x100);
+    assert_eq!(read_all(&mut rb), Err("overflown"));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn stream_wakes_up_and_ends_on_overflow() {
+    use std::{future::poll_fn, pin::Pin, task::Poll};
+
+    use futures_core::Stream;
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (mut producer, rb) = Producer::new(0x1000).unwrap();
+        let mut stream = rb.into_stream::<Record>().unwrap();
+        let backpressure = stream.backpressure();
+        assert_eq!(backpressure.capacity(), 0x1000);
+
+        // nothing yet, the stream waits for the wakeup
+        let pending = poll_fn(|cx| Poll::Ready(Pin::new(&mut stream).poll_next(cx).is_pending()));
+        assert!(pending.await);
+
+        let first = record(0, 0x10);
+        let second = record(1, 0x20);
+        assert!(producer.output(&first, false));
+        assert!(producer.output(&record(2, 0x30), true));
+        assert!(producer.output(&second, false));
+
+        assert_eq!(next(&mut stream).await.unwrap().unwrap(), Record(first));
+        assert!(backpressure.remaining() > 0);
+        assert_eq!(next(&mut stream).await.unwrap().unwrap(), Record(second));
+        assert_eq!(backpressure.remaining(), 0);
+        assert!(backpressure.high_water_mark() > 0);
+
+        // the producer went too far, the stream yields the error and ends
+        producer.set_producer_pos(producer.producer_pos + 0x1100);
+        assert!(next(&mut stream).await.unwrap().is_err());
+        assert!(next(&mut stream).await.is_none());
+    });
+}
@@ -5,6 +5,14 @@ use std::{
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
+#[cfg(test)]
+mod harness;
+
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(feature = "async")]
+pub use self::stream::{Backpressure, RingBufferStream};
+
 pub trait RingBufferData
 where
     Self: Sized,
@@ -21,6 +29,8 @@ pub enum Output<D> {
 }
 
 pub struct RingBuffer {
+    // the fd which becomes readable when there is data, it is the map itself,
+    // except in the test harness
     fd: i32,
     mask: usize,
     consumer_pos_value: usize,
@@ -64,6 +74,11 @@ enum Error {
 
 impl RingBuffer {
     pub fn new(fd: i32, max_length: usize) -> io::Result<Self> {
+        Self::with_wakeup_fd(fd, fd, max_length)
+    }
+
+    /// Map the ring buffer `fd`, but wait for the data on `wakeup_fd`
+    fn with_wakeup_fd(fd: i32, wakeup_fd: i32, max_length: usize) -> io::Result<Self> {
         debug_assert_eq!(max_length & (max_length - 1), 0);
 
         // The layout is:
@@ -129,7 +144,7 @@ impl RingBuffer {
         );
         let event = epoll::Event::new(epoll::Events::EPOLLIN, 1);
         Ok(RingBuffer {
-            fd,
+            fd: wakeup_fd,
             mask: max_length - 1,
             consumer_pos_value: 0,
             observer: RingBufferObserver {
@@ -139,7 +154,7 @@ impl RingBuffer {
                 producer_pos,
                 epfd: {
                     let epfd = epoll::create(true)?;
-                    epoll::ctl(epfd, epoll::ControlOptions::EPOLL_CTL_ADD, fd, event)?;
+                    epoll::ctl(epfd, epoll::ControlOptions::EPOLL_CTL_ADD, wakeup_fd, event)?;
                     let epoll::Event { events, data } = event;
                     assert_eq!(events, epoll::Events::EPOLLIN.bits());
                     assert_eq!(data, 1);
@@ -156,9 +171,8 @@ impl RingBuffer {
         D: RingBufferData,
     {
         let (v, remaining) = self.read_slice()?;
-        if v.is_some() {
-            self.read_finish();
-        }
+        // release the slice even if it is discarded, otherwise the kernel has no space
+        self.read_finish();
         Ok((v, remaining))
     }
 
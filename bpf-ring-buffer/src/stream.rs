@@ -0,0 +1,122 @@
+use std::{
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_core::{ready, Stream};
+use tokio::io::unix::AsyncFd;
+
+use super::{Error, RingBuffer, RingBufferData};
+
+/// How far the consumer is behind the kernel, shared with the stream
+#[derive(Default)]
+pub struct Backpressure {
+    capacity: usize,
+    remaining: AtomicUsize,
+    high_water_mark: AtomicUsize,
+}
+
+impl Backpressure {
+    fn update(&self, remaining: usize) {
+        self.remaining.store(remaining, Ordering::Relaxed);
+        let previous = self.high_water_mark.fetch_max(remaining, Ordering::Relaxed);
+        if remaining > previous {
+            log::debug!("ring buffer high water mark: {remaining}");
+        }
+    }
+
+    /// Size of the ring buffer in bytes
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many bytes are remaining in the ring buffer after the latest item
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Relaxed)
+    }
+
+    /// The maximal `remaining` ever observed
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark.load(Ordering::Relaxed)
+    }
+}
+
+/// Yields the items of the ring buffer as they come, the fd is registered in the tokio reactor.
+/// The stream yields the error and ends if the ring buffer is overflown.
+pub struct RingBufferStream<D> {
+    inner: AsyncFd<RingBuffer>,
+    backpressure: Arc<Backpressure>,
+    overflown: bool,
+    phantom_data: PhantomData<fn() -> D>,
+}
+
+impl RingBuffer {
+    /// Must be called within the tokio runtime
+    pub fn into_stream<D>(self) -> io::Result<RingBufferStream<D>>
+    where
+        D: RingBufferData,
+    {
+        let backpressure = Arc::new(Backpressure {
+            capacity: self.mask + 1,
+            ..Default::default()
+        });
+        // the newer tokio has unsafe `AsyncFd::register` instead, keep working with 1.22
+        #[allow(deprecated)]
+        let inner = AsyncFd::new(self)?;
+        Ok(RingBufferStream {
+            inner,
+            backpressure,
+            overflown: false,
+            phantom_data: PhantomData,
+        })
+    }
+}
+
+impl<D> RingBufferStream<D> {
+    pub fn backpressure(&self) -> Arc<Backpressure> {
+        self.backpressure.clone()
+    }
+
+    pub fn into_inner(self) -> RingBuffer {
+        self.inner.into_inner()
+    }
+}
+
+impl<D> Stream for RingBufferStream<D>
+where
+    D: RingBufferData,
+{
+    type Item = io::Result<D>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // all fields are `Unpin`, `D` is only produced
+        let this = self.get_mut();
+        if this.overflown {
+            return Poll::Ready(None);
+        }
+        loop {
+            let mut guard = ready!(this.inner.poll_read_ready_mut(cx))?;
+            match guard.get_inner_mut().read_value::<D>() {
+                Ok((Some(value), remaining)) => {
+                    this.backpressure.update(remaining);
+                    return Poll::Ready(Some(Ok(value)));
+                }
+                // discarded or cannot parse, try next
+                Ok((None, remaining)) => this.backpressure.update(remaining),
+                // the consumer caught up, so the kernel wakes it up when submits the next item
+                Err(Error::WouldBlock) => guard.clear_ready(),
+                Err(Error::Overflown) => {
+                    this.overflown = true;
+                    let err = io::Error::new(io::ErrorKind::Other, "overflow");
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+        }
+    }
+}
@@ -15,6 +15,7 @@ fn main() {
             "src/decode/structs.proto",
             "src/decode/envelope.proto",
             "src/decode/identify.proto",
+            "src/decode/bitswap.proto",
         ],
         &["src/decode"],
     )
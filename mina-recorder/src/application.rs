@@ -1,13 +1,17 @@
 use std::{
-    env,
-    sync::{mpsc, Mutex, Arc},
+    env, fmt, fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex, Arc,
+    },
     collections::BTreeMap,
     net::{IpAddr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
 };
 
 use ebpf_user::{
     kind::{AppItem, AppItemKind},
-    HashMapRef,
+    ArrayPerCpuRef, HashMapRef,
 };
 
 use serde::{Serialize, Deserialize};
@@ -18,10 +22,43 @@ pub struct StatsBlocked {
     pub bytes: u32,
 }
 
+/// Which rule of the firewall dropped the packet, see `bpf_recorder::FirewallRule`
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FirewallRule {
+    Whitelist,
+    Blacklist,
+    RateLimit,
+    PacketLoss,
+}
+
+impl FirewallRule {
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(FirewallRule::Whitelist),
+            1 => Some(FirewallRule::Blacklist),
+            2 => Some(FirewallRule::RateLimit),
+            3 => Some(FirewallRule::PacketLoss),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StatsItem {
     pub src: SocketAddr,
     pub dst: SocketAddr,
+    pub rule: FirewallRule,
+}
+
+/// How well the events of the ring buffers are merged, the events which are still out of order
+/// after the merge came later than the reorder window
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReorderStats {
+    pub released: u64,
+    pub max_buffered: usize,
+    pub disordered: u64,
+    pub max_disorder_ns: u64,
 }
 
 #[derive(Deserialize)]
@@ -30,10 +67,144 @@ pub struct EnableWhitelist {
     pub ports: Vec<u16>,
 }
 
+/// The packets from these ips are dropped, the empty list disables the blacklist
+#[derive(Deserialize)]
+pub struct Blacklist {
+    pub ips: Vec<IpAddr>,
+}
+
+/// Token bucket per source ip, the packet which does not fit in the bucket is dropped
+#[derive(Clone, Debug, Deserialize)]
+pub struct RateLimit {
+    pub ip: IpAddr,
+    pub bytes_per_second: u32,
+    /// Capacity of the bucket, must fit the largest packet
+    pub burst: u32,
+}
+
+/// Drop the percent of packets from the ip, to or from the port,
+/// absent ip or port matches any
+#[derive(Clone, Debug, Deserialize)]
+pub struct PacketLoss {
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub percent: u8,
+}
+
+const RATE_LIMIT_MAX: usize = 0x400;
+const PACKET_LOSS_MAX: usize = 0x400;
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+    .octets()
+}
+
+fn socket_addr(b: &[u8]) -> SocketAddr {
+    let ip = Ipv6Addr::from(<[u8; 16]>::try_from(&b[0..16]).unwrap());
+    let ip = ip.to_ipv4_mapped().map(IpAddr::from).unwrap_or(ip.into());
+    let port = u16::from_be_bytes(b[16..18].try_into().unwrap());
+    SocketAddr::new(ip, port)
+}
+
+/// Size of the kernel map `drops`, the index is the tag
+const DROPS_TAGS: u32 = 0x20;
+
+const DROPS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits of the kernel maps `match_names` and `match_env`,
+/// the value is the length byte followed by the string
+const MATCH_NAMES_MAX: usize = 0x8;
+const MATCH_PATTERN_SIZE: usize = 0x20;
+
+/// Which processes should be tracked
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessMatching {
+    /// Prefixes of the first command line argument
+    pub names: Vec<String>,
+    /// Environment variable which holds the alias of the process
+    pub env_flag: String,
+    /// Processes tracked regardless of their name
+    pub pids: Vec<u32>,
+    /// Every process of these cgroups is tracked
+    pub cgroups: Vec<u64>,
+}
+
+impl Default for ProcessMatching {
+    fn default() -> Self {
+        ProcessMatching {
+            names: vec!["coda-libp2p_helper".to_owned(), "openmina".to_owned()],
+            env_flag: "BPF_ALIAS".to_owned(),
+            pids: vec![],
+            cgroups: vec![],
+        }
+    }
+}
+
+impl ProcessMatching {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.names.len() > MATCH_NAMES_MAX {
+            return Err(format!("at most {MATCH_NAMES_MAX} names allowed"));
+        }
+        for name in &self.names {
+            if name.is_empty() || name.len() >= MATCH_PATTERN_SIZE {
+                return Err(format!(
+                    "name {name:?} must be from 1 to {} bytes",
+                    MATCH_PATTERN_SIZE - 1
+                ));
+            }
+        }
+        // the kernel compares the name and `=` after it
+        if self.env_flag.is_empty()
+            || self.env_flag.len() >= MATCH_PATTERN_SIZE - 1
+            || self.env_flag.contains('=')
+        {
+            return Err(format!(
+                "env flag {:?} must be from 1 to {} bytes without `=`",
+                self.env_flag,
+                MATCH_PATTERN_SIZE - 2
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The kernel map cannot be updated, the error is reported to the caller of the command
+fn map_error<E: fmt::Debug>(name: &'static str) -> impl Fn(E) -> String {
+    move |err| format!("cannot update kernel map `{name}`: {err:?}")
+}
+
+fn pattern(s: &str) -> [u8; MATCH_PATTERN_SIZE] {
+    let mut value = [0; MATCH_PATTERN_SIZE];
+    value[0] = s.len() as u8;
+    value[1..(1 + s.len())].clone_from_slice(s.as_bytes());
+    value
+}
+
+/// The value of the environment variable `name` of the process
+fn read_env(pid: u32, name: &str) -> Option<String> {
+    let environ = fs::read(format!("/proc/{pid}/environ")).ok()?;
+    environ.split(|c| *c == 0).find_map(|entry| {
+        let value = entry.strip_prefix(name.as_bytes())?.strip_prefix(b"=")?;
+        String::from_utf8(value.to_vec()).ok()
+    })
+}
+
 enum ApplicationCommand {
     EnableWhitelist(EnableWhitelist),
     DisableWhitelist,
+    SetBlacklist(Vec<IpAddr>),
+    SetRateLimits(Vec<RateLimit>),
+    SetPacketLoss(Vec<PacketLoss>),
     GetFirewallStats,
+    GetCaptureDrops,
+    SetProcessMatching(ProcessMatching),
+    GetProcessMatching,
+    TrackConnections(u32, Vec<u32>),
     Terminate,
 }
 
@@ -41,28 +212,95 @@ enum ApplicationCommand {
 pub struct Application {
     ctx: mpsc::SyncSender<ApplicationCommand>,
     drx: Arc<Mutex<mpsc::Receiver<BTreeMap<StatsItem, StatsBlocked>>>>,
+    rrx: Arc<Mutex<mpsc::Receiver<BTreeMap<String, u64>>>>,
+    lrx: Arc<Mutex<mpsc::Receiver<(u32, u32)>>>,
+    // set when `lrx` has something, so the receiver is not locked for every event
+    lost: Arc<AtomicBool>,
+    mrx: Arc<Mutex<mpsc::Receiver<ProcessMatching>>>,
+    // the result of the command which updates the kernel maps
+    erx: Arc<Mutex<mpsc::Receiver<Result<(), String>>>>,
+    arx: Arc<Mutex<mpsc::Receiver<(u32, String)>>>,
+    // updated by the thread which merges the ring buffers, not by the server
+    reorder: Arc<Mutex<ReorderStats>>,
 }
 
 /// It is !Send, so will block thread where created
 pub struct ApplicationServer {
     whitelist: HashMapRef<16, 4>,
     whitelist_ports: HashMapRef<2, 4>,
-    blocked: HashMapRef<36, 8>,
+    blacklist: HashMapRef<16, 4>,
+    rate_limit: HashMapRef<16, 16>,
+    packet_loss: HashMapRef<18, 4>,
+    blocked: HashMapRef<37, 8>,
+    tag_name: fn(u32) -> Option<&'static str>,
+    drops: ArrayPerCpuRef<8>,
+    drops_connections: HashMapRef<8, 4>,
+    drops_total: BTreeMap<String, u64>,
+    pid: HashMapRef<4, 4>,
+    match_names: HashMapRef<4, MATCH_PATTERN_SIZE>,
+    match_env: HashMapRef<4, MATCH_PATTERN_SIZE>,
+    match_cgroups: HashMapRef<8, 4>,
+    process_matching: ProcessMatching,
+    connections: HashMapRef<8, 4>,
     crx: mpsc::Receiver<ApplicationCommand>,
     dtx: mpsc::Sender<BTreeMap<StatsItem, StatsBlocked>>,
+    rtx: mpsc::Sender<BTreeMap<String, u64>>,
+    ltx: mpsc::Sender<(u32, u32)>,
+    lost: Arc<AtomicBool>,
+    mtx: mpsc::Sender<ProcessMatching>,
+    etx: mpsc::Sender<Result<(), String>>,
+    atx: mpsc::Sender<(u32, String)>,
 }
 
 impl Application {
-    pub fn enable_firewall(&self, list: EnableWhitelist) {
-        self.ctx
-            .send(ApplicationCommand::EnableWhitelist(list))
-            .unwrap_or_default();
+    /// Send the command which updates the kernel maps and wait for the result
+    fn update(&self, command: ApplicationCommand) -> Result<(), String> {
+        let erx = self
+            .erx
+            .lock()
+            .expect("must not panic while hold this lock");
+        self.ctx.send(command).unwrap_or_default();
+        erx.recv()
+            .unwrap_or_else(|_| Err("the application is terminated".to_owned()))
     }
 
-    pub fn disable_firewall(&self) {
-        self.ctx
-            .send(ApplicationCommand::DisableWhitelist)
-            .unwrap_or_default();
+    pub fn enable_firewall(&self, list: EnableWhitelist) -> Result<(), String> {
+        self.update(ApplicationCommand::EnableWhitelist(list))
+    }
+
+    pub fn disable_firewall(&self) -> Result<(), String> {
+        self.update(ApplicationCommand::DisableWhitelist)
+    }
+
+    pub fn set_firewall_blacklist(&self, list: Blacklist) -> Result<(), String> {
+        self.update(ApplicationCommand::SetBlacklist(list.ips))
+    }
+
+    /// Replace the rate limits, the empty list disables rate limiting
+    pub fn set_firewall_rate_limits(&self, limits: Vec<RateLimit>) -> Result<(), String> {
+        if limits.len() > RATE_LIMIT_MAX {
+            return Err(format!("at most {RATE_LIMIT_MAX} rate limits allowed"));
+        }
+        if let Some(limit) = limits
+            .iter()
+            .find(|l| l.bytes_per_second == 0 || l.burst == 0)
+        {
+            return Err(format!("rate and burst of {} must be positive", limit.ip));
+        }
+        self.update(ApplicationCommand::SetRateLimits(limits))
+    }
+
+    /// Replace the packet loss rules, the empty list disables packet loss
+    pub fn set_firewall_packet_loss(&self, rules: Vec<PacketLoss>) -> Result<(), String> {
+        if rules.len() > PACKET_LOSS_MAX {
+            return Err(format!(
+                "at most {PACKET_LOSS_MAX} packet loss rules allowed"
+            ));
+        }
+        if let Some(rule) = rules.iter().find(|r| r.percent > 100) {
+            return Err(format!("percent {} is greater than 100", rule.percent));
+        }
+        self.update(ApplicationCommand::SetPacketLoss(rules))
     }
 
     pub fn get_firewall_stats(&self) -> BTreeMap<StatsItem, StatsBlocked> {
@@ -76,6 +314,78 @@ impl Application {
         drx.recv().unwrap_or_default()
     }
 
+    /// Number of events lost in the kernel because the ring buffer was full, by tag
+    pub fn get_capture_drops(&self) -> BTreeMap<String, u64> {
+        let rrx = self
+            .rrx
+            .lock()
+            .expect("must not panic while hold this lock");
+        self.ctx
+            .send(ApplicationCommand::GetCaptureDrops)
+            .unwrap_or_default();
+        rrx.recv().unwrap_or_default()
+    }
+
+    /// Connections `(pid, fd)` which lost some data since the previous call
+    pub fn capture_lost(&self) -> Vec<(u32, u32)> {
+        if !self.lost.swap(false, Ordering::Acquire) {
+            return vec![];
+        }
+        let lrx = self
+            .lrx
+            .lock()
+            .expect("must not panic while hold this lock");
+        lrx.try_iter().collect()
+    }
+
+    pub fn set_reorder_stats(&self, stats: &ReorderStats) {
+        self.reorder
+            .lock()
+            .expect("must not panic while hold this lock")
+            .clone_from(stats);
+    }
+
+    pub fn get_reorder_stats(&self) -> ReorderStats {
+        self.reorder
+            .lock()
+            .expect("must not panic while hold this lock")
+            .clone()
+    }
+
+    pub fn set_process_matching(&self, matching: ProcessMatching) -> Result<(), String> {
+        matching.validate()?;
+        self.update(ApplicationCommand::SetProcessMatching(matching))
+    }
+
+    pub fn get_process_matching(&self) -> ProcessMatching {
+        let mrx = self
+            .mrx
+            .lock()
+            .expect("must not panic while hold this lock");
+        self.ctx
+            .send(ApplicationCommand::GetProcessMatching)
+            .unwrap_or_default();
+        mrx.recv().unwrap_or_default()
+    }
+
+    /// Processes `(pid, alias)` added by pid since the previous call,
+    /// the kernel reports nothing about them
+    pub fn new_apps(&self) -> Vec<(u32, String)> {
+        let arx = self
+            .arx
+            .lock()
+            .expect("must not panic while hold this lock");
+        arx.try_iter().collect()
+    }
+
+    /// Let the kernel report the data of the sockets `fds` of the process `pid`,
+    /// the sockets were opened before the process was tracked
+    pub fn track_connections(&self, pid: u32, fds: Vec<u32>) {
+        self.ctx
+            .send(ApplicationCommand::TrackConnections(pid, fds))
+            .unwrap_or_default();
+    }
+
     pub fn terminate(&self) {
         self.ctx
             .send(ApplicationCommand::Terminate)
@@ -83,35 +393,156 @@ impl Application {
     }
 }
 
+fn clear_map<const K: usize, const V: usize>(map: &HashMapRef<K, V>) {
+    let fd = match map.kind() {
+        AppItemKind::Map(map) => map.fd(),
+        _ => unreachable!(),
+    };
+
+    // collect first, removing the current key breaks the iteration
+    let mut keys = vec![];
+    let mut it = std::ptr::null();
+    let mut next_key = [0; K];
+    while unsafe { libbpf_sys::bpf_map_get_next_key(fd, it, next_key.as_mut_ptr() as _) } == 0 {
+        keys.push(next_key);
+        it = &next_key as *const _ as _;
+    }
+    for key in keys {
+        map.remove(&key).unwrap_or_default();
+    }
+}
+
 impl ApplicationServer {
     fn clear_whitelist(&self) {
-        let fd = match self.whitelist.kind() {
-            AppItemKind::Map(map) => map.fd(),
-            _ => unreachable!(),
-        };
+        clear_map(&self.whitelist);
+        clear_map(&self.whitelist_ports);
+    }
 
-        // remove all entries
-        let mut key = std::ptr::null();
-        let mut next_key = [0; 16];
-        while unsafe { libbpf_sys::bpf_map_get_next_key(fd, key, next_key.as_mut_ptr() as _) } == 0
-        {
-            self.whitelist.remove(&next_key).unwrap();
-            key = &next_key as *const _ as _;
+    fn enable_whitelist(&self, mut ips: Vec<IpAddr>, ports: Vec<u16>) -> Result<(), String> {
+        self.clear_whitelist();
+
+        // remove mark that whitelist is disabled
+        self.whitelist.remove(&[0; 16]).unwrap_or_default();
+
+        if let Ok(list) = env::var("FIREWALL_DEFAULT_WHITELIST") {
+            for ip in list.split(',').filter_map(|s| s.parse::<IpAddr>().ok()) {
+                ips.push(ip);
+            }
+        }
+        for &addr in &ips {
+            self.whitelist
+                .insert(ipv6_octets(addr), [0, 0, 0, 1])
+                .map_err(map_error("whitelist"))?;
         }
+        for &port in &ports {
+            self.whitelist_ports
+                .insert(port.to_be_bytes(), [0, 0, 0, 1])
+                .map_err(map_error("whitelist_ports"))?;
+        }
+        log::info!("firewall: whitelist {ips:?}, ports: {ports:?}");
 
-        let fd = match self.whitelist_ports.kind() {
-            AppItemKind::Map(map) => map.fd(),
-            _ => unreachable!(),
-        };
+        Ok(())
+    }
 
-        // remove all entries
-        let mut key = std::ptr::null();
-        let mut next_key = [0; 2];
-        while unsafe { libbpf_sys::bpf_map_get_next_key(fd, key, next_key.as_mut_ptr() as _) } == 0
-        {
-            self.whitelist_ports.remove(&next_key).unwrap();
-            key = &next_key as *const _ as _;
+    fn disable_whitelist(&self) -> Result<(), String> {
+        self.clear_whitelist();
+
+        // insert mark that whitelist is disabled
+        self.whitelist
+            .insert([0; 16], [0, 0, 0, 1])
+            .map_err(map_error("whitelist"))?;
+
+        log::info!("firewall: whitelist disable");
+
+        Ok(())
+    }
+
+    fn set_blacklist(&self, ips: Vec<IpAddr>) -> Result<(), String> {
+        clear_map(&self.blacklist);
+        for &ip in &ips {
+            self.blacklist
+                .insert(ipv6_octets(ip), [0, 0, 0, 1])
+                .map_err(map_error("blacklist"))?;
+        }
+        log::info!("firewall: blacklist {ips:?}");
+
+        Ok(())
+    }
+
+    fn set_rate_limits(&self, limits: Vec<RateLimit>) -> Result<(), String> {
+        clear_map(&self.rate_limit);
+        for limit in &limits {
+            // see `bpf_recorder::RateLimit`, zero time means the bucket is full
+            let mut value = [0; 16];
+            value[0..4].clone_from_slice(&limit.bytes_per_second.to_ne_bytes());
+            value[4..8].clone_from_slice(&limit.burst.to_ne_bytes());
+            self.rate_limit
+                .insert(ipv6_octets(limit.ip), value)
+                .map_err(map_error("rate_limit"))?;
         }
+        log::info!("firewall: rate limits {limits:?}");
+
+        Ok(())
+    }
+
+    fn set_packet_loss(&self, rules: Vec<PacketLoss>) -> Result<(), String> {
+        clear_map(&self.packet_loss);
+        for rule in &rules {
+            let mut key = [0; 18];
+            if let Some(ip) = rule.ip {
+                key[0..16].clone_from_slice(&ipv6_octets(ip));
+            }
+            key[16..].clone_from_slice(&rule.port.unwrap_or(0).to_be_bytes());
+            self.packet_loss
+                .insert(key, (rule.percent as u32).to_ne_bytes())
+                .map_err(map_error("packet_loss"))?;
+        }
+        log::info!("firewall: packet loss {rules:?}");
+
+        Ok(())
+    }
+
+    fn set_process_matching(&mut self, matching: ProcessMatching) -> Result<(), String> {
+        for i in 0..MATCH_NAMES_MAX {
+            let key = (i as u32).to_ne_bytes();
+            match matching.names.get(i) {
+                Some(name) => self
+                    .match_names
+                    .insert(key, pattern(name))
+                    .map_err(map_error("match_names"))?,
+                None => self.match_names.remove(&key).unwrap_or_default(),
+            }
+        }
+        self.match_env
+            .insert(0_u32.to_ne_bytes(), pattern(&matching.env_flag))
+            .map_err(map_error("match_env"))?;
+
+        clear_map(&self.match_cgroups);
+        for &cgroup in &matching.cgroups {
+            self.match_cgroups
+                .insert(cgroup.to_ne_bytes(), [0, 0, 0, 1])
+                .map_err(map_error("match_cgroups"))?;
+        }
+
+        for pid in &self.process_matching.pids {
+            if !matching.pids.contains(pid) {
+                self.pid.remove(&pid.to_ne_bytes()).unwrap_or_default();
+            }
+        }
+        for &pid in &matching.pids {
+            if !self.process_matching.pids.contains(&pid) {
+                self.pid
+                    .insert(pid.to_ne_bytes(), 0x_ffff_ffff_u32.to_ne_bytes())
+                    .map_err(map_error("pid"))?;
+                let alias = read_env(pid, &matching.env_flag).unwrap_or_default();
+                self.atx.send((pid, alias)).unwrap_or_default();
+            }
+        }
+
+        log::info!("process matching: {matching:?}");
+        self.process_matching = matching;
+
+        Ok(())
     }
 
     fn list(&self) -> BTreeMap<StatsItem, StatsBlocked> {
@@ -122,27 +553,18 @@ impl ApplicationServer {
             _ => unreachable!(),
         };
         let mut it = std::ptr::null();
-        let mut next_key = [0; 36];
+        let mut next_key = [0; 37];
         while unsafe { libbpf_sys::bpf_map_get_next_key(fd, it, next_key.as_mut_ptr() as _) } == 0 {
             let value = self.blocked.get(&next_key).unwrap();
+            it = &next_key as *const _ as _;
 
-            let src_ip = Ipv6Addr::from(<[u8; 16]>::try_from(&next_key[0..16]).unwrap());
-            let src_ip = src_ip
-                .to_ipv4_mapped()
-                .map(IpAddr::from)
-                .unwrap_or(src_ip.into());
-            let src_port = u16::from_be_bytes(next_key[16..18].try_into().unwrap());
-
-            let dst_ip = Ipv6Addr::from(<[u8; 16]>::try_from(&next_key[18..34]).unwrap());
-            let dst_ip = dst_ip
-                .to_ipv4_mapped()
-                .map(IpAddr::from)
-                .unwrap_or(src_ip.into());
-            let dst_port = u16::from_be_bytes(next_key[34..36].try_into().unwrap());
-
+            let Some(rule) = FirewallRule::from_tag(next_key[36]) else {
+                continue;
+            };
             let key = StatsItem {
-                src: SocketAddr::new(src_ip, src_port),
-                dst: SocketAddr::new(dst_ip, dst_port),
+                src: socket_addr(&next_key[0..18]),
+                dst: socket_addr(&next_key[18..36]),
+                rule,
             };
             let value = StatsBlocked {
                 packets: u32::from_ne_bytes(value[..4].try_into().unwrap()),
@@ -150,51 +572,139 @@ impl ApplicationServer {
             };
 
             list.insert(key, value);
-            it = &next_key as *const _ as _;
         }
 
         list
     }
 
-    pub fn run(mut self) {
-        while let Ok(command) = self.crx.recv() {
-            match command {
-                ApplicationCommand::EnableWhitelist(EnableWhitelist { mut ips, ports }) => {
-                    self.clear_whitelist();
+    fn list_drops(&self) -> BTreeMap<String, u64> {
+        let mut list = BTreeMap::new();
 
-                    // remove mark that whitelist is disabled
-                    self.whitelist.remove(&[0; 16]).unwrap_or_default();
+        let fd = match self.drops.kind() {
+            AppItemKind::Map(map) => map.fd(),
+            _ => unreachable!(),
+        };
+        // the lookup of the per cpu map yields the value of each possible cpu
+        let cpus = unsafe { libbpf_sys::libbpf_num_possible_cpus() }.max(1) as usize;
+        let mut values = vec![0_u64; cpus];
+        for tag in 0..DROPS_TAGS {
+            let key = &tag as *const u32 as _;
+            if unsafe { libbpf_sys::bpf_map_lookup_elem(fd, key, values.as_mut_ptr() as _) } != 0 {
+                continue;
+            }
+            let count = values.iter().sum::<u64>();
+            if count != 0 {
+                let name = (self.tag_name)(tag)
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| tag.to_string());
+                list.insert(name, count);
+            }
+        }
 
-                    if let Ok(list) = env::var("FIREWALL_DEFAULT_WHITELIST") {
-                        for ip in list.split(',').filter_map(|s| s.parse::<IpAddr>().ok()) {
-                            ips.push(ip);
-                        }
-                    }
-                    for &addr in &ips {
-                        let ipv6 = match addr {
-                            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
-                            IpAddr::V6(ip) => ip,
-                        };
-                        self.whitelist.insert(ipv6.octets(), [0, 0, 0, 1]).unwrap();
-                    }
-                    for &port in &ports {
-                        self.whitelist_ports
-                            .insert(port.to_be_bytes(), [0, 0, 0, 1])
-                            .unwrap();
-                    }
-                    log::info!("firewall: whitelist {ips:?}, ports: {ports:?}");
-                }
-                ApplicationCommand::DisableWhitelist => {
-                    self.clear_whitelist();
+        list
+    }
 
-                    // insert mark that whitelist is disabled
-                    self.whitelist.insert([0; 16], [0, 0, 0, 1]).unwrap();
+    fn poll_drops(&mut self) {
+        let list = self.list_drops();
+        for (name, &count) in &list {
+            let previous = self.drops_total.get(name).cloned().unwrap_or_default();
+            if count > previous {
+                log::warn!(
+                    "ring buffer overflow, lost {} `{name}` events, total: {count}",
+                    count - previous
+                );
+            }
+        }
+        self.drops_total = list;
 
-                    log::info!("firewall: whitelist disable");
+        let fd = match self.drops_connections.kind() {
+            AppItemKind::Map(map) => map.fd(),
+            _ => unreachable!(),
+        };
+        let mut lost = vec![];
+        let mut it = std::ptr::null();
+        let mut next_key = [0; 8];
+        while unsafe { libbpf_sys::bpf_map_get_next_key(fd, it, next_key.as_mut_ptr() as _) } == 0 {
+            lost.push(next_key);
+            it = &next_key as *const _ as _;
+        }
+        for key in lost {
+            self.drops_connections.remove(&key).unwrap_or_default();
+            // socket id is `(fd << 32) + pid`
+            let socket_id = u64::from_ne_bytes(key);
+            let pid = (socket_id & 0xffffffff) as u32;
+            let fd = (socket_id >> 32) as u32;
+            self.ltx.send((pid, fd)).unwrap_or_default();
+            self.lost.store(true, Ordering::Release);
+        }
+    }
+
+    fn reply(&self, result: Result<(), String>) {
+        if let Err(err) = &result {
+            log::error!("{err}");
+        }
+        self.etx.send(result).unwrap_or_default();
+    }
+
+    pub fn run(mut self) {
+        // the deadline is not reset by commands, so they cannot starve the polling
+        let mut poll_deadline = Instant::now() + DROPS_POLL_INTERVAL;
+        loop {
+            let now = Instant::now();
+            if now >= poll_deadline {
+                self.poll_drops();
+                poll_deadline = now + DROPS_POLL_INTERVAL;
+            }
+            let command = match self.crx.recv_timeout(poll_deadline - now) {
+                Ok(v) => v,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            match command {
+                ApplicationCommand::EnableWhitelist(EnableWhitelist { ips, ports }) => {
+                    let result = self.enable_whitelist(ips, ports);
+                    self.reply(result);
+                }
+                ApplicationCommand::DisableWhitelist => {
+                    let result = self.disable_whitelist();
+                    self.reply(result);
+                }
+                ApplicationCommand::SetBlacklist(ips) => {
+                    let result = self.set_blacklist(ips);
+                    self.reply(result);
+                }
+                ApplicationCommand::SetRateLimits(limits) => {
+                    let result = self.set_rate_limits(limits);
+                    self.reply(result);
+                }
+                ApplicationCommand::SetPacketLoss(rules) => {
+                    let result = self.set_packet_loss(rules);
+                    self.reply(result);
                 }
                 ApplicationCommand::GetFirewallStats => {
                     self.dtx.send(self.list()).unwrap_or_default();
                 }
+                ApplicationCommand::GetCaptureDrops => {
+                    self.rtx.send(self.list_drops()).unwrap_or_default();
+                }
+                ApplicationCommand::SetProcessMatching(matching) => {
+                    let result = self.set_process_matching(matching);
+                    self.reply(result);
+                }
+                ApplicationCommand::GetProcessMatching => {
+                    self.mtx
+                        .send(self.process_matching.clone())
+                        .unwrap_or_default();
+                }
+                ApplicationCommand::TrackConnections(pid, fds) => {
+                    for fd in fds {
+                        // socket id is `(fd << 32) + pid`
+                        let socket_id = ((fd as u64) << 32) + (pid as u64);
+                        self.connections
+                            .insert(socket_id.to_ne_bytes(), 0x1_u32.to_ne_bytes())
+                            .unwrap_or_default();
+                    }
+                }
                 ApplicationCommand::Terminate => break,
             }
         }
@@ -202,22 +712,80 @@ impl ApplicationServer {
 }
 
 pub fn new(
+    tag_name: fn(u32) -> Option<&'static str>,
     whitelist: HashMapRef<16, 4>,
     whitelist_ports: HashMapRef<2, 4>,
-    blocked: HashMapRef<36, 8>,
+    blacklist: HashMapRef<16, 4>,
+    rate_limit: HashMapRef<16, 16>,
+    packet_loss: HashMapRef<18, 4>,
+    blocked: HashMapRef<37, 8>,
+    drops: ArrayPerCpuRef<8>,
+    drops_connections: HashMapRef<8, 4>,
+    pid: HashMapRef<4, 4>,
+    match_names: HashMapRef<4, MATCH_PATTERN_SIZE>,
+    match_env: HashMapRef<4, MATCH_PATTERN_SIZE>,
+    match_cgroups: HashMapRef<8, 4>,
+    process_matching: ProcessMatching,
+    connections: HashMapRef<8, 4>,
 ) -> (Application, ApplicationServer) {
     let (ctx, crx) = mpsc::sync_channel(256);
     let (dtx, drx) = mpsc::channel();
     let drx = Arc::new(Mutex::new(drx));
+    let (rtx, rrx) = mpsc::channel();
+    let rrx = Arc::new(Mutex::new(rrx));
+    let (ltx, lrx) = mpsc::channel();
+    let lrx = Arc::new(Mutex::new(lrx));
+    let lost = Arc::new(AtomicBool::new(false));
+    let (mtx, mrx) = mpsc::channel();
+    let mrx = Arc::new(Mutex::new(mrx));
+    let (etx, erx) = mpsc::channel();
+    let erx = Arc::new(Mutex::new(erx));
+    let (atx, arx) = mpsc::channel();
+    let arx = Arc::new(Mutex::new(arx));
+
+    let mut server = ApplicationServer {
+        whitelist,
+        whitelist_ports,
+        blacklist,
+        rate_limit,
+        packet_loss,
+        blocked,
+        tag_name,
+        drops,
+        drops_connections,
+        drops_total: BTreeMap::new(),
+        pid,
+        match_names,
+        match_env,
+        match_cgroups,
+        process_matching: ProcessMatching::default(),
+        connections,
+        crx,
+        dtx,
+        rtx,
+        ltx,
+        lost: lost.clone(),
+        mtx,
+        etx,
+        atx,
+    };
+    // the kernel matches nothing until the maps are filled
+    if let Err(err) = server.set_process_matching(process_matching) {
+        log::error!("{err}");
+    }
 
     (
-        Application { ctx, drx },
-        ApplicationServer {
-            whitelist,
-            whitelist_ports,
-            blocked,
-            crx,
-            dtx,
+        Application {
+            ctx,
+            drx,
+            rrx,
+            lrx,
+            lost,
+            mrx,
+            erx,
+            arx,
+            reorder: Arc::new(Mutex::new(ReorderStats::default())),
         },
+        server,
     )
 }
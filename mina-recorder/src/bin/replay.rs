@@ -12,7 +12,7 @@ fn main() {
 
     let db = DbFacade::open("target/replay_db").unwrap();
 
-    let mut recorder = P2pRecorder::new(db, false);
+    let mut recorder = P2pRecorder::new(db, false, None);
     let metadata = EventMetadata::default();
     recorder.on_alias(metadata.id.pid, "mainnet-node".to_owned());
     recorder.on_connect::<true>(true, metadata, 0, String::new());
@@ -1,7 +1,8 @@
 use super::accumulator;
 
 mod meshsub;
-mod rpc;
+pub mod rpc;
+mod node_status;
 
 use crate::{
     database::{StreamId, StreamKind, ConnectionStats, DbStream},
@@ -15,6 +16,11 @@ pub struct State {
     kind: StreamKind,
     rpc_state: Option<rpc::State>,
     meshsub_state: Option<meshsub::State>,
+    node_status_state: Option<node_status::State>,
+    // the same framing as node status, the responder writes a JSON
+    peer_exchange_state: Option<node_status::State>,
+    // the same framing as meshsub, length delimited protobuf
+    bitswap_state: Option<meshsub::State>,
 }
 
 impl DynamicProtocol for State {
@@ -37,6 +43,27 @@ impl DynamicProtocol for State {
                     None
                 }
             },
+            node_status_state: {
+                if let StreamKind::NodeStatus = kind {
+                    Some(node_status::State::default())
+                } else {
+                    None
+                }
+            },
+            peer_exchange_state: {
+                if let StreamKind::PeerExchange = kind {
+                    Some(node_status::State::default())
+                } else {
+                    None
+                }
+            },
+            bitswap_state: {
+                if let StreamKind::BitswapExchange = kind {
+                    Some(meshsub::State::default())
+                } else {
+                    None
+                }
+            },
         }
     }
 }
@@ -53,18 +80,12 @@ impl HandleData for State {
                     match st.next_msg() {
                         Err(err) => log::error!("{id} {}: {err}", db.id()),
                         Ok(None) => break,
-                        Ok(Some(msg)) => {
-                            if let Err(err) = stream.add(&id, self.kind, &msg) {
-                                log::error!("{id} {}: {err}", db.id());
-                            }
+                        Ok(Some((msg, call))) => {
+                            rpc_sink(&id, db, &stream, self.kind, st, &msg, call)
                         }
                     }
                 },
-                Ok(Some(msg)) => {
-                    if let Err(err) = stream.add(&id, self.kind, &msg) {
-                        log::error!("{id} {}: {err}, {}", db.id(), hex::encode(bytes));
-                    }
-                }
+                Ok(Some((msg, call))) => rpc_sink(&id, db, &stream, self.kind, st, &msg, call),
             }
         } else if self.kind == StreamKind::Meshsub {
             let st = self.meshsub_state.as_mut().expect("must exist");
@@ -75,6 +96,33 @@ impl HandleData for State {
                     meshsub_sink(&id, db, &stream, slice, cx);
                 }
             }
+        } else if self.kind == StreamKind::NodeStatus {
+            let st = self.node_status_state.as_mut().expect("must exist");
+            if let Some(msg) = st.extend(id.incoming, bytes) {
+                if let Err(err) = stream.add(&id, self.kind, &msg) {
+                    log::error!("{id} {}: {err}", db.id());
+                }
+            }
+        } else if self.kind == StreamKind::PeerExchange {
+            let st = self.peer_exchange_state.as_mut().expect("must exist");
+            if let Some(msg) = st.extend(id.incoming, bytes) {
+                if let Err(err) = stream.add(&id, self.kind, &msg) {
+                    log::error!("{id} {}: {err}", db.id());
+                }
+            }
+        } else if self.kind == StreamKind::BitswapExchange {
+            let st = self.bitswap_state.as_mut().expect("must exist");
+            if !st.extend(bytes) {
+                if let Err(err) = stream.add(&id, self.kind, bytes) {
+                    log::error!("{id} {}: {err}", db.id());
+                }
+            } else {
+                while let Some(slice) = st.next_msg() {
+                    if let Err(err) = stream.add(&id, self.kind, slice) {
+                        log::error!("{id} {}: {err}", db.id());
+                    }
+                }
+            }
         } else {
             stream.add(&id, self.kind, bytes)?;
         }
@@ -91,6 +139,47 @@ impl HandleData for State {
     }
 }
 
+/// Store the rpc message, pair the response with its query
+pub fn rpc_sink(
+    id: &DirectedId,
+    db: &Db,
+    stream: &DbStream,
+    kind: StreamKind,
+    st: &mut rpc::State,
+    msg: &[u8],
+    call: rpc::Call,
+) {
+    let message_id = match stream.add(id, kind, msg) {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("{id} {}: {err}, {}", db.id(), hex::encode(msg));
+            return;
+        }
+    };
+    match call {
+        rpc::Call::Query(rpc_id) => {
+            let request = rpc::Request {
+                message_id,
+                timestamp: id.metadata.time,
+                size: msg.len() as u32,
+            };
+            st.set_request(rpc_id, request);
+        }
+        rpc::Call::Response(Some(request)) => {
+            let rpc::Request {
+                message_id: request_id,
+                timestamp,
+                size,
+            } = request;
+            let result = stream.add_rpc_call(id, message_id, msg, request_id, timestamp, size);
+            if let Err(err) = result {
+                log::error!("{id} {}: {err}", db.id());
+            }
+        }
+        rpc::Call::Response(None) => {}
+    }
+}
+
 fn meshsub_sink(id: &DirectedId, db: &Db, stream: &DbStream, msg: &[u8], cx: &Cx) {
     let node_address = {
         let lock = cx.apps.lock();
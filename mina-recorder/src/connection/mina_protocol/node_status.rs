@@ -0,0 +1,38 @@
+use std::mem;
+
+use crate::decode::node_status;
+
+/// The status is a few kilobytes, more means something is wrong, store it as is
+const MAX_SIZE: usize = 0x100000;
+
+/// The JSON is not length prefixed, accumulate it until it is complete
+#[derive(Default)]
+pub struct State {
+    // indexed by `incoming`
+    acc: [Vec<u8>; 2],
+}
+
+impl State {
+    /// Returns the message if it is complete
+    pub fn extend(&mut self, incoming: bool, bytes: &[u8]) -> Option<Vec<u8>> {
+        let acc = &mut self.acc[incoming as usize];
+        acc.extend_from_slice(bytes);
+        let request = acc.iter().all(u8::is_ascii_whitespace);
+        if request || node_status::is_complete(acc) || acc.len() >= MAX_SIZE {
+            Some(mem::take(acc))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn node_status_chunks() {
+    let json = br#"{"sync_status": "Synced", "git_commit": "55b78189"}"#;
+    let mut st = State::default();
+    assert!(st.extend(false, &json[..10]).is_none());
+    assert!(st.extend(true, b"").is_some());
+    assert_eq!(st.extend(false, &json[10..]).as_deref(), Some(&json[..]));
+    assert!(st.extend(false, &json[..10]).is_none());
+}
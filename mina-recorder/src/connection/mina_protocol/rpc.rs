@@ -2,6 +2,7 @@ use std::{
     collections::BTreeMap,
     io::{Cursor, self},
     borrow::Cow,
+    time::SystemTime,
 };
 
 use mina_p2p_messages::{
@@ -12,6 +13,8 @@ use mina_p2p_messages::{
 };
 use thiserror::Error;
 
+use crate::database::MessageId;
+
 use super::accumulator;
 
 #[derive(Default)]
@@ -23,6 +26,21 @@ pub struct State {
 struct Header {
     tag: BString,
     version: i32,
+    request: Option<Request>,
+}
+
+/// The stored query, known when its response arrives
+pub struct Request {
+    pub message_id: MessageId,
+    pub timestamp: SystemTime,
+    pub size: u32,
+}
+
+pub enum Call {
+    /// Use `State::set_request` when the query is stored
+    Query(i64),
+    /// `None` if the query was not stored
+    Response(Option<Request>),
 }
 
 #[derive(Debug, Error)]
@@ -44,7 +62,10 @@ impl State {
         Some((l0, l1))
     }
 
-    pub fn extend<'a>(&mut self, bytes: &'a mut [u8]) -> Result<Option<Cow<'a, [u8]>>, Error> {
+    pub fn extend<'a>(
+        &mut self,
+        bytes: &'a mut [u8],
+    ) -> Result<Option<(Cow<'a, [u8]>, Call)>, Error> {
         if self.acc.extend(Self::decode_size, bytes) {
             Ok(None)
         } else {
@@ -52,15 +73,26 @@ impl State {
         }
     }
 
-    pub fn next_msg(&mut self) -> Result<Option<Vec<u8>>, Error> {
+    pub fn next_msg(&mut self) -> Result<Option<(Vec<u8>, Call)>, Error> {
         let mut msg = match self.acc.next_msg(Self::decode_size) {
             Some(v) => v.to_vec(),
             None => return Ok(None),
         };
-        self.post_process(&mut msg).map(|x| x.map(|c| c.to_vec()))
+        self.post_process(&mut msg)
+            .map(|x| x.map(|(c, call)| (c.to_vec(), call)))
+    }
+
+    /// Remember where the query is stored, so its response can be paired with it
+    pub fn set_request(&mut self, id: i64, request: Request) {
+        if let Some(header) = self.pending.get_mut(&id) {
+            header.request = Some(request);
+        }
     }
 
-    fn post_process<'a>(&mut self, bytes: &'a mut [u8]) -> Result<Option<Cow<'a, [u8]>>, Error> {
+    fn post_process<'a>(
+        &mut self,
+        bytes: &'a mut [u8],
+    ) -> Result<Option<(Cow<'a, [u8]>, Call)>, Error> {
         let (l0, _) = Self::decode_size(bytes).ok_or(Error::DecodeSize)?;
         let mut stream = Cursor::new(&mut bytes[l0..]);
         match MessageHeader::binprot_read(&mut stream) {
@@ -70,12 +102,21 @@ impl State {
             }
             Ok(MessageHeader::Heartbeat) => Ok(None),
             Ok(MessageHeader::Query(QueryHeader { tag, version, id })) => {
-                let header = Header { tag, version };
+                let header = Header {
+                    tag,
+                    version,
+                    request: None,
+                };
                 self.pending.insert(id, header);
-                Ok(Some(Cow::Borrowed(bytes)))
+                Ok(Some((Cow::Borrowed(bytes), Call::Query(id))))
             }
             Ok(MessageHeader::Response(ResponseHeader { id })) => {
-                if let Some(Header { tag, version }) = self.pending.remove(&id) {
+                if let Some(Header {
+                    tag,
+                    version,
+                    request,
+                }) = self.pending.remove(&id)
+                {
                     let q = QueryHeader { tag, version, id };
                     let mut b = [0; 8].to_vec();
                     b.push(2);
@@ -88,7 +129,7 @@ impl State {
                     let new_len = b.len() - l0;
                     b[0..8].clone_from_slice(&(new_len as u64).to_le_bytes());
 
-                    Ok(Some(Cow::Owned(b)))
+                    Ok(Some((Cow::Owned(b), Call::Response(request))))
                 } else if id != 4411474 {
                     Err(Error::ResponseWithoutRequest { id })
                 } else {
@@ -11,6 +11,13 @@ pub trait DynamicProtocol {
 pub trait HandleData {
     // TODO: use Cow for bytes
     fn on_data(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()>;
+
+    /// The plaintext of the Noise transport message captured from the process,
+    /// the layers below the Noise forward it, the Noise passes it to its inner layer
+    fn on_plaintext(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        let _ = (id, bytes, cx, db);
+        Ok(())
+    }
 }
 
 mod accumulator;
@@ -221,6 +221,13 @@ where
 
         Ok(())
     }
+
+    fn on_plaintext(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        match &mut self.inner {
+            Some(inner) => inner.on_plaintext(id, bytes, cx, db),
+            None => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
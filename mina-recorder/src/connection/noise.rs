@@ -13,7 +13,10 @@ use vru_noise::{
 };
 use thiserror::Error;
 
-use crate::database::{StreamId, StreamKind, RandomnessDatabase, ConnectionStats};
+use crate::database::{
+    StreamId, StreamKind, RandomnessDatabase, ConnectionStats, NoiseDiagnostics, NoiseFailure,
+    NoiseStage,
+};
 
 use super::{HandleData, DirectedId, DynamicProtocol, Cx, Db, DbResult};
 
@@ -78,6 +81,10 @@ where
 
         Ok(())
     }
+
+    fn on_plaintext(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        self.inner.on_plaintext(id, bytes, cx, db)
+    }
 }
 
 pub struct NoiseState<Inner> {
@@ -87,6 +94,22 @@ pub struct NoiseState<Inner> {
     inner: Inner,
     decrypted: usize,
     failed_to_decrypt: usize,
+    // the plaintext is captured from the process, the transport messages are not decrypted here
+    plaintext: bool,
+    // number of the transport messages, indexed by `incoming`
+    transport_messages: [u64; 2],
+    // the latest transport message decrypted here
+    decrypted_messages: [u64; 2],
+    plaintext_messages: [u64; 2],
+    key_search: KeySearch,
+    diagnosed: bool,
+}
+
+/// Which secret keys the handshake looked for, reported if the connection cannot be decrypted
+#[derive(Default)]
+struct KeySearch {
+    keys: Vec<MontgomeryPoint>,
+    candidates: u64,
 }
 
 impl<Inner> DynamicProtocol for NoiseState<Inner>
@@ -101,6 +124,12 @@ where
             inner: Inner::from(stream_id),
             decrypted: 0,
             failed_to_decrypt: 0,
+            plaintext: false,
+            transport_messages: [0; 2],
+            decrypted_messages: [0; 2],
+            plaintext_messages: [0; 2],
+            key_search: KeySearch::default(),
+            diagnosed: false,
         }
     }
 }
@@ -139,6 +168,17 @@ where
             Some(St::SecondMessage { .. }) => Msg::Third,
             Some(_) => Msg::Other,
         };
+        let direction = id.incoming as usize;
+        if let Msg::Other = msg {
+            self.transport_messages[direction] += 1;
+        }
+        if self.plaintext && (self.error || matches!(msg, Msg::Other)) {
+            let stats = ConnectionStats {
+                total_bytes: bytes.len() as u64,
+                ..Default::default()
+            };
+            return db.update(stats, id.incoming);
+        }
         if !self.error {
             match self.on_data_(id.incoming, bytes, &cx.db.core()) {
                 Ok(range) => {
@@ -173,12 +213,20 @@ where
                             }
                         }
                         Msg::Other => {
+                            self.decrypted_messages[direction] = self.transport_messages[direction];
                             self.inner.on_data(id, bytes, cx, db)?;
                         }
                     }
                 }
                 Err(err) => {
                     self.error = true;
+                    let stage = match msg {
+                        Msg::First => NoiseStage::FirstMessage,
+                        Msg::Second => NoiseStage::SecondMessage,
+                        Msg::Third => NoiseStage::ThirdMessage,
+                        Msg::Other => NoiseStage::Transport,
+                    };
+                    self.diagnose(&id, stage, &err, db)?;
                     self.on_error(id, bytes, cx, db, err)?;
                 }
             }
@@ -188,6 +236,33 @@ where
 
         Ok(())
     }
+
+    fn on_plaintext(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        if !self.plaintext {
+            log::info!("{id} {}, plaintext is captured", db.id());
+            self.plaintext = true;
+        }
+        // every plaintext is a transport message, the ciphertext may come before it,
+        // and may be already decrypted here
+        let direction = id.incoming as usize;
+        self.plaintext_messages[direction] += 1;
+        if self.plaintext_messages[direction] <= self.decrypted_messages[direction] {
+            return Ok(());
+        }
+
+        self.decrypted += bytes.len();
+        cx.stats.decrypted.fetch_add(bytes.len(), Ordering::Relaxed);
+        db.update(
+            ConnectionStats {
+                total_bytes: 0,
+                decrypted_bytes: bytes.len() as u64,
+                decrypted_chunks: 1,
+                messages: 0,
+            },
+            id.incoming,
+        )?;
+        self.inner.on_data(id, bytes, cx, db)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -233,6 +308,69 @@ pub enum NoiseError {
 }
 
 impl<Inner> NoiseState<Inner> {
+    fn diagnose(
+        &mut self,
+        id: &DirectedId,
+        stage: NoiseStage,
+        err: &NoiseError,
+        db: &Db,
+    ) -> DbResult<()> {
+        if self.diagnosed {
+            return Ok(());
+        }
+        self.diagnosed = true;
+
+        // the responder sends the second message, the initiator sends the third
+        let out_of_order = match stage {
+            NoiseStage::SecondMessage => id.incoming == self.initiator_is_incoming,
+            NoiseStage::ThirdMessage => id.incoming != self.initiator_is_incoming,
+            _ => false,
+        };
+        let reason = match err {
+            _ if out_of_order => NoiseFailure::HandshakeOutOfOrder,
+            NoiseError::FirstMessageTooShort
+            | NoiseError::FirstMessageTooBig
+            | NoiseError::SecondMessageTooShort
+            | NoiseError::ThirdMessageTooShort
+            | NoiseError::DataTooShort => NoiseFailure::UnexpectedSize,
+            NoiseError::SecondMessageMacMismatch
+            | NoiseError::SecondMessagePayloadMacMismatch
+            | NoiseError::ThirdMessageMacMismatch
+            | NoiseError::ThirdMessagePayloadMacMismatch => NoiseFailure::MacMismatch,
+            NoiseError::EphemeralSecretKeyNotFound { .. }
+            | NoiseError::SecondSecretKeyNotFound { .. }
+            | NoiseError::ThirdSecretKeyNotFound { .. } => {
+                if self.key_search.candidates == 0 {
+                    NoiseFailure::NoRandomness
+                } else {
+                    NoiseFailure::UnknownKey
+                }
+            }
+            NoiseError::CannotDecrypt => NoiseFailure::CannotDecrypt,
+        };
+        let failed_nonce = match stage {
+            NoiseStage::Transport => self.transport_messages[id.incoming as usize] - 1,
+            _ => 0,
+        };
+
+        db.set_noise_diagnostics(NoiseDiagnostics {
+            time: id.metadata.time,
+            reason,
+            stage,
+            incoming: id.incoming,
+            initiator_is_incoming: self.initiator_is_incoming,
+            tried_keys: self
+                .key_search
+                .keys
+                .iter()
+                .map(|pk| hex::encode(pk.as_bytes()))
+                .collect(),
+            randomness_candidates: self.key_search.candidates,
+            failed_nonce,
+            decrypted_bytes: self.decrypted as u64,
+        })
+    }
+
     fn on_error(
         &mut self,
         id: DirectedId,
@@ -286,11 +424,20 @@ impl<Inner> NoiseState<Inner> {
         bytes: &'a mut [u8],
         cx: &impl RandomnessDatabase,
     ) -> Result<Range<usize>, NoiseError> {
-        fn find_sk(pk: &MontgomeryPoint, cx: &impl RandomnessDatabase) -> Option<Scalar> {
+        fn find_sk(
+            pk: &MontgomeryPoint,
+            cx: &impl RandomnessDatabase,
+            search: &mut KeySearch,
+        ) -> Option<Scalar> {
+            if !search.keys.contains(pk) {
+                search.keys.push(*pk);
+            }
+            let mut candidates = 0;
             let sk = cx.iterate_randomness().find_map(|rand| {
                 if rand.len() != 32 {
                     return None;
                 }
+                candidates += 1;
                 let mut sk_bytes = [0; 32];
                 sk_bytes.clone_from_slice(&rand);
                 sk_bytes[0] &= 248;
@@ -302,21 +449,24 @@ impl<Inner> NoiseState<Inner> {
                 } else {
                     None
                 }
-            })?;
-            Some(sk)
+            });
+            search.candidates = search.candidates.max(candidates);
+            sk
         }
 
         fn try_dh(
             a: &MontgomeryPoint,
             b: &MontgomeryPoint,
             cx: &impl RandomnessDatabase,
+            search: &mut KeySearch,
         ) -> Option<[u8; 32]> {
-            find_sk(a, cx)
+            find_sk(a, cx, search)
                 .map(|sk| b * sk)
-                .or_else(|| find_sk(b, cx).map(|sk| a * sk))
+                .or_else(|| find_sk(b, cx, search).map(|sk| a * sk))
                 .map(|ss| ss.to_bytes())
         }
 
+        let search = &mut self.key_search;
         let range;
         let len = bytes.len();
         self.machine = match self.machine.take() {
@@ -355,7 +505,7 @@ impl<Inner> NoiseState<Inner> {
                 let payload_tag = *GenericArray::from_slice(&bytes[(len - 16)..]);
                 let st = st
                     .mix_hash(r_epk.as_bytes())
-                    .mix_shared_secret(try_dh(&r_epk, &i_epk, cx).ok_or_else(|| {
+                    .mix_shared_secret(try_dh(&r_epk, &i_epk, cx, search).ok_or_else(|| {
                         let i = self.initiator_is_incoming;
                         NoiseError::EphemeralSecretKeyNotFound { i, r_epk, i_epk }
                     })?)
@@ -363,7 +513,7 @@ impl<Inner> NoiseState<Inner> {
                     .map_err(|_| NoiseError::SecondMessageMacMismatch)?
                     .mix_shared_secret({
                         r_spk = MontgomeryPoint(r_spk_bytes);
-                        try_dh(&r_spk, &i_epk, cx).ok_or_else(|| {
+                        try_dh(&r_spk, &i_epk, cx, search).ok_or_else(|| {
                             let i = self.initiator_is_incoming;
                             NoiseError::SecondSecretKeyNotFound { i, r_spk, i_epk }
                         })?
@@ -391,7 +541,7 @@ impl<Inner> NoiseState<Inner> {
                     .map_err(|_| NoiseError::ThirdMessageMacMismatch)?
                     .mix_shared_secret({
                         i_spk = MontgomeryPoint(i_spk_bytes);
-                        try_dh(&i_spk, &r_epk, cx).ok_or_else(|| {
+                        try_dh(&i_spk, &r_epk, cx, search).ok_or_else(|| {
                             let i = self.initiator_is_incoming;
                             NoiseError::ThirdSecretKeyNotFound { i, r_epk, i_spk }
                         })?
@@ -467,6 +617,32 @@ fn noise() {
     noise.on_data_(id.incoming, &mut hex::decode("00375cd2640426acf52810f89147cf5446f8b4bff334c9727c0a45abd220746b2e8b10d269ff28be87c8bb1d53e43e69922ff4b19760ef875d").expect("valid constant"), &mut cx).expect("test");
 }
 
+#[cfg(test)]
+#[test]
+#[rustfmt::skip]
+fn noise_key_search() {
+    struct Randomness(Vec<Vec<u8>>);
+
+    impl RandomnessDatabase for Randomness {
+        fn iterate_randomness<'a>(&'a self) -> Box<dyn Iterator<Item = Box<[u8]>> + 'a> {
+            Box::new(self.0.iter().map(|x| x.clone().into_boxed_slice()))
+        }
+    }
+
+    let mut noise = NoiseState::<super::multistream_select::State<()>>::from_name("/noise", StreamId::Handshake);
+    // not the secret key of the handshake, the value of wrong size is not a candidate
+    let cx = Randomness(vec![vec![0x11; 32], vec![0x22; 16]]);
+
+    noise.on_data_(true, &mut hex::decode("00209844288f8c8f0337dff411d66e0378d950fb7590f9f44d6df969fd59a18ab849").expect("valid constant"), &cx).expect("test");
+    let err = noise.on_data_(false, &mut hex::decode("00c8c0e8867216784ce23e6ad97120c8bfa139941424d0aebcdfe14e339798af4a377f2a97c280a913fdf6a96b4b89c5471a7f4761bec49a557d734b65495eb87e1e00b707d561da835698fe08bab7962b0491751110e8a32a260605a64dbdc18f503958be161fe9546f3c0494c0714f6e57c3eca413cec2d20a483855b4958b96ee79e05f34fa63a74c758ebe9537f4e1c733a7a7ebcd9b1bcc47c2c882ffa361f6ebb404225b60a6bae8e7a6d479d6e1b5c5c1d858ca13dde8cbd285f5bb4d9805578553e3881d5a0d").expect("valid constant"), &cx).unwrap_err();
+    assert!(matches!(err, NoiseError::EphemeralSecretKeyNotFound { .. }));
+
+    let keys = noise.key_search.keys.iter().map(|pk| hex::encode(pk.as_bytes())).collect::<Vec<_>>();
+    // responder's ephemeral key, then initiator's ephemeral key
+    assert_eq!(keys, ["c0e8867216784ce23e6ad97120c8bfa139941424d0aebcdfe14e339798af4a37", "9844288f8c8f0337dff411d66e0378d950fb7590f9f44d6df969fd59a18ab849"]);
+    assert_eq!(noise.key_search.candidates, 1);
+}
+
 #[cfg(test)]
 #[test]
 #[should_panic]
@@ -4,14 +4,23 @@ use salsa20::{
     XSalsa20,
 };
 
-use crate::chunk::EncryptionStatus;
+use crate::{
+    chunk::EncryptionStatus,
+    database::{NoiseDiagnostics, NoiseFailure, NoiseStage},
+};
 
 use super::{HandleData, DirectedId, Cx, Db, DbResult, StreamId};
 
 pub struct State<Inner> {
-    shared_secret: GenericArray<u8, typenum::U32>,
-    cipher_in: Option<XSalsa20>,
-    cipher_out: Option<XSalsa20>,
+    // the candidate chain ids and their keys, the most likely first
+    candidates: Vec<(String, GenericArray<u8, typenum::U32>)>,
+    // the candidate which is used to decrypt, chosen by the first data
+    chosen: Option<usize>,
+    // indexed by `incoming`
+    nonces: [Option<GenericArray<u8, typenum::U24>>; 2],
+    ciphers: [Option<XSalsa20>; 2],
+    // the first data after the nonce is checked, indexed by `incoming`
+    checked: [bool; 2],
     skip: bool,
     inner: Inner,
 }
@@ -20,18 +29,103 @@ impl<Inner> State<Inner>
 where
     Inner: From<StreamId>,
 {
-    pub fn new(chain_id: &[u8]) -> Self {
+    /// The `chain_ids` are tried in order, must be at least one,
+    /// the first is used if none of them matches
+    pub fn new<I>(chain_ids: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let candidates = chain_ids
+            .into_iter()
+            .map(|chain_id| {
+                let key = Self::shared_secret(chain_id.as_bytes());
+                (chain_id, key)
+            })
+            .collect::<Vec<_>>();
+        assert!(!candidates.is_empty(), "must be at least one chain id");
         State {
-            shared_secret: Self::shared_secret(chain_id),
-            cipher_in: None,
-            cipher_out: None,
+            candidates,
+            chosen: None,
+            nonces: [None; 2],
+            ciphers: [None, None],
+            checked: [false; 2],
             skip: false,
             inner: Inner::from(StreamId::Handshake),
         }
     }
 }
 
+const PREFIX: &[u8] = b"\x13/multistream/1.0.0\n";
+
+fn is_multistream(bytes: &[u8]) -> bool {
+    let len = bytes.len().min(PREFIX.len());
+    bytes[..len] == PREFIX[..len]
+}
+
+/// Index of the first key which decrypts the `bytes` into multistream-select
+fn detect(
+    keys: impl IntoIterator<Item = GenericArray<u8, typenum::U32>>,
+    nonce: &GenericArray<u8, typenum::U24>,
+    bytes: &[u8],
+) -> Option<usize> {
+    keys.into_iter().position(|key| {
+        let mut bytes = bytes[..bytes.len().min(PREFIX.len())].to_vec();
+        XSalsa20::new(&key, nonce).apply_keystream(&mut bytes);
+        is_multistream(&bytes)
+    })
+}
+
 impl<Inner> State<Inner> {
+    /// Both sides start with multistream-select,
+    /// anything else means the key, so the chain id, is wrong
+    fn check_chain_id(id: &DirectedId, bytes: &[u8], db: &Db) -> DbResult<()> {
+        if is_multistream(bytes) {
+            return Ok(());
+        }
+        log::warn!("{id} {}, wrong chain id", db.id());
+        db.set_noise_diagnostics(NoiseDiagnostics {
+            time: id.metadata.time,
+            reason: NoiseFailure::WrongChainId,
+            stage: NoiseStage::Pnet,
+            incoming: id.incoming,
+            initiator_is_incoming: false,
+            tried_keys: vec![],
+            randomness_candidates: 0,
+            failed_nonce: 0,
+            decrypted_bytes: 0,
+        })
+    }
+
+    /// Choose the chain id by the first data of the connection,
+    /// and create the ciphers for the nonces already seen
+    fn choose(&mut self, id: &DirectedId, bytes: &[u8], db: &Db) -> DbResult<()> {
+        let nonce = self.nonces[id.incoming as usize]
+            .as_ref()
+            .expect("must be called after the nonce");
+        let keys = self.candidates.iter().map(|(_, key)| *key);
+        let chosen = match detect(keys, nonce, bytes) {
+            Some(index) => {
+                let chain_id = &self.candidates[index].0;
+                log::debug!("{id} {}, chain id {chain_id}", db.id());
+                db.set_chain_id(Some(chain_id.as_str()))?;
+                index
+            }
+            None => {
+                db.set_chain_id(None)?;
+                0
+            }
+        };
+        self.chosen = Some(chosen);
+        let key = &self.candidates[chosen].1;
+        for (cipher, nonce) in self.ciphers.iter_mut().zip(&self.nonces) {
+            if let Some(nonce) = nonce {
+                *cipher = Some(XSalsa20::new(key, nonce));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn shared_secret(chain_id: &[u8]) -> GenericArray<u8, typenum::U32> {
         use blake2::{
             digest::{Update, VariableOutput},
@@ -57,14 +151,35 @@ where
         if self.skip {
             return Ok(());
         }
-        let cipher = if id.incoming {
-            &mut self.cipher_in
-        } else {
-            &mut self.cipher_out
-        };
         db.add_raw(EncryptionStatus::Raw, id.incoming, id.metadata.time, bytes)?;
-        if let Some(cipher) = cipher {
+        let i = id.incoming as usize;
+        if self.nonces[i].is_none() {
+            if bytes.len() != 24 {
+                self.skip = true;
+                log::warn!(
+                    "{id} {} skip connection, bytes: {}",
+                    db.id(),
+                    hex::encode(bytes)
+                );
+            } else {
+                let nonce = *GenericArray::from_slice(bytes);
+                if let Some(chosen) = self.chosen {
+                    self.ciphers[i] = Some(XSalsa20::new(&self.candidates[chosen].1, &nonce));
+                }
+                self.nonces[i] = Some(nonce);
+            }
+            return Ok(());
+        }
+        if self.chosen.is_none() {
+            self.choose(&id, bytes, db)?;
+        }
+        if let Some(cipher) = &mut self.ciphers[i] {
             cipher.apply_keystream(bytes);
+            let checked = &mut self.checked[i];
+            if !*checked {
+                *checked = true;
+                Self::check_chain_id(&id, bytes, db)?;
+            }
             db.add_raw(
                 EncryptionStatus::DecryptedPnet,
                 id.incoming,
@@ -72,20 +187,36 @@ where
                 bytes,
             )?;
             self.inner.on_data(id, bytes, cx, db)?;
-        } else if bytes.len() != 24 {
-            self.skip = true;
-            log::warn!(
-                "{id} {} skip connection, bytes: {}",
-                db.id(),
-                hex::encode(bytes)
-            );
-        } else {
-            *cipher = Some(XSalsa20::new(
-                &self.shared_secret,
-                GenericArray::from_slice(bytes),
-            ));
         }
 
         Ok(())
     }
+
+    fn on_plaintext(&mut self, id: DirectedId, bytes: &mut [u8], cx: &Cx, db: &Db) -> DbResult<()> {
+        if self.skip {
+            return Ok(());
+        }
+        self.inner.on_plaintext(id, bytes, cx, db)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn detect_chain_id() {
+    type St = State<super::multistream_select::State<()>>;
+
+    let nonce = GenericArray::from([7; 24]);
+    let mut bytes = PREFIX.to_vec();
+    XSalsa20::new(&St::shared_secret(b"/coda/0.0.1/right"), &nonce).apply_keystream(&mut bytes);
+
+    let keys = |ids: &[&str]| {
+        ids.iter()
+            .map(|id| St::shared_secret(id.as_bytes()))
+            .collect::<Vec<_>>()
+    };
+    let ids = ["/coda/0.0.1/wrong", "/coda/0.0.1/right"];
+    assert_eq!(detect(keys(&ids), &nonce, &bytes), Some(1));
+    // the first chunk might be shorter than the header
+    assert_eq!(detect(keys(&ids), &nonce, &bytes[..5]), Some(1));
+    assert_eq!(detect(keys(&ids[..1]), &nonce, &bytes), None);
 }
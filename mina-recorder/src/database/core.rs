@@ -3,7 +3,7 @@ use std::{
     time::{Duration, SystemTime},
     cmp::Ordering,
     sync::{Arc, Mutex},
-    collections::{BTreeMap, HashSet, BTreeSet},
+    collections::{btree_map, BTreeMap, HashSet, BTreeSet},
     io,
     convert::TryInto,
     net::SocketAddr,
@@ -19,12 +19,20 @@ use super::{
     types::{
         Connection, ConnectionId, StreamFullId, Message, StreamKind, FullMessage, MessageId,
         Timestamp, StatsDbKey, StatsV2DbKey, CapnpEventWithMetadata, CapnpEventWithMetadataKey,
-        CapnpTableRow, CapnpEventDecoded,
+        CapnpTableRow, CapnpEventDecoded, TcpInfoDbKey, TcpInfoSample, NoiseDiagnostics,
+        BitswapEvent, PeerProtocolsDbKey, PeerProtocols, PeerExchangeEvent, RpcCall, RpcLatency,
+        SnarkWorker, SnarkWork, SnarkWorkKind, HelperLogDbKey, HelperLog, IpcMessageKey,
+        IpcMessageWithMetadata, IpcEvent, GossipValidationKey, GossipValidation,
+        GossipValidationEvent, RejectedGossip,
+    },
+    params::{
+        ValidParams, Coordinate, StreamFilter, Direction, KindFilter, ValidParamsConnection,
+        RpcParams, HelperLogParams, IpcParams, GossipValidationParams,
     },
-    params::{ValidParams, Coordinate, StreamFilter, Direction, KindFilter, ValidParamsConnection},
     index::{
         ConnectionIdx, StreamIdx, StreamByKindIdx, MessageKindIdx, AddressIdx, LedgerHash,
-        LedgerHashIdx,
+        LedgerHashIdx, NodeIdx, HashedIdx, GitCommitIdx, SyncStatusIdx, CidIdx, AdvertisedPeerIdx,
+        GossipHashIdx,
     },
     sorted_intersect::sorted_intersect,
 };
@@ -33,9 +41,13 @@ use crate::{
     decode::{
         DecodeError, MessageType,
         meshsub_stats::{self, BlockStat, TxStat, Hash},
+        node_status::NodeStatus,
+        identify::Protocols,
+        peer_exchange::Peer,
     },
     strace::StraceLine,
     meshsub::{SnarkByHash, Event, SnarkWithHash},
+    libp2p_helper::ipc::{self, ValidationResult},
     ChunkHeader,
 };
 
@@ -90,11 +102,14 @@ impl<'pa> From<nom::Err<ParseError<&'pa [u8]>>> for DbError {
 #[derive(Clone)]
 pub struct DbCore {
     cache: Arc<Mutex<BTreeMap<ConnectionId, u64>>>,
+    // the protocols are stored as the difference with the previous record,
+    // the identify messages of many connections must not interleave
+    peer_protocols_lock: Arc<Mutex<()>>,
     inner: Arc<rocksdb::DB>,
 }
 
 impl DbCore {
-    const CFS: [&'static str; 15] = [
+    const CFS: [&'static str; 28] = [
         Self::CONNECTIONS,
         Self::MESSAGES,
         Self::RANDOMNESS,
@@ -104,12 +119,25 @@ impl DbCore {
         Self::CAPNP,
         Self::STATS_BLOCK_V2,
         Self::BLOBS,
+        Self::TCP_INFO,
+        Self::NOISE_DIAGNOSTICS,
+        Self::PEER_PROTOCOLS,
+        Self::RPC_CALLS,
+        Self::HELPER_LOGS,
+        Self::IPC,
+        Self::GOSSIP_VALIDATIONS,
         Self::CONNECTION_ID_INDEX,
         Self::STREAM_ID_INDEX,
         Self::STREAM_KIND_INDEX,
         Self::MESSAGE_KIND_INDEX,
         Self::ADDR_INDEX,
         Self::LEDGER_HASH_INDEX,
+        Self::NODE_INDEX,
+        Self::GIT_COMMIT_INDEX,
+        Self::SYNC_STATUS_INDEX,
+        Self::CID_INDEX,
+        Self::ADVERTISED_PEER_INDEX,
+        Self::GOSSIP_HASH_INDEX,
     ];
 
     const TTL: Duration = Duration::from_secs(0);
@@ -140,6 +168,20 @@ impl DbCore {
 
     const BLOBS: &'static str = "blobs";
 
+    const TCP_INFO: &'static str = "tcp_info";
+
+    const NOISE_DIAGNOSTICS: &'static str = "noise_diagnostics";
+
+    const PEER_PROTOCOLS: &'static str = "peer_protocols";
+
+    const RPC_CALLS: &'static str = "rpc_calls";
+
+    const HELPER_LOGS: &'static str = "helper_logs";
+
+    const IPC: &'static str = "ipc";
+
+    const GOSSIP_VALIDATIONS: &'static str = "gossip_validations";
+
     // indexes
 
     const CONNECTION_ID_INDEX: &'static str = "connection_id_index";
@@ -154,6 +196,18 @@ impl DbCore {
 
     const LEDGER_HASH_INDEX: &'static str = "ledger_hash_index";
 
+    const NODE_INDEX: &'static str = "node_index";
+
+    const GIT_COMMIT_INDEX: &'static str = "git_commit_index";
+
+    const SYNC_STATUS_INDEX: &'static str = "sync_status_index";
+
+    const CID_INDEX: &'static str = "cid_index";
+
+    const ADVERTISED_PEER_INDEX: &'static str = "advertised_peer_index";
+
+    const GOSSIP_HASH_INDEX: &'static str = "gossip_hash_index";
+
     pub fn open<P>(path: P) -> Result<Self, DbError>
     where
         P: AsRef<Path>,
@@ -182,19 +236,40 @@ impl DbCore {
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[7], opts_with_prefix_extractor(4)),
             // BLOBS
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[8], Default::default()),
-            // INDEXES
+            // TCP_INFO
             rocksdb::ColumnFamilyDescriptor::new(Self::CFS[9], opts_with_prefix_extractor(8)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[10], opts_with_prefix_extractor(16)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[11], opts_with_prefix_extractor(2)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[12], opts_with_prefix_extractor(2)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[13], opts_with_prefix_extractor(18)),
-            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[14], opts_with_prefix_extractor(32)),
+            // NOISE_DIAGNOSTICS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[10], Default::default()),
+            // PEER_PROTOCOLS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[11], opts_with_prefix_extractor(8)),
+            // RPC_CALLS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[12], Default::default()),
+            // HELPER_LOGS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[13], Default::default()),
+            // IPC
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[14], Default::default()),
+            // GOSSIP_VALIDATIONS
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[15], Default::default()),
+            // INDEXES
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[16], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[17], opts_with_prefix_extractor(16)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[18], opts_with_prefix_extractor(2)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[19], opts_with_prefix_extractor(2)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[20], opts_with_prefix_extractor(18)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[21], opts_with_prefix_extractor(32)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[22], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[23], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[24], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[25], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[26], opts_with_prefix_extractor(8)),
+            rocksdb::ColumnFamilyDescriptor::new(Self::CFS[27], opts_with_prefix_extractor(32)),
         ];
         let inner =
             rocksdb::DB::open_cf_descriptors_with_ttl(&opts, path.join("rocksdb"), cfs, Self::TTL)?;
 
         Ok(DbCore {
             cache: Arc::new(Mutex::new(BTreeMap::default())),
+            peer_protocols_lock: Arc::new(Mutex::new(())),
             inner: Arc::new(inner),
         })
     }
@@ -239,6 +314,40 @@ impl DbCore {
         self.inner.cf_handle(Self::BLOBS).expect("must exist")
     }
 
+    fn tcp_info(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::TCP_INFO).expect("must exist")
+    }
+
+    fn noise_diagnostics(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::NOISE_DIAGNOSTICS)
+            .expect("must exist")
+    }
+
+    fn peer_protocols(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::PEER_PROTOCOLS)
+            .expect("must exist")
+    }
+
+    fn rpc_calls(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::RPC_CALLS).expect("must exist")
+    }
+
+    fn helper_logs(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::HELPER_LOGS).expect("must exist")
+    }
+
+    fn ipc(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::IPC).expect("must exist")
+    }
+
+    fn gossip_validations(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GOSSIP_VALIDATIONS)
+            .expect("must exist")
+    }
+
     fn connection_id_index(&self) -> &rocksdb::ColumnFamily {
         self.inner
             .cf_handle(Self::CONNECTION_ID_INDEX)
@@ -273,6 +382,38 @@ impl DbCore {
             .expect("must exist")
     }
 
+    fn node_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::NODE_INDEX).expect("must exist")
+    }
+
+    fn git_commit_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GIT_COMMIT_INDEX)
+            .expect("must exist")
+    }
+
+    fn sync_status_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::SYNC_STATUS_INDEX)
+            .expect("must exist")
+    }
+
+    fn cid_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner.cf_handle(Self::CID_INDEX).expect("must exist")
+    }
+
+    fn advertised_peer_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::ADVERTISED_PEER_INDEX)
+            .expect("must exist")
+    }
+
+    fn gossip_hash_index(&self) -> &rocksdb::ColumnFamily {
+        self.inner
+            .cf_handle(Self::GOSSIP_HASH_INDEX)
+            .expect("must exist")
+    }
+
     pub fn put_cn(&self, id: ConnectionId, v: Connection) -> Result<(), DbError> {
         self.inner
             .put_cf(self.connections(), id.chain(vec![]), v.chain(vec![]))?;
@@ -283,6 +424,7 @@ impl DbCore {
     pub fn put_message(
         &self,
         addr: &SocketAddr,
+        node: &str,
         id: MessageId,
         v: Message,
         tys: Vec<MessageType>,
@@ -299,6 +441,9 @@ impl DbCore {
         };
         self.inner
             .put_cf(self.connection_id_index(), index.chain(vec![]), vec![])?;
+        if !node.is_empty() {
+            self.put_hashed_index::<NodeIdx>(self.node_index(), NodeIdx::hash(node), id)?;
+        }
         let index = StreamIdx {
             stream_full_id: StreamFullId {
                 cn: v.connection_id,
@@ -341,6 +486,144 @@ impl DbCore {
         Ok(())
     }
 
+    fn put_hashed_index<I>(
+        &self,
+        cf: &rocksdb::ColumnFamily,
+        hash: I::Hash,
+        id: MessageId,
+    ) -> Result<(), DbError>
+    where
+        I: HashedIdx,
+    {
+        self.inner
+            .put_cf(cf, I::new(hash, id).chain(vec![]), vec![])?;
+
+        Ok(())
+    }
+
+    /// Index the node status response by what the peer reports about itself
+    pub fn put_node_status_index(&self, id: MessageId, v: &NodeStatus) -> Result<(), DbError> {
+        let git_commit = NodeIdx::hash(&v.git_commit);
+        self.put_hashed_index::<GitCommitIdx>(self.git_commit_index(), git_commit, id)?;
+        let sync_status = NodeIdx::hash(&v.sync_status);
+        self.put_hashed_index::<SyncStatusIdx>(self.sync_status_index(), sync_status, id)?;
+
+        Ok(())
+    }
+
+    pub fn put_cid_index(&self, id: MessageId, cids: &[String]) -> Result<(), DbError> {
+        for cid in cids {
+            self.put_hashed_index::<CidIdx>(self.cid_index(), NodeIdx::hash(cid), id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn put_gossip_hash_index(&self, id: MessageId, hashes: &[Hash]) -> Result<(), DbError> {
+        for hash in hashes {
+            self.put_hashed_index::<GossipHashIdx>(self.gossip_hash_index(), *hash, id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply the identify message to the protocols of the peer,
+    /// stores the new set if it has changed
+    pub fn put_peer_protocols(
+        &self,
+        peer_id: &str,
+        msg: &Message,
+        message_id: MessageId,
+        kind: MessageType,
+        update: Protocols,
+    ) -> Result<(), DbError> {
+        let _lock = self.peer_protocols_lock.lock().expect("must be ok");
+        let last = self
+            .fetch_peer_protocols(peer_id, Direction::Reverse)
+            .next();
+        let old: BTreeSet<String> = last
+            .as_ref()
+            .map(|v| v.protocols.iter().cloned().collect())
+            .unwrap_or_default();
+        let new = match update {
+            Protocols::Full { protocols, .. } => protocols.into_iter().collect(),
+            Protocols::Delta { added, removed } => {
+                let mut new = old.clone();
+                new.extend(added);
+                for protocol in &removed {
+                    new.remove(protocol);
+                }
+                new
+            }
+        };
+        if last.is_some() && old == new {
+            return Ok(());
+        }
+
+        let key = PeerProtocolsDbKey {
+            peer: NodeIdx::hash(peer_id),
+            id: message_id,
+        };
+        let v = PeerProtocols {
+            peer_id: peer_id.to_owned(),
+            timestamp: msg.timestamp,
+            message_id,
+            connection_id: msg.connection_id,
+            kind,
+            added: new.difference(&old).cloned().collect(),
+            removed: old.difference(&new).cloned().collect(),
+            protocols: new.into_iter().collect(),
+        };
+        self.inner
+            .put_cf(self.peer_protocols(), key.chain(vec![]), v.chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_advertised_peer_index(&self, id: MessageId, peers: &[Peer]) -> Result<(), DbError> {
+        for peer in peers {
+            let hash = NodeIdx::hash(&peer.peer_id);
+            self.put_hashed_index::<AdvertisedPeerIdx>(self.advertised_peer_index(), hash, id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn put_rpc_call(&self, v: RpcCall) -> Result<(), DbError> {
+        self.inner.put_cf(
+            self.rpc_calls(),
+            v.response_id.0.to_be_bytes(),
+            v.chain(vec![]),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn put_tcp_info(&self, id: ConnectionId, v: TcpInfoSample) -> Result<(), DbError> {
+        let key = TcpInfoDbKey {
+            connection_id: id,
+            time: v.time,
+        };
+        self.inner
+            .put_cf(self.tcp_info(), key.chain(vec![]), v.chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_noise_diagnostics(
+        &self,
+        id: ConnectionId,
+        v: NoiseDiagnostics,
+    ) -> Result<(), DbError> {
+        self.inner.put_cf(
+            self.noise_diagnostics(),
+            id.0.to_be_bytes(),
+            v.chain(vec![]),
+        )?;
+
+        Ok(())
+    }
+
     pub fn put_randomness(&self, id: u64, bytes: Vec<u8>) -> Result<(), DbError> {
         self.inner
             .put_cf(self.randomness(), id.to_be_bytes(), bytes)?;
@@ -403,6 +686,34 @@ impl DbCore {
         Ok(())
     }
 
+    pub fn put_helper_log(&self, key: HelperLogDbKey, v: HelperLog) -> Result<(), DbError> {
+        self.inner
+            .put_cf(self.helper_logs(), key.chain(vec![]), v.chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_ipc(&self, key: IpcMessageKey, v: IpcMessageWithMetadata) -> Result<(), DbError> {
+        self.inner
+            .put_cf(self.ipc(), key.chain(vec![]), v.chain(vec![]))?;
+
+        Ok(())
+    }
+
+    pub fn put_gossip_validation(
+        &self,
+        key: GossipValidationKey,
+        v: GossipValidation,
+    ) -> Result<(), DbError> {
+        self.inner.put_cf(
+            self.gossip_validations(),
+            key.chain(vec![]),
+            v.chain(vec![]),
+        )?;
+
+        Ok(())
+    }
+
     pub fn put_blob(&self, cn: ConnectionId, data: &[u8]) -> Result<u64, DbError> {
         let mut lock = self.cache.lock().expect("must be ok");
         let position = lock.entry(cn).or_default();
@@ -559,6 +870,7 @@ impl DbCore {
             FullMessage {
                 connection_id: msg.connection_id,
                 remote_addr: connection.info.addr,
+                node: connection.node,
                 incoming: msg.incoming,
                 timestamp: msg.timestamp,
                 stream_id: msg.stream_id,
@@ -578,16 +890,14 @@ impl DbCore {
             StreamKind::Kad => crate::decode::kademlia::parse(buf, preview)?,
             StreamKind::Meshsub => crate::decode::meshsub::parse(buf, preview)?,
             StreamKind::Handshake => crate::decode::noise::parse(buf, preview)?,
-            StreamKind::Rpc => crate::decode::rpc::parse(buf, preview)?,
+            StreamKind::Rpc | StreamKind::SnarkWorker => crate::decode::rpc::parse(buf, preview)?,
             StreamKind::IpfsId => crate::decode::identify::parse(buf, preview, msg.stream_kind)?,
             StreamKind::IpfsPush => crate::decode::identify::parse(buf, preview, msg.stream_kind)?,
+            StreamKind::IpfsDelta => crate::decode::identify::parse(buf, preview, msg.stream_kind)?,
+            StreamKind::PeerExchange => crate::decode::peer_exchange::parse(buf, preview)?,
+            StreamKind::BitswapExchange => crate::decode::bitswap::parse(buf, preview)?,
             // TODO: proper decode
-            StreamKind::IpfsDelta => serde_json::Value::String(hex::encode(&buf)),
-            StreamKind::PeerExchange => crate::decode::json_string::parse(buf, preview)?,
-            // TODO: proper decode
-            StreamKind::BitswapExchange => serde_json::Value::String(hex::encode(&buf)),
-            // TODO: proper decode
-            StreamKind::NodeStatus => serde_json::Value::String(hex::encode(&buf)),
+            StreamKind::NodeStatus => crate::decode::node_status::parse(buf, preview)?,
             StreamKind::Select => {
                 let s = String::from_utf8(buf)
                     .map_err(|err| DbError::Decode(DecodeError::Utf8(err)))?;
@@ -630,6 +940,7 @@ impl DbCore {
         Ok(FullMessage {
             connection_id: msg.connection_id,
             remote_addr: connection.info.addr,
+            node: connection.node,
             incoming: msg.incoming,
             timestamp: msg.timestamp,
             stream_id: msg.stream_id,
@@ -710,10 +1021,14 @@ impl DbCore {
             .filter_map(Self::decode);
         let it = Box::new(it) as Box<dyn Iterator<Item = (u64, Connection)>>;
         let now = SystemTime::now();
+        let node = params.node.clone();
         params.limit(it.filter_map(move |(id, cn)| {
             if cn.stats_in.total_bytes == 0 && cn.stats_out.total_bytes == 0 {
                 return None;
             }
+            if node.as_ref().map_or(false, |node| cn.node != *node) {
+                return None;
+            }
             Some((id, cn.post_process(Some(now))))
         }))
     }
@@ -727,7 +1042,14 @@ impl DbCore {
         let coordinate = &params.coordinate;
         let direction = coordinate.direction;
 
-        let it = if params.stream_filter.is_some() || params.kind_filter.is_some() {
+        let it = if params.stream_filter.is_some()
+            || params.kind_filter.is_some()
+            || params.node.is_some()
+            || params.git_commit.is_some()
+            || params.sync_status.is_some()
+            || params.cid.is_some()
+            || params.advertised_peer.is_some()
+        {
             let stream_indexes = match &params.stream_filter {
                 Some(StreamFilter::AnyStreamByAddr(addr)) => {
                     // TODO: duplicated code
@@ -832,15 +1154,48 @@ impl DbCore {
                 }
                 None => None,
             };
-            match (stream_indexes, kind_indexes) {
-                (Some(a), Some(b)) => {
-                    let forward = matches!(&direction, &Direction::Forward);
-                    let it = sorted_intersect(&mut [a, b], coordinate.limit, forward).into_iter();
-                    self.fetch_messages_by_indexes(it)
-                }
-                (Some(i), None) => self.fetch_messages_by_indexes(i),
-                (None, Some(i)) => self.fetch_messages_by_indexes(i),
-                (None, None) => unreachable!(),
+            let node_indexes = params.node.as_ref().map(|node| {
+                let node = NodeIdx::hash(node);
+                self.hashed_index_messages::<NodeIdx>(self.node_index(), node, id, direction)
+            });
+            let git_commit_indexes = params.git_commit.as_ref().map(|git_commit| {
+                let git_commit = NodeIdx::hash(git_commit);
+                let cf = self.git_commit_index();
+                self.hashed_index_messages::<GitCommitIdx>(cf, git_commit, id, direction)
+            });
+            let sync_status_indexes = params.sync_status.as_ref().map(|sync_status| {
+                let sync_status = NodeIdx::hash(sync_status);
+                let cf = self.sync_status_index();
+                self.hashed_index_messages::<SyncStatusIdx>(cf, sync_status, id, direction)
+            });
+            let cid_indexes = params.cid.as_ref().map(|cid| {
+                let cid = NodeIdx::hash(cid);
+                self.hashed_index_messages::<CidIdx>(self.cid_index(), cid, id, direction)
+            });
+            let advertised_peer_indexes = params.advertised_peer.as_ref().map(|peer_id| {
+                let peer = NodeIdx::hash(peer_id);
+                let cf = self.advertised_peer_index();
+                self.hashed_index_messages::<AdvertisedPeerIdx>(cf, peer, id, direction)
+            });
+            let mut indexes = [
+                stream_indexes,
+                kind_indexes,
+                node_indexes,
+                git_commit_indexes,
+                sync_status_indexes,
+                cid_indexes,
+                advertised_peer_indexes,
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+            if indexes.len() > 1 {
+                let forward = matches!(&direction, &Direction::Forward);
+                let it = sorted_intersect(&mut indexes, coordinate.limit, forward).into_iter();
+                self.fetch_messages_by_indexes(it)
+            } else {
+                let i = indexes.pop().expect("at least one filter is checked above");
+                self.fetch_messages_by_indexes(i)
             }
         } else {
             let id = id.to_be_bytes();
@@ -859,6 +1214,188 @@ impl DbCore {
         params.limit(it.filter_map(|v| self.fetch_details(v)))
     }
 
+    /// The messages which mention the thing of the hash, starting from the message `id`
+    fn hashed_index_messages<'a, I>(
+        &'a self,
+        cf: &'a rocksdb::ColumnFamily,
+        hash: I::Hash,
+        id: u64,
+        direction: Direction,
+    ) -> Box<dyn Iterator<Item = MessageId> + 'a>
+    where
+        I: HashedIdx,
+    {
+        let id = I::new(hash, MessageId(id)).chain(vec![]);
+        let mode = rocksdb::IteratorMode::From(&id, direction.into());
+
+        let it = self
+            .inner
+            .iterator_cf(cf, mode)
+            .filter_map(Self::decode_index::<I>)
+            .take_while(move |index| index.hash() == hash)
+            .map(|index| index.id());
+        Box::new(it) as Box<dyn Iterator<Item = MessageId>>
+    }
+
+    /// Every peer exchange message which advertises the peer, in order,
+    /// with the advertiser and the addresses it gives
+    pub fn fetch_peer_exchange(&self, peer_id: &str) -> Result<Vec<PeerExchangeEvent>, DbError> {
+        use crate::decode::peer_exchange;
+
+        let mut events = vec![];
+        let peer = NodeIdx::hash(peer_id);
+        let cf = self.advertised_peer_index();
+        let ids = self.hashed_index_messages::<AdvertisedPeerIdx>(cf, peer, 0, Direction::Forward);
+        for (message_id, msg) in self.fetch_messages_by_indexes(ids) {
+            let buf = self.fetch_blob(msg.connection_id, msg.offset)?;
+            let (_, peers) = peer_exchange::parse_types(&buf)?;
+            let Some(peer) = peers.into_iter().find(|peer| peer.peer_id == peer_id) else {
+                // hash collision
+                continue;
+            };
+            let connection = self.fetch_connection(msg.connection_id.0)?;
+            let advertiser = if msg.incoming {
+                connection.remote_peer_id
+            } else {
+                connection.local_peer_id
+            };
+            events.push(PeerExchangeEvent {
+                message_id,
+                connection_id: msg.connection_id,
+                remote_addr: connection.info.addr,
+                incoming: msg.incoming,
+                timestamp: msg.timestamp,
+                advertiser,
+                addrs: peer.addrs,
+                unreachable_addrs: peer.unreachable_addrs,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// Work fetched and submitted by every snark worker, in order
+    pub fn fetch_snark_workers(&self) -> Result<Vec<SnarkWorker>, DbError> {
+        use crate::decode::rpc;
+
+        let stream_kind = StreamKind::SnarkWorker;
+        let id = StreamByKindIdx {
+            stream_kind,
+            id: MessageId(0),
+        };
+        let id = id.chain(vec![]);
+        let mode = rocksdb::IteratorMode::From(&id, rocksdb::Direction::Forward);
+        let ids = self
+            .inner
+            .iterator_cf(self.stream_kind_index(), mode)
+            .filter_map(Self::decode_index::<StreamByKindIdx>)
+            .take_while(move |index| index.stream_kind == stream_kind)
+            .map(|StreamByKindIdx { id, .. }| id);
+
+        // the worker opens a new connection for every rpc, the worker is the process
+        let mut workers = BTreeMap::<(String, u32), SnarkWorker>::new();
+        let mut connections = BTreeMap::<ConnectionId, (String, u32)>::new();
+        // the time the worker fetched the work it computes now
+        let mut fetched = BTreeMap::<(String, u32), SystemTime>::new();
+        for (message_id, msg) in self.fetch_messages_by_indexes(ids) {
+            // the worker sends queries and reads responses
+            let kind = match (msg.brief.as_str(), msg.incoming) {
+                ("get_work", true) => {
+                    let buf = self.fetch_blob(msg.connection_id, msg.offset)?;
+                    // `(spec, public key) option`
+                    match rpc::response_payload(&buf)? {
+                        Some([1, ..]) => SnarkWorkKind::Fetched,
+                        _ => SnarkWorkKind::NoWork,
+                    }
+                }
+                ("submit_work", false) => SnarkWorkKind::Submitted,
+                ("failed_to_generate_snark", false) => SnarkWorkKind::Failed,
+                _ => continue,
+            };
+            let key = match connections.entry(msg.connection_id) {
+                btree_map::Entry::Occupied(v) => v.get().clone(),
+                btree_map::Entry::Vacant(v) => {
+                    let connection = self.fetch_connection(msg.connection_id.0)?;
+                    v.insert((connection.node, connection.info.pid)).clone()
+                }
+            };
+            let compute_time = match kind {
+                SnarkWorkKind::Fetched => {
+                    fetched.insert(key.clone(), msg.timestamp);
+                    None
+                }
+                SnarkWorkKind::NoWork => None,
+                // the worker computes one work at a time
+                SnarkWorkKind::Submitted | SnarkWorkKind::Failed => fetched
+                    .remove(&key)
+                    .and_then(|time| msg.timestamp.duration_since(time).ok()),
+            };
+            let (node, pid) = key.clone();
+            let worker = workers.entry(key).or_insert_with(|| SnarkWorker {
+                pid,
+                node,
+                work: vec![],
+            });
+            worker.work.push(SnarkWork {
+                connection_id: msg.connection_id,
+                message_id,
+                timestamp: msg.timestamp,
+                kind,
+                size: msg.size,
+                compute_time,
+            });
+        }
+
+        Ok(workers.into_values().collect())
+    }
+
+    /// Every bitswap message which mentions the block, in order,
+    /// the response has the time since the request
+    pub fn fetch_bitswap(&self, cid: &str) -> Result<Vec<BitswapEvent>, DbError> {
+        use crate::decode::bitswap;
+
+        // connection id, incoming -> the time of the last want
+        let mut wants = BTreeMap::<(ConnectionId, bool), SystemTime>::new();
+        let mut events = vec![];
+        let hash = NodeIdx::hash(cid);
+        let ids =
+            self.hashed_index_messages::<CidIdx>(self.cid_index(), hash, 0, Direction::Forward);
+        for (message_id, msg) in self.fetch_messages_by_indexes(ids) {
+            let buf = self.fetch_blob(msg.connection_id, msg.offset)?;
+            let kinds = bitswap::find(&buf, cid)?;
+            if kinds.is_empty() {
+                // hash collision
+                continue;
+            }
+            let connection = self.fetch_connection(msg.connection_id.0)?;
+            let want = kinds.iter().any(|kind| {
+                matches!(
+                    kind,
+                    MessageType::BitswapWantHave | MessageType::BitswapWantBlock
+                )
+            });
+            let round_trip = if want {
+                wants.insert((msg.connection_id, msg.incoming), msg.timestamp);
+                None
+            } else {
+                wants
+                    .get(&(msg.connection_id, !msg.incoming))
+                    .and_then(|time| msg.timestamp.duration_since(*time).ok())
+            };
+            events.push(BitswapEvent {
+                message_id,
+                connection_id: msg.connection_id,
+                remote_addr: connection.info.addr,
+                incoming: msg.incoming,
+                timestamp: msg.timestamp,
+                kinds,
+                round_trip,
+            });
+        }
+
+        Ok(events)
+    }
+
     pub fn fetch_full_message(&self, id: u64) -> Result<FullMessage, DbError> {
         let msg = self.get::<Message, _>(self.messages(), id.to_be_bytes())?;
         self.fetch_details_inner(msg, false)
@@ -936,6 +1473,196 @@ impl DbCore {
             })
     }
 
+    pub fn fetch_tcp_info(&self, id: ConnectionId) -> Vec<TcpInfoSample> {
+        let id_bytes = id.0.to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&id_bytes, rocksdb::Direction::Forward);
+        self.inner
+            .iterator_cf(self.tcp_info(), mode)
+            .filter_map(Self::decode::<TcpInfoDbKey, TcpInfoSample>)
+            .take_while(|(key, _)| key.connection_id == id)
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// Every change of the protocols of the peer, the last one holds the current set
+    pub fn fetch_peer_protocols(
+        &self,
+        peer_id: &str,
+        direction: Direction,
+    ) -> impl Iterator<Item = PeerProtocols> + '_ {
+        let peer = NodeIdx::hash(peer_id);
+        let start = match direction {
+            Direction::Forward => 0,
+            Direction::Reverse => u64::MAX,
+        };
+        let key = PeerProtocolsDbKey {
+            peer,
+            id: MessageId(start),
+        };
+        let key = key.chain(vec![]);
+        let mode = rocksdb::IteratorMode::From(&key, direction.into());
+        let peer_id = peer_id.to_owned();
+        self.inner
+            .iterator_cf(self.peer_protocols(), mode)
+            .filter_map(Self::decode::<PeerProtocolsDbKey, PeerProtocols>)
+            .take_while(move |(key, _)| key.peer == peer)
+            // hash collision
+            .filter(move |(_, v)| v.peer_id == peer_id)
+            .map(|(_, v)| v)
+    }
+
+    fn rpc_calls_iter<'a>(&'a self, params: &'a RpcParams) -> impl Iterator<Item = RpcCall> + 'a {
+        let start = params.start().to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, params.direction().into());
+        self.inner
+            .iterator_cf(self.rpc_calls(), mode)
+            .filter_map(Self::decode::<u64, RpcCall>)
+            .map(|(_, v)| v)
+            .filter(|v| params.matches(v))
+    }
+
+    /// The calls paired with their responses, by the id of the response
+    pub fn fetch_rpc_calls(&self, params: &RpcParams) -> Vec<RpcCall> {
+        self.rpc_calls_iter(params).take(params.limit()).collect()
+    }
+
+    pub fn fetch_helper_logs(&self, params: &HelperLogParams) -> Vec<HelperLog> {
+        let start = params.start().to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, params.direction().into());
+        let it = self
+            .inner
+            .iterator_cf(self.helper_logs(), mode)
+            .filter_map(Self::decode::<HelperLogDbKey, HelperLog>)
+            .map(|(_, v)| v)
+            .filter(|v| params.matches(v));
+        params.limit(it).collect()
+    }
+
+    /// The incoming meshsub message which delivered the gossip,
+    /// prefer the message from the peer the helper reports as the sender
+    fn gossip_message(&self, v: &GossipValidation) -> Result<Option<MessageId>, DbError> {
+        let mut first = None;
+        let cf = self.gossip_hash_index();
+        let ids = self.hashed_index_messages::<GossipHashIdx>(cf, v.hash, 0, Direction::Forward);
+        for (id, msg) in self.fetch_messages_by_indexes(ids) {
+            if !msg.incoming {
+                continue;
+            }
+            let connection = match self.fetch_connection(msg.connection_id.0) {
+                Ok(v) => v,
+                // the connection is not stored yet, or is lost
+                Err(DbError::NoItemAtCursor(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            if connection.remote_peer_id == v.peer_id {
+                return Ok(Some(MessageId(id)));
+            }
+            first.get_or_insert(MessageId(id));
+        }
+
+        Ok(first)
+    }
+
+    fn gossip_validations_iter<'a>(
+        &'a self,
+        params: &'a GossipValidationParams,
+    ) -> impl Iterator<Item = GossipValidation> + 'a {
+        let start = params.start().to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, params.direction().into());
+        self.inner
+            .iterator_cf(self.gossip_validations(), mode)
+            .filter_map(Self::decode::<GossipValidationKey, GossipValidation>)
+            .map(|(_, v)| v)
+            .filter(|v| params.matches(v))
+    }
+
+    /// The verdicts of the daemon with the meshsub message which delivered the gossip
+    pub fn fetch_gossip_validations(
+        &self,
+        params: &GossipValidationParams,
+    ) -> Result<Vec<GossipValidationEvent>, DbError> {
+        params
+            .limit(self.gossip_validations_iter(params))
+            .map(|validation| {
+                Ok(GossipValidationEvent {
+                    message_id: self.gossip_message(&validation)?,
+                    validation,
+                })
+            })
+            .collect()
+    }
+
+    /// The peers whose gossip the daemon rejected, most rejected first
+    pub fn fetch_rejected_gossip(&self, params: &GossipValidationParams) -> Vec<RejectedGossip> {
+        let peers = params.range(self.gossip_validations_iter(params)).fold(
+            BTreeMap::<String, RejectedGossip>::new(),
+            |mut acc, v| {
+                let peer = acc.entry(v.peer_id.clone()).or_default();
+                peer.total += 1;
+                if v.result == ValidationResult::Reject {
+                    peer.rejected += 1;
+                    *peer.kinds.entry(v.kind.to_string()).or_default() += 1;
+                }
+                acc
+            },
+        );
+        let mut peers = peers
+            .into_iter()
+            .filter(|(_, v)| v.rejected != 0)
+            .map(|(peer_id, v)| RejectedGossip { peer_id, ..v })
+            .collect::<Vec<_>>();
+        peers.sort_by(|a, b| b.rejected.cmp(&a.rejected));
+        peers
+    }
+
+    /// Every message between the daemon and the helper, decoded
+    pub fn fetch_ipc(&self, params: &IpcParams) -> Vec<IpcEvent> {
+        let start = params.start().to_be_bytes();
+        let mode = rocksdb::IteratorMode::From(&start, params.direction().into());
+        let it = self
+            .inner
+            .iterator_cf(self.ipc(), mode)
+            .filter_map(Self::decode::<IpcMessageKey, IpcMessageWithMetadata>)
+            .filter_map(|(k, v)| match ipc::decode(k.incoming, &v.bytes) {
+                Ok(message) => Some(IpcEvent {
+                    time: k.time,
+                    real_time: v.real_time,
+                    pid: k.pid,
+                    node_address: v.node_address,
+                    incoming: k.incoming,
+                    message,
+                }),
+                Err(err) => {
+                    log::error!("key {k}, err: {err}");
+                    None
+                }
+            })
+            .filter(|v| params.matches(v));
+        params.limit(it).collect()
+    }
+
+    /// Latency of the calls `fetch_rpc_calls` gives, grouped by the tag
+    pub fn fetch_rpc_latency(&self, params: &RpcParams) -> BTreeMap<String, RpcLatency> {
+        let calls = self.rpc_calls_iter(params).take(params.limit());
+        calls.fold(BTreeMap::<String, RpcLatency>::new(), |mut acc, call| {
+            acc.entry(call.tag.clone()).or_default().add(&call);
+            acc
+        })
+    }
+
+    pub fn fetch_noise_diagnostics(
+        &self,
+        id: ConnectionId,
+    ) -> Result<Option<NoiseDiagnostics>, DbError> {
+        match self
+            .inner
+            .get_cf(self.noise_diagnostics(), id.0.to_be_bytes())?
+        {
+            None => Ok(None),
+            Some(v) => Ok(Some(AbsorbExt::absorb_ext(&v)?)),
+        }
+    }
+
     pub fn fetch_stats_block_v2(&self, id: u32) -> Vec<meshsub_stats::Event> {
         let id_bytes = id.to_be_bytes();
         let mode = rocksdb::IteratorMode::From(&id_bytes, rocksdb::Direction::Forward);
@@ -1239,3 +1966,62 @@ fn duplicates_removed() {
     assert_eq!(result.next().unwrap().events.len(), 1);
     assert!(result.next().is_none());
 }
+
+#[cfg(test)]
+#[test]
+fn rejected_gossip() {
+    use temp_dir::TempDir;
+    use self::{
+        MessageType::{PublishNewState, PublishSnarkPoolDiff, PublishTransactionPoolDiff},
+        ValidationResult::{Accept, Ignore, Reject},
+    };
+
+    let d = TempDir::new().expect("cannot create temporary directory");
+    let db = DbCore::open(d.path()).unwrap();
+
+    let now = SystemTime::now();
+    let verdicts = [
+        ("peer_a", PublishNewState, Reject),
+        ("peer_a", PublishNewState, Accept),
+        ("peer_b", PublishTransactionPoolDiff, Reject),
+        ("peer_b", PublishTransactionPoolDiff, Reject),
+        ("peer_c", PublishSnarkPoolDiff, Ignore),
+    ];
+    for (i, (peer_id, kind, result)) in verdicts.into_iter().enumerate() {
+        let time = now + Duration::from_millis(i as u64);
+        let key = GossipValidationKey {
+            time,
+            pid: 1,
+            validation_id: i as u64,
+        };
+        let value = GossipValidation {
+            validation_id: i as u64,
+            received_time: time,
+            time,
+            latency: Duration::ZERO,
+            pid: 1,
+            node_address: "0.0.0.0:0".parse().unwrap(),
+            peer_id: peer_id.to_owned(),
+            peer_host: String::new(),
+            peer_port: 0,
+            topic: String::new(),
+            kind,
+            hash: Hash([i as u8; 32]),
+            result,
+        };
+        db.put_gossip_validation(key, value).unwrap();
+    }
+
+    let rejected = db.fetch_rejected_gossip(&GossipValidationParams::default());
+    let rejected = rejected
+        .iter()
+        .map(|v| (v.peer_id.as_str(), v.rejected, v.total))
+        .collect::<Vec<_>>();
+    assert_eq!(rejected, [("peer_b", 2, 2), ("peer_a", 1, 2)]);
+
+    let params = serde_json::from_str(r#"{"result":"reject","peer":"peer_a"}"#).unwrap();
+    let validations = db.fetch_gossip_validations(&params).unwrap();
+    assert_eq!(validations.len(), 1);
+    assert_eq!(validations[0].validation.validation_id, 0);
+    assert!(validations[0].message_id.is_none());
+}
@@ -1,8 +1,8 @@
 use std::net::SocketAddr;
 
-use radiation::{Absorb, Emit};
+use radiation::{Absorb, AbsorbExt, Emit};
 
-use crate::{decode::MessageType, custom_coding};
+use crate::{decode::MessageType, meshsub_stats::Hash, custom_coding};
 use super::types::{ConnectionId, MessageId, StreamFullId, StreamKind};
 
 #[derive(Absorb, Emit)]
@@ -13,6 +13,91 @@ pub struct AddressIdx {
     pub id: MessageId,
 }
 
+/// The index of the messages by the hash of something they mention, the hash goes first,
+/// so the messages which mention the same thing are contiguous and ordered by id
+pub trait HashedIdx: Emit<Vec<u8>> + for<'pa> AbsorbExt<'pa> {
+    type Hash: Copy + PartialEq + 'static;
+
+    fn new(hash: Self::Hash, id: MessageId) -> Self;
+
+    fn hash(&self) -> Self::Hash;
+
+    fn id(&self) -> MessageId;
+}
+
+/// Defines the index of the messages whose first field is the hash and implements `HashedIdx`
+macro_rules! hashed_idx {
+    ($($(#[$meta:meta])* $name:ident { $(#[$hash_meta:meta])* $hash:ident: $ty:ty })*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Absorb, Emit)]
+            pub struct $name {
+                $(#[$hash_meta])*
+                pub $hash: $ty,
+                pub id: MessageId,
+            }
+
+            impl HashedIdx for $name {
+                type Hash = $ty;
+
+                fn new($hash: $ty, id: MessageId) -> Self {
+                    $name { $hash, id }
+                }
+
+                fn hash(&self) -> $ty {
+                    self.$hash
+                }
+
+                fn id(&self) -> MessageId {
+                    self.id
+                }
+            }
+        )*
+    };
+}
+
+hashed_idx! {
+    /// The node which sent or received the message
+    NodeIdx {
+        /// See `NodeIdx::hash`
+        node: u64
+    }
+    /// The git commit the peer reports in its node status
+    GitCommitIdx {
+        /// See `NodeIdx::hash`
+        git_commit: u64
+    }
+    /// The sync status the peer reports in its node status
+    SyncStatusIdx {
+        /// See `NodeIdx::hash`
+        sync_status: u64
+    }
+    /// The block the bitswap message mentions
+    CidIdx {
+        /// See `NodeIdx::hash`, the text form of the cid is hashed
+        cid: u64
+    }
+    /// The peer advertised in the peer exchange message
+    AdvertisedPeerIdx {
+        /// See `NodeIdx::hash`, the peer id is hashed
+        peer: u64
+    }
+    /// The gossip message the meshsub message publishes
+    GossipHashIdx {
+        hash: Hash
+    }
+}
+
+impl NodeIdx {
+    /// The key must have fixed size prefix, so the name of the node is hashed
+    pub fn hash(node: &str) -> u64 {
+        use sha2::{Digest, Sha256};
+
+        let hash = Sha256::digest(node.as_bytes());
+        u64::from_be_bytes(hash[..8].try_into().expect("cannot fail"))
+    }
+}
+
 #[derive(Absorb, Emit)]
 pub struct ConnectionIdx {
     pub connection_id: ConnectionId,
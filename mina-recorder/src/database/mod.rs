@@ -1,14 +1,19 @@
 mod types;
 pub use self::types::{
     StreamKind, StreamId, ConnectionId, ConnectionStats, FullMessage, CapnpEventWithMetadata,
-    CapnpEventWithMetadataKey,
+    CapnpEventWithMetadataKey, TcpInfoSample, NoiseDiagnostics, NoiseFailure, NoiseStage,
+    BitswapEvent, PeerProtocols, PeerExchangeEvent, MessageId, RpcCall, SnarkWorker, SnarkWork,
+    SnarkWorkKind, HelperLog, HelperLogDbKey, IpcMessageKey, IpcMessageWithMetadata, IpcEvent,
+    GossipValidationKey, GossipValidation, GossipValidationEvent, RejectedGossip,
 };
 
 mod rocksdb;
 pub use self::rocksdb::{DbFacade, DbGroup, DbStream, DbStrace};
 
 mod params;
-pub use self::params::Params;
+pub use self::params::{
+    Params, Direction, RpcParams, HelperLogParams, IpcParams, GossipValidationParams,
+};
 
 mod index;
 pub use self::index::LedgerHash;
@@ -6,7 +6,10 @@ use thiserror::Error;
 
 use crate::decode::MessageType;
 
-use super::types::{ConnectionId, StreamFullId, StreamKind, Timestamp};
+use super::types::{
+    ConnectionId, StreamFullId, StreamKind, Timestamp, RpcCall, HelperLog, IpcEvent,
+    GossipValidation,
+};
 
 #[derive(Debug, Error)]
 pub enum ParamsCoordinateValidateError {
@@ -39,10 +42,16 @@ pub struct ValidParams {
     pub coordinate: ValidParamsCoordinate,
     pub stream_filter: Option<StreamFilter>,
     pub kind_filter: Option<KindFilter>,
+    pub node: Option<String>,
+    pub git_commit: Option<String>,
+    pub sync_status: Option<String>,
+    pub cid: Option<String>,
+    pub advertised_peer: Option<String>,
 }
 
 pub struct ValidParamsConnection {
     pub coordinate: ValidParamsCoordinate,
+    pub node: Option<String>,
 }
 
 pub enum Coordinate {
@@ -82,6 +91,287 @@ pub struct Params {
     stream_id: Option<String>,
     stream_kind: Option<String>,
     message_kind: Option<String>,
+    // filter by the container or pod of the debuggee
+    node: Option<String>,
+    // filter node status responses by what the peer reports
+    git_commit: Option<String>,
+    sync_status: Option<String>,
+    // filter bitswap messages by the block
+    cid: Option<String>,
+    // filter peer exchange messages by the peer id they advertise
+    advertised_peer: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+pub struct RpcParams {
+    // the start of the list, id of the response
+    id: Option<u64>,
+    // wether go `forward` or `reverse`, default is `forward`
+    #[serde(default)]
+    direction: Direction,
+    // how many calls to read, default is 16
+    limit: Option<usize>,
+    // comma separated tags of the rpc
+    tag: Option<String>,
+    // the address or the peer id of the remote peer
+    peer: Option<String>,
+    // only the calls which take longer
+    min_latency_ms: Option<u64>,
+}
+
+impl RpcParams {
+    pub fn start(&self) -> u64 {
+        match (self.id, self.direction) {
+            (Some(id), _) => id,
+            (None, Direction::Forward) => 0,
+            (None, Direction::Reverse) => u64::MAX,
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(16)
+    }
+
+    pub fn matches(&self, call: &RpcCall) -> bool {
+        if let Some(tag) = &self.tag {
+            if !tag.split(',').any(|tag| tag == call.tag) {
+                return false;
+            }
+        }
+        if let Some(peer) = &self.peer {
+            if *peer != call.remote_peer_id && *peer != call.remote_addr.to_string() {
+                return false;
+            }
+        }
+        if let Some(min_latency_ms) = self.min_latency_ms {
+            if call.latency.as_millis() < min_latency_ms as u128 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct HelperLogParams {
+    // the start of the list, timestamp in seconds
+    timestamp: Option<u64>,
+    // wether go `forward` or `reverse`, default is `forward`
+    #[serde(default)]
+    direction: Direction,
+    // how many lines to read, default is 16
+    // if `limit_timestamp` is specified, default limit is `usize::MAX`
+    limit: Option<usize>,
+    limit_timestamp: Option<u64>,
+    // comma separated levels, `info`, `warn`, `error`, etc.
+    level: Option<String>,
+    // filter by the container or pod of the debuggee
+    node: Option<String>,
+}
+
+impl HelperLogParams {
+    /// Seconds since the unix epoch, the end of the list if not specified
+    pub fn start(&self) -> u64 {
+        time_start(self.timestamp, self.direction)
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn matches(&self, v: &HelperLog) -> bool {
+        if let Some(level) = &self.level {
+            if !level
+                .split(',')
+                .any(|level| level.eq_ignore_ascii_case(&v.level))
+            {
+                return false;
+            }
+        }
+        if let Some(node) = &self.node {
+            if *node != v.node {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn limit<'a, It>(&self, it: It) -> impl Iterator<Item = HelperLog> + 'a
+    where
+        It: Iterator<Item = HelperLog> + 'a,
+    {
+        let coordinate = time_coordinate(
+            self.start(),
+            self.direction,
+            self.limit,
+            self.limit_timestamp,
+        );
+        coordinate.limit(it.map(|v| (0, v))).map(|(_, v)| v)
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct IpcParams {
+    // the start of the list, timestamp in seconds
+    timestamp: Option<u64>,
+    // wether go `forward` or `reverse`, default is `forward`
+    #[serde(default)]
+    direction: Direction,
+    // how many messages to read, default is 16
+    // if `limit_timestamp` is specified, default limit is `usize::MAX`
+    limit: Option<usize>,
+    limit_timestamp: Option<u64>,
+    // comma separated kinds, `add_peer`, `validation`, `peer_connected`, etc.
+    kind: Option<String>,
+    // the process of the libp2p_helper
+    pid: Option<u32>,
+}
+
+impl IpcParams {
+    /// Seconds since the unix epoch, the end of the list if not specified
+    pub fn start(&self) -> u64 {
+        time_start(self.timestamp, self.direction)
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn matches(&self, v: &IpcEvent) -> bool {
+        if let Some(kind) = &self.kind {
+            if !kind.split(',').any(|kind| kind == v.message.kind()) {
+                return false;
+            }
+        }
+        if let Some(pid) = self.pid {
+            if pid != v.pid {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn limit<'a, It>(&self, it: It) -> impl Iterator<Item = IpcEvent> + 'a
+    where
+        It: Iterator<Item = IpcEvent> + 'a,
+    {
+        let coordinate = time_coordinate(
+            self.start(),
+            self.direction,
+            self.limit,
+            self.limit_timestamp,
+        );
+        coordinate.limit(it.map(|v| (0, v))).map(|(_, v)| v)
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct GossipValidationParams {
+    // the start of the list, timestamp in seconds
+    timestamp: Option<u64>,
+    // wether go `forward` or `reverse`, default is `forward`
+    #[serde(default)]
+    direction: Direction,
+    // how many verdicts to read, default is 16
+    // if `limit_timestamp` is specified, default limit is `usize::MAX`
+    limit: Option<usize>,
+    limit_timestamp: Option<u64>,
+    // comma separated, `accept`, `reject` or `ignore`
+    result: Option<String>,
+    // the peer id of the peer which forwarded the gossip
+    peer: Option<String>,
+    // the process of the libp2p_helper
+    pid: Option<u32>,
+}
+
+impl GossipValidationParams {
+    /// Seconds since the unix epoch, the end of the list if not specified
+    pub fn start(&self) -> u64 {
+        time_start(self.timestamp, self.direction)
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn matches(&self, v: &GossipValidation) -> bool {
+        if let Some(result) = &self.result {
+            if !result
+                .split(',')
+                .any(|result| result == v.result.to_string())
+            {
+                return false;
+            }
+        }
+        if let Some(peer) = &self.peer {
+            if *peer != v.peer_id {
+                return false;
+            }
+        }
+        if let Some(pid) = self.pid {
+            if pid != v.pid {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn limit<'a, It>(&self, it: It) -> impl Iterator<Item = GossipValidation> + 'a
+    where
+        It: Iterator<Item = GossipValidation> + 'a,
+    {
+        let coordinate = time_coordinate(
+            self.start(),
+            self.direction,
+            self.limit,
+            self.limit_timestamp,
+        );
+        coordinate.limit(it.map(|v| (0, v))).map(|(_, v)| v)
+    }
+
+    /// Every verdict until `limit_timestamp`, the `limit` is ignored
+    pub fn range<'a, It>(&self, it: It) -> impl Iterator<Item = GossipValidation> + 'a
+    where
+        It: Iterator<Item = GossipValidation> + 'a,
+    {
+        let coordinate = time_coordinate(
+            self.start(),
+            self.direction,
+            Some(usize::MAX),
+            self.limit_timestamp,
+        );
+        coordinate.limit(it.map(|v| (0, v))).map(|(_, v)| v)
+    }
+}
+
+fn time_start(timestamp: Option<u64>, direction: Direction) -> u64 {
+    match (timestamp, direction) {
+        (Some(timestamp), _) => timestamp,
+        (None, Direction::Forward) => 0,
+        (None, Direction::Reverse) => u64::MAX,
+    }
+}
+
+fn time_coordinate(
+    start: u64,
+    direction: Direction,
+    limit: Option<usize>,
+    limit_timestamp: Option<u64>,
+) -> ValidParamsCoordinate {
+    ValidParamsCoordinate {
+        start: Coordinate::ByTimestamp(start),
+        limit: if limit_timestamp.is_some() {
+            limit.unwrap_or(usize::MAX)
+        } else {
+            limit.unwrap_or(16)
+        },
+        limit_timestamp,
+        direction,
+    }
 }
 
 #[derive(Default, Clone, Copy, Deserialize)]
@@ -156,7 +446,10 @@ impl Params {
         self,
     ) -> Result<ValidParamsConnection, ParamsCoordinateValidateError> {
         let coordinate = self.validate_coordinate()?;
-        Ok(ValidParamsConnection { coordinate })
+        Ok(ValidParamsConnection {
+            coordinate,
+            node: self.node,
+        })
     }
 
     pub fn validate(self) -> Result<ValidParams, ParamsValidateError> {
@@ -201,6 +494,11 @@ impl Params {
             coordinate,
             stream_filter,
             kind_filter,
+            node: self.node,
+            git_commit: self.git_commit,
+            sync_status: self.sync_status,
+            cid: self.cid,
+            advertised_peer: self.advertised_peer,
         })
     }
 }
@@ -19,6 +19,7 @@ use crate::{
     chunk::{ChunkHeader, EncryptionStatus},
     decode::{
         MessageType,
+        identify::Protocols,
         meshsub_stats::{BlockStat, TxStat},
     },
     strace::StraceLine,
@@ -28,8 +29,8 @@ use crate::{
 use super::{
     core::{DbCore, DbError},
     types::{
-        Connection, ConnectionId, Message, MessageId, StreamId, StreamKind,
-        ConnectionStats,
+        Connection, ConnectionId, Message, MessageId, StreamId, StreamKind, ConnectionStats,
+        TcpInfoSample, NoiseDiagnostics, RpcCall,
     },
 };
 
@@ -85,6 +86,7 @@ impl DbFacade {
         info: ConnectionInfo,
         incoming: bool,
         alias: String,
+        node: String,
         timestamp: SystemTime,
     ) -> Result<DbGroup, DbError> {
         let id = ConnectionId(self.cns.fetch_add(1, SeqCst));
@@ -97,18 +99,51 @@ impl DbFacade {
             stats_out: ConnectionStats::default(),
             timestamp_close: SystemTime::UNIX_EPOCH,
             alias,
+            timestamp_capture_incomplete: SystemTime::UNIX_EPOCH,
+            joined_mid_stream: false,
+            node: node.clone(),
+            chain_id: String::new(),
+            chain_id_unknown: false,
+            remote_peer_id: String::new(),
+            local_peer_id: String::new(),
         };
         self.inner.put_cn(id, v)?;
         self.inner.set_total::<{ DbCore::CONNECTIONS_CNT }>(id.0)?;
 
         Ok(DbGroup {
             addr,
+            node,
             id,
             messages: self.messages.clone(),
             inner: self.inner.clone(),
         })
     }
 
+    /// Mark the connection as captured incompletely, keeps the earliest time
+    pub fn mark_capture_incomplete(
+        &self,
+        id: ConnectionId,
+        time: SystemTime,
+    ) -> Result<(), DbError> {
+        let mut cn = self.inner.fetch_connection(id.0)?;
+        if cn.timestamp_capture_incomplete == SystemTime::UNIX_EPOCH {
+            cn.timestamp_capture_incomplete = time;
+            self.inner.put_cn(id, cn)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn add_tcp_info(&self, id: ConnectionId, sample: TcpInfoSample) -> Result<(), DbError> {
+        self.inner.put_tcp_info(id, sample)
+    }
+
+    pub fn mark_joined_mid_stream(&self, id: ConnectionId) -> Result<(), DbError> {
+        let mut cn = self.inner.fetch_connection(id.0)?;
+        cn.joined_mid_stream = true;
+        self.inner.put_cn(id, cn)
+    }
+
     pub fn add_randomness(&self, bytes: Vec<u8>) -> Result<(), DbError> {
         let id = self.rnd_cnt.fetch_add(1, SeqCst);
         self.inner.put_randomness(id, bytes)?;
@@ -145,6 +180,7 @@ impl DbStrace {
 #[derive(Clone)]
 pub struct DbGroup {
     addr: SocketAddr,
+    node: String,
     id: ConnectionId,
     messages: Arc<AtomicU64>,
     inner: DbCore,
@@ -172,6 +208,46 @@ impl DbGroup {
         self.inner.put_cn(self.id, cn)
     }
 
+    /// The chain id detected by the pnet, `None` if none of the candidates matches
+    pub fn set_chain_id(&self, chain_id: Option<&str>) -> Result<(), DbError> {
+        let mut cn = self.inner.fetch_connection(self.id.0)?;
+        match chain_id {
+            Some(chain_id) => cn.chain_id = chain_id.to_owned(),
+            None => cn.chain_id_unknown = true,
+        }
+        self.inner.put_cn(self.id, cn)
+    }
+
+    /// The peer id of the sender of the message, remote if `incoming`, otherwise local
+    fn set_peer_id(&self, incoming: bool, peer_id: &str) -> Result<(), DbError> {
+        let mut cn = self.inner.fetch_connection(self.id.0)?;
+        let v = if incoming {
+            &mut cn.remote_peer_id
+        } else {
+            &mut cn.local_peer_id
+        };
+        if v != peer_id {
+            *v = peer_id.to_owned();
+            self.inner.put_cn(self.id, cn)?;
+        }
+
+        Ok(())
+    }
+
+    fn peer_id(&self, incoming: bool) -> Result<String, DbError> {
+        let cn = self.inner.fetch_connection(self.id.0)?;
+        Ok(if incoming {
+            cn.remote_peer_id
+        } else {
+            cn.local_peer_id
+        })
+    }
+
+    /// Why the connection cannot be decrypted
+    pub fn set_noise_diagnostics(&self, v: NoiseDiagnostics) -> Result<(), DbError> {
+        self.inner.put_noise_diagnostics(self.id, v)
+    }
+
     pub fn add_raw(
         &self,
         encryption_status: EncryptionStatus,
@@ -214,6 +290,74 @@ pub struct DbStream {
 }
 
 impl DbStream {
+    /// Pair the stored rpc response with its query
+    pub fn add_rpc_call(
+        &self,
+        did: &DirectedId,
+        response_id: MessageId,
+        bytes: &[u8],
+        request_id: MessageId,
+        request_timestamp: SystemTime,
+        request_size: u32,
+    ) -> Result<(), DbError> {
+        let (tag, version, rpc_id, ok) = crate::decode::rpc::parse_response_header(bytes)?;
+        let cn = self.group.inner.fetch_connection(self.group.id.0)?;
+        let response_timestamp = did.metadata.time;
+        let v = RpcCall {
+            request_id,
+            response_id,
+            connection_id: self.group.id,
+            stream_id: self.s_id,
+            remote_addr: self.group.addr,
+            remote_peer_id: cn.remote_peer_id,
+            incoming: !did.incoming,
+            tag,
+            version,
+            rpc_id,
+            request_timestamp,
+            response_timestamp,
+            latency: response_timestamp
+                .duration_since(request_timestamp)
+                .unwrap_or_default(),
+            request_size,
+            response_size: bytes.len() as u32,
+            ok,
+        };
+        self.group.inner.put_rpc_call(v)
+    }
+
+    /// Track the protocols of the sender of the identify message
+    fn add_protocols(
+        &self,
+        id: MessageId,
+        msg: &Message,
+        kind: MessageType,
+        bytes: &[u8],
+    ) -> Result<(), DbError> {
+        let update = match crate::decode::identify::protocols(bytes) {
+            Ok(v) => v,
+            Err(err) => {
+                log::error!("{}, cannot decode {kind}: {err}", self.group.id);
+                return Ok(());
+            }
+        };
+        if let Protocols::Full {
+            peer_id: Some(peer_id),
+            ..
+        } = &update
+        {
+            self.group.set_peer_id(msg.incoming, peer_id)?;
+        }
+        let peer_id = self.group.peer_id(msg.incoming)?;
+        if peer_id.is_empty() {
+            log::warn!("{}, unknown peer, protocols: {kind}", self.group.id);
+            return Ok(());
+        }
+        self.group
+            .inner
+            .put_peer_protocols(&peer_id, msg, id, kind, update)
+    }
+
     pub fn add(
         &self,
         did: &DirectedId,
@@ -225,23 +369,51 @@ impl DbStream {
         let offset = self.group.add_raw(EncryptionStatus::DecryptedNoise, did.incoming, did.metadata.time, bytes)?;
 
         let mut ledger_hashes = vec![];
+        let mut node_status = None;
+        let mut cids = vec![];
+        let mut peers = vec![];
+        let mut gossip_hashes = vec![];
         let tys = match stream_kind {
             StreamKind::Unknown => vec![],
             StreamKind::Meshsub => {
-                let (tys, hashes) = crate::decode::meshsub::parse_types(bytes, index_ledger_hash)?;
+                let (tys, hashes, gossip) =
+                    crate::decode::meshsub::parse_types(bytes, index_ledger_hash)?;
                 ledger_hashes = hashes;
+                gossip_hashes = gossip;
                 tys
             }
             StreamKind::Kad => crate::decode::kademlia::parse_types(bytes)?,
-            StreamKind::Handshake => crate::decode::noise::parse_types(bytes)?,
-            StreamKind::Rpc => crate::decode::rpc::parse_types(bytes)?,
+            StreamKind::Handshake => {
+                if let Some(peer_id) = crate::decode::noise::peer_id(bytes) {
+                    self.group.set_peer_id(did.incoming, &peer_id)?;
+                }
+                crate::decode::noise::parse_types(bytes)?
+            }
+            StreamKind::Rpc | StreamKind::SnarkWorker => crate::decode::rpc::parse_types(bytes)?,
             StreamKind::IpfsId => vec![MessageType::Identify],
             StreamKind::IpfsPush => vec![MessageType::IdentifyPush],
-            // TODO: message type (types)
-            StreamKind::IpfsDelta => vec![],
-            StreamKind::PeerExchange => vec![MessageType::PeerExchange],
-            StreamKind::BitswapExchange => vec![MessageType::BitswapExchange],
-            StreamKind::NodeStatus => vec![MessageType::NodeStatus],
+            StreamKind::IpfsDelta => vec![MessageType::IdentifyDelta],
+            StreamKind::PeerExchange => {
+                let (tys, p) = crate::decode::peer_exchange::parse_types(bytes)?;
+                peers = p;
+                tys
+            }
+            StreamKind::BitswapExchange => {
+                let (tys, c) = crate::decode::bitswap::parse_types(bytes)?;
+                cids = c;
+                tys
+            }
+            StreamKind::NodeStatus => match crate::decode::node_status::parse_types(bytes) {
+                Ok((tys, status)) => {
+                    node_status = status;
+                    tys
+                }
+                Err(err) => {
+                    // the message is stored anyway, but not indexed
+                    log::warn!("{did} cannot decode node status: {err}");
+                    vec![MessageType::NodeStatus]
+                }
+            },
             StreamKind::Select => vec![MessageType::Select],
             StreamKind::Mplex => vec![MessageType::Mplex],
             StreamKind::Yamux => vec![MessageType::Yamux],
@@ -258,8 +430,17 @@ impl DbStream {
             size: bytes.len() as u32,
             brief: tys.iter().map(|ty| ty.to_string()).join(","),
         };
+        if let StreamKind::IpfsId | StreamKind::IpfsPush | StreamKind::IpfsDelta = stream_kind {
+            self.add_protocols(id, &v, tys[0].clone(), bytes)?;
+        }
         self.group.inner
-            .put_message(&self.group.addr, id, v, tys, ledger_hashes)?;
+            .put_message(&self.group.addr, &self.group.node, id, v, tys, ledger_hashes)?;
+        if let Some(status) = &node_status {
+            self.group.inner.put_node_status_index(id, status)?;
+        }
+        self.group.inner.put_cid_index(id, &cids)?;
+        self.group.inner.put_advertised_peer_index(id, &peers)?;
+        self.group.inner.put_gossip_hash_index(id, &gossip_hashes)?;
         self.group.inner.set_total::<{ DbCore::MESSAGES_CNT }>(id.0)?;
 
         Ok(id)
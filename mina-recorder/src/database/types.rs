@@ -4,6 +4,7 @@ use std::{
     str::FromStr,
     net::SocketAddr,
     ops::AddAssign,
+    collections::BTreeMap,
 };
 
 use mina_p2p_messages::{binprot::BinProtRead, v2, gossip::GossipNetMessageV2};
@@ -13,8 +14,9 @@ use serde::{Serialize, Deserialize};
 
 use crate::{
     event::ConnectionInfo, custom_coding, strace::StraceLine, libp2p_helper::CapnpEvent,
-    meshsub_stats::Hash,
+    meshsub_stats::Hash, decode::MessageType,
 };
+use crate::libp2p_helper::ipc::{IpcMessage, ValidationResult};
 
 #[derive(
     Clone, Copy, Debug, Absorb, Emit, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord,
@@ -46,6 +48,28 @@ pub struct Connection {
     pub timestamp_close: SystemTime,
 
     pub alias: String,
+
+    /// Since this moment some data of the connection was lost by the debugger,
+    /// `UNIX_EPOCH` if the capture is complete
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp_capture_incomplete: SystemTime,
+
+    /// The connection existed before the debugger started tracking the process,
+    /// its data cannot be decrypted, only counted
+    pub joined_mid_stream: bool,
+
+    /// The container or pod of the process, empty if unknown
+    pub node: String,
+
+    /// The chain id whose pnet key decrypts the connection, empty if not detected
+    pub chain_id: String,
+    /// None of the known chain ids decrypts the connection
+    pub chain_id_unknown: bool,
+
+    /// Learned from the noise handshake or identify, empty if not yet known
+    pub remote_peer_id: String,
+    pub local_peer_id: String,
 }
 
 impl Connection {
@@ -136,6 +160,7 @@ pub enum StreamKind {
     NodeStatus = 0x0302,
     Meshsub = 0x0400,
     Rpc = 0x0500,
+    SnarkWorker = 0x0501,
     Select = 0x0600,
     Mplex = 0x0700,
     Yamux = 0x0701,
@@ -154,6 +179,7 @@ impl fmt::Display for StreamKind {
             StreamKind::NodeStatus => write!(f, "/mina/node-status"),
             StreamKind::Meshsub => write!(f, "/meshsub/1.1.0"),
             StreamKind::Rpc => write!(f, "coda/rpcs/0.0.1"),
+            StreamKind::SnarkWorker => write!(f, "snark_worker_rpc"),
             StreamKind::Select => write!(f, "/multistream/1.0.0"),
             StreamKind::Mplex => write!(f, "/coda/mplex/1.0.0"),
             StreamKind::Yamux => write!(f, "/coda/yamux/1.0.0"),
@@ -196,6 +222,7 @@ impl FromStr for StreamKind {
             "/mina/node-status" => Ok(StreamKind::NodeStatus),
             "/meshsub/1.1.0" => Ok(StreamKind::Meshsub),
             "coda/rpcs/0.0.1" => Ok(StreamKind::Rpc),
+            "snark_worker_rpc" => Ok(StreamKind::SnarkWorker),
             "/multistream/1.0.0" => Ok(StreamKind::Select),
             "/coda/mplex/1.0.0" => Ok(StreamKind::Mplex),
             "/coda/yamux/1.0.0" => Ok(StreamKind::Yamux),
@@ -217,6 +244,7 @@ impl StreamKind {
             StreamKind::NodeStatus,
             StreamKind::Meshsub,
             StreamKind::Rpc,
+            StreamKind::SnarkWorker,
             StreamKind::Select,
             StreamKind::Mplex,
             StreamKind::Yamux,
@@ -286,6 +314,8 @@ pub struct Message {
 pub struct FullMessage {
     pub connection_id: ConnectionId,
     pub remote_addr: SocketAddr,
+    #[serde(default)]
+    pub node: String,
     pub incoming: bool,
     pub timestamp: SystemTime,
     pub stream_id: StreamId,
@@ -295,6 +325,221 @@ pub struct FullMessage {
     pub size: u32,
 }
 
+/// The message mentions the block, see `/bitswap/{cid}`
+#[derive(Serialize)]
+pub struct BitswapEvent {
+    pub message_id: u64,
+    pub connection_id: ConnectionId,
+    pub remote_addr: SocketAddr,
+    pub incoming: bool,
+    pub timestamp: SystemTime,
+    pub kinds: Vec<MessageType>,
+    /// Since the want of the block sent in the opposite direction of the same connection
+    pub round_trip: Option<Duration>,
+}
+
+/// The query paired with its response, keyed by the id of the response
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct RpcCall {
+    pub request_id: MessageId,
+    pub response_id: MessageId,
+    pub connection_id: ConnectionId,
+    pub stream_id: StreamId,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub remote_addr: SocketAddr,
+    /// Empty if unknown
+    pub remote_peer_id: String,
+    /// The remote peer is the caller
+    pub incoming: bool,
+    pub tag: String,
+    pub version: i32,
+    pub rpc_id: i64,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub request_timestamp: SystemTime,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub response_timestamp: SystemTime,
+    #[custom_absorb(custom_coding::duration_absorb)]
+    #[custom_emit(custom_coding::duration_emit)]
+    pub latency: Duration,
+    pub request_size: u32,
+    pub response_size: u32,
+    /// The response is not an error
+    pub ok: bool,
+}
+
+impl Timestamp for RpcCall {
+    fn timestamp(&self) -> Duration {
+        self.response_timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("timestamp cannot be earlier the `UNIX_EPOCH`")
+    }
+}
+
+/// Latency of the calls of the same tag
+#[derive(Default, Serialize)]
+pub struct RpcLatency {
+    pub calls: u64,
+    pub errors: u64,
+    pub mean: Duration,
+    pub max: Duration,
+    #[serde(skip)]
+    total: Duration,
+    pub histogram: Vec<RpcLatencyBucket>,
+}
+
+#[derive(Serialize)]
+pub struct RpcLatencyBucket {
+    /// The upper bound, `None` is infinity
+    pub le_ms: Option<u64>,
+    pub calls: u64,
+}
+
+impl RpcLatency {
+    const BUCKETS_MS: [u64; 14] = [
+        1, 2, 5, 10, 20, 50, 100, 200, 500, 1_000, 2_000, 5_000, 10_000, 30_000,
+    ];
+
+    pub fn add(&mut self, call: &RpcCall) {
+        if self.histogram.is_empty() {
+            self.histogram = Self::BUCKETS_MS
+                .iter()
+                .copied()
+                .map(Some)
+                .chain(Some(None))
+                .map(|le_ms| RpcLatencyBucket { le_ms, calls: 0 })
+                .collect();
+        }
+        let ms = call.latency.as_millis();
+        let bucket = self
+            .histogram
+            .iter_mut()
+            .find(|b| b.le_ms.map_or(true, |le_ms| ms <= le_ms as u128))
+            .expect("the last bucket is infinite");
+        bucket.calls += 1;
+
+        self.calls += 1;
+        self.total += call.latency;
+        self.mean = self.total.div_f64(self.calls as f64);
+        self.max = self.max.max(call.latency);
+        if !call.ok {
+            self.errors += 1;
+        }
+    }
+}
+
+#[derive(Emit, Absorb)]
+pub struct HelperLogDbKey {
+    #[custom_emit(custom_coding::time_emit)]
+    #[custom_absorb(custom_coding::time_absorb)]
+    pub time: SystemTime,
+    pub pid: u32,
+    pub seq: u32,
+}
+
+impl fmt::Display for HelperLogDbKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {} {}", self.time, self.pid, self.seq)
+    }
+}
+
+/// The line the libp2p_helper writes to stderr
+#[derive(Absorb, Emit, Serialize)]
+pub struct HelperLog {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub pid: u32,
+    pub node: String,
+    /// Lowercase, empty if the line is not JSON
+    pub level: String,
+    pub module: String,
+    pub message: String,
+    pub line: String,
+}
+
+impl Timestamp for HelperLog {
+    fn timestamp(&self) -> Duration {
+        self.timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("timestamp cannot be earlier the `UNIX_EPOCH`")
+    }
+}
+
+#[derive(Serialize)]
+pub struct SnarkWorker {
+    pub pid: u32,
+    pub node: String,
+    pub work: Vec<SnarkWork>,
+}
+
+#[derive(Serialize)]
+pub struct SnarkWork {
+    /// The worker opens a new connection for every rpc
+    pub connection_id: ConnectionId,
+    pub message_id: u64,
+    pub timestamp: SystemTime,
+    pub kind: SnarkWorkKind,
+    pub size: u32,
+    /// Since the work it computed was fetched, for the submitted or failed work
+    pub compute_time: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnarkWorkKind {
+    Fetched,
+    /// The daemon has no work for the worker
+    NoWork,
+    Submitted,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct PeerExchangeEvent {
+    pub message_id: u64,
+    pub connection_id: ConnectionId,
+    pub remote_addr: SocketAddr,
+    pub incoming: bool,
+    pub timestamp: SystemTime,
+    /// The peer id of the sender of the message, empty if unknown
+    pub advertiser: String,
+    pub addrs: Vec<String>,
+    pub unreachable_addrs: Vec<String>,
+}
+
+#[derive(Emit, Absorb)]
+pub struct PeerProtocolsDbKey {
+    /// See `NodeIdx::hash`, the peer id is hashed
+    pub peer: u64,
+    pub id: MessageId,
+}
+
+impl fmt::Display for PeerProtocolsDbKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.peer, self.id.0)
+    }
+}
+
+/// The set of protocols the peer supports, as it changes
+/// by the identify, identify push and identify delta messages
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct PeerProtocols {
+    pub peer_id: String,
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub timestamp: SystemTime,
+    pub message_id: MessageId,
+    pub connection_id: ConnectionId,
+    pub kind: MessageType,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// The whole set after the change
+    pub protocols: Vec<String>,
+}
+
 pub trait Timestamp {
     fn timestamp(&self) -> Duration;
 }
@@ -438,6 +683,91 @@ pub struct StatsDbKey {
     pub node_address: SocketAddr,
 }
 
+#[derive(Emit, Absorb)]
+pub struct TcpInfoDbKey {
+    pub connection_id: ConnectionId,
+    #[custom_emit(custom_coding::time_emit)]
+    #[custom_absorb(custom_coding::time_absorb)]
+    pub time: SystemTime,
+}
+
+/// The state of the socket reported by the kernel `tcp_info`,
+/// times are in microseconds
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct TcpInfoSample {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    pub state: u8,
+    pub retransmits: u8,
+    pub rto: u32,
+    pub snd_mss: u32,
+    pub unacked: u32,
+    pub lost: u32,
+    pub rtt: u32,
+    pub rttvar: u32,
+    pub min_rtt: u32,
+    pub snd_ssthresh: u32,
+    pub snd_cwnd: u32,
+    pub total_retrans: u32,
+    pub bytes_acked: u64,
+    pub bytes_received: u64,
+    /// Bytes per second
+    pub delivery_rate: u64,
+}
+
+/// Why the connection cannot be decrypted, written at the first failure
+#[derive(Clone, Absorb, Emit, Serialize)]
+pub struct NoiseDiagnostics {
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    pub reason: NoiseFailure,
+    /// The message which failed
+    pub stage: NoiseStage,
+    /// Direction of the message which failed
+    pub incoming: bool,
+    /// Unknown at the `pnet` stage
+    pub initiator_is_incoming: bool,
+    /// The public keys whose secret keys were searched in the randomness, hex
+    pub tried_keys: Vec<String>,
+    /// How many values of the randomness were checked
+    pub randomness_candidates: u64,
+    /// The nonce of the first transport message which failed, zero if the handshake failed
+    pub failed_nonce: u64,
+    /// Bytes decrypted before the failure
+    pub decrypted_bytes: u64,
+}
+
+#[derive(Clone, Copy, Absorb, Emit, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseFailure {
+    /// The pnet decrypted something which is not multistream-select, the chain id is wrong
+    WrongChainId,
+    /// The debugger did not capture any randomness of the process
+    NoRandomness,
+    /// The handshake message came from the wrong side,
+    /// usually the beginning of the connection is lost
+    HandshakeOutOfOrder,
+    /// None of the randomness is the secret key of the peer's public key
+    UnknownKey,
+    /// The size of the handshake message is wrong
+    UnexpectedSize,
+    MacMismatch,
+    /// The handshake is done, but the transport message cannot be decrypted
+    CannotDecrypt,
+}
+
+#[derive(Clone, Copy, Absorb, Emit, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseStage {
+    Pnet,
+    FirstMessage,
+    SecondMessage,
+    ThirdMessage,
+    Transport,
+}
+
 #[derive(Emit, Absorb)]
 pub struct StatsV2DbKey {
     pub height: u32,
@@ -466,6 +796,20 @@ impl fmt::Display for StatsV2DbKey {
     }
 }
 
+impl fmt::Display for TcpInfoDbKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.connection_id,
+            self.time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("timestamp cannot be earlier the `UNIX_EPOCH`")
+                .as_nanos()
+        )
+    }
+}
+
 #[derive(Emit, Absorb)]
 pub struct CapnpEventWithMetadataKey {
     pub height: u32,
@@ -507,6 +851,131 @@ pub enum GossipNetMessageV2Short {
     },
 }
 
+#[derive(Emit, Absorb)]
+pub struct IpcMessageKey {
+    #[custom_emit(custom_coding::time_emit)]
+    #[custom_absorb(custom_coding::time_absorb)]
+    pub time: SystemTime,
+    pub pid: u32,
+    /// The helper reads the message, it is sent by the daemon
+    pub incoming: bool,
+    pub seq: u32,
+}
+
+impl fmt::Display for IpcMessageKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {} {}", self.time, self.pid, self.seq)
+    }
+}
+
+/// The capnp message as captured, decoded on read
+#[derive(Emit, Absorb)]
+pub struct IpcMessageWithMetadata {
+    #[custom_emit(custom_coding::time_emit)]
+    #[custom_absorb(custom_coding::time_absorb)]
+    pub real_time: SystemTime,
+    #[custom_emit(custom_coding::addr_emit)]
+    #[custom_absorb(custom_coding::addr_absorb)]
+    pub node_address: SocketAddr,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Serialize)]
+pub struct IpcEvent {
+    pub time: SystemTime,
+    pub real_time: SystemTime,
+    pub pid: u32,
+    pub node_address: SocketAddr,
+    pub incoming: bool,
+    pub message: IpcMessage,
+}
+
+impl Timestamp for IpcEvent {
+    fn timestamp(&self) -> Duration {
+        self.time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("timestamp cannot be earlier the `UNIX_EPOCH`")
+    }
+}
+
+#[derive(Emit, Absorb)]
+pub struct GossipValidationKey {
+    #[custom_emit(custom_coding::time_emit)]
+    #[custom_absorb(custom_coding::time_absorb)]
+    pub time: SystemTime,
+    pub pid: u32,
+    pub validation_id: u64,
+}
+
+impl fmt::Display for GossipValidationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {} {}", self.time, self.pid, self.validation_id)
+    }
+}
+
+/// The verdict of the daemon on the gossip the helper received
+#[derive(Absorb, Emit, Serialize)]
+pub struct GossipValidation {
+    pub validation_id: u64,
+    /// The helper passed the gossip to the daemon
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub received_time: SystemTime,
+    /// The daemon sent the verdict
+    #[custom_absorb(custom_coding::time_absorb)]
+    #[custom_emit(custom_coding::time_emit)]
+    pub time: SystemTime,
+    #[custom_absorb(custom_coding::duration_absorb)]
+    #[custom_emit(custom_coding::duration_emit)]
+    pub latency: Duration,
+    pub pid: u32,
+    #[custom_absorb(custom_coding::addr_absorb)]
+    #[custom_emit(custom_coding::addr_emit)]
+    pub node_address: SocketAddr,
+    /// The peer which forwarded the gossip
+    pub peer_id: String,
+    pub peer_host: String,
+    pub peer_port: u16,
+    pub topic: String,
+    pub kind: MessageType,
+    /// The id of the gossip message, the same as of the `publish_v2` of the meshsub
+    pub hash: Hash,
+    pub result: ValidationResult,
+}
+
+impl Timestamp for GossipValidation {
+    fn timestamp(&self) -> Duration {
+        self.time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("timestamp cannot be earlier the `UNIX_EPOCH`")
+    }
+}
+
+#[derive(Serialize)]
+pub struct GossipValidationEvent {
+    #[serde(flatten)]
+    pub validation: GossipValidation,
+    /// The meshsub message which delivered the gossip, `None` if not captured
+    pub message_id: Option<MessageId>,
+}
+
+impl Timestamp for GossipValidationEvent {
+    fn timestamp(&self) -> Duration {
+        self.validation.timestamp()
+    }
+}
+
+/// The peer which forwarded the gossip the daemon rejected
+#[derive(Default, Serialize)]
+pub struct RejectedGossip {
+    pub peer_id: String,
+    pub rejected: u64,
+    /// Every verdict on the gossip of the peer
+    pub total: u64,
+    /// The rejected gossip by the kind, `publish_new_state`, etc.
+    pub kinds: BTreeMap<String, u64>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "type")]
@@ -0,0 +1,258 @@
+use serde::Serialize;
+
+use prost::{bytes::Bytes, Message};
+
+use super::{DecodeError, MessageType};
+
+#[allow(clippy::derive_partial_eq_without_eq)]
+mod pb {
+    include!(concat!(env!("OUT_DIR"), "/bitswap.pb.rs"));
+}
+
+use self::pb::message::{wantlist::WantType, BlockPresenceType};
+
+const SHA2_256: u64 = 0x12;
+const BLAKE2B_256: u64 = 0xb220;
+
+/// Text form of the cid, base58 for version 0, base32 multibase for version 1
+pub fn cid_to_string(cid: &[u8]) -> String {
+    if cid.is_empty() {
+        String::new()
+    } else if cid.len() == 34 && cid[0] == SHA2_256 as u8 && cid[1] == 32 {
+        multibase::Base::Base58Btc.encode(cid)
+    } else {
+        multibase::encode(multibase::Base::Base32Lower, cid)
+    }
+}
+
+/// The block carries only the prefix of its cid, the digest is calculated from the data,
+/// `None` if the hash function is not supported
+fn block_cid(prefix: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    use sha2::{Digest, Sha256};
+    use blake2::{
+        digest::{Update, VariableOutput},
+        Blake2bVar,
+    };
+
+    let (version, rest) = unsigned_varint::decode::u64(prefix).ok()?;
+    let (_codec, rest) = unsigned_varint::decode::u64(rest).ok()?;
+    let (code, rest) = unsigned_varint::decode::u64(rest).ok()?;
+    let (len, _) = unsigned_varint::decode::u64(rest).ok()?;
+    let digest = match (code, len) {
+        (SHA2_256, 32) => Sha256::digest(data).to_vec(),
+        (BLAKE2B_256, 32) => {
+            let mut digest = vec![0; 32];
+            Blake2bVar::new(32)
+                .expect("valid constant")
+                .chain(data)
+                .finalize_variable(&mut digest)
+                .expect("good buffer size");
+            digest
+        }
+        _ => return None,
+    };
+    match version {
+        // the cid is the multihash itself
+        0 => Some([&[SHA2_256 as u8, 32][..], &digest].concat()),
+        // the prefix is the beginning of the cid
+        _ => Some([prefix, &digest].concat()),
+    }
+}
+
+/// What the message says about every cid it mentions
+fn items(msg: &pb::Message) -> Vec<(MessageType, Vec<u8>)> {
+    let mut items = vec![];
+    for entry in msg.wantlist.iter().flat_map(|w| &w.entries) {
+        let ty = if entry.cancel {
+            MessageType::BitswapCancel
+        } else {
+            match entry.want_type() {
+                WantType::Block => MessageType::BitswapWantBlock,
+                WantType::Have => MessageType::BitswapWantHave,
+            }
+        };
+        items.push((ty, entry.block.clone()));
+    }
+    for data in &msg.blocks {
+        let cid = block_cid(&[0, 0x70, SHA2_256 as u8, 32], data).unwrap_or_default();
+        items.push((MessageType::BitswapBlock, cid));
+    }
+    for block in &msg.payload {
+        let cid = block_cid(&block.prefix, &block.data).unwrap_or_default();
+        items.push((MessageType::BitswapBlock, cid));
+    }
+    for presence in &msg.block_presences {
+        let ty = match presence.r#type() {
+            BlockPresenceType::Have => MessageType::BitswapHave,
+            BlockPresenceType::DontHave => MessageType::BitswapDontHave,
+        };
+        items.push((ty, presence.cid.clone()));
+    }
+    items
+}
+
+fn decode(bytes: &[u8]) -> Result<pb::Message, DecodeError> {
+    let buf = Bytes::from(bytes.to_vec());
+    <pb::Message as Message>::decode_length_delimited(buf).map_err(DecodeError::Protobuf)
+}
+
+/// Kinds of the message and the cids it mentions, for the index
+pub fn parse_types(bytes: &[u8]) -> Result<(Vec<MessageType>, Vec<String>), DecodeError> {
+    let msg = decode(bytes)?;
+    let mut tys = vec![];
+    let mut cids = vec![];
+    for (ty, cid) in items(&msg) {
+        if !tys.contains(&ty) {
+            tys.push(ty);
+        }
+        let cid = cid_to_string(&cid);
+        // empty if the hash function of the block is not supported
+        if !cid.is_empty() && !cids.contains(&cid) {
+            cids.push(cid);
+        }
+    }
+    if tys.is_empty() {
+        tys.push(MessageType::BitswapExchange);
+    }
+
+    Ok((tys, cids))
+}
+
+/// What the message says about the `cid`
+pub fn find(bytes: &[u8], cid: &str) -> Result<Vec<MessageType>, DecodeError> {
+    let msg = decode(bytes)?;
+    Ok(items(&msg)
+        .into_iter()
+        .filter(|(_, c)| cid_to_string(c) == cid)
+        .map(|(ty, _)| ty)
+        .collect())
+}
+
+pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
+    #[derive(Serialize)]
+    struct T {
+        full_wantlist: bool,
+        entries: Vec<Entry>,
+        blocks: Vec<Block>,
+        block_presences: Vec<Presence>,
+        pending_bytes: i32,
+    }
+
+    #[derive(Serialize)]
+    struct Entry {
+        kind: MessageType,
+        cid: String,
+        priority: i32,
+        send_dont_have: bool,
+    }
+
+    #[derive(Serialize)]
+    struct Block {
+        cid: String,
+        size: usize,
+        data: String,
+    }
+
+    #[derive(Serialize)]
+    struct Presence {
+        kind: MessageType,
+        cid: String,
+    }
+
+    if preview {
+        let (tys, _) = parse_types(&bytes)?;
+        return serde_json::to_value(tys).map_err(DecodeError::Serde);
+    }
+
+    let msg = decode(&bytes)?;
+    let mut items = items(&msg).into_iter();
+    let wantlist = msg.wantlist.unwrap_or_default();
+    let entries = wantlist
+        .entries
+        .iter()
+        .zip(&mut items)
+        .map(|(entry, (kind, cid))| Entry {
+            kind,
+            cid: cid_to_string(&cid),
+            priority: entry.priority,
+            send_dont_have: entry.send_dont_have,
+        })
+        .collect();
+    let data = msg.blocks.iter().chain(msg.payload.iter().map(|b| &b.data));
+    let blocks = data
+        .zip(&mut items)
+        .map(|(data, (_, cid))| Block {
+            cid: cid_to_string(&cid),
+            size: data.len(),
+            data: hex::encode(data),
+        })
+        .collect();
+    let block_presences = items
+        .map(|(kind, cid)| Presence {
+            kind,
+            cid: cid_to_string(&cid),
+        })
+        .collect();
+    let t = T {
+        full_wantlist: wantlist.full,
+        entries,
+        blocks,
+        block_presences,
+        pending_bytes: msg.pending_bytes,
+    };
+    serde_json::to_value(&t).map_err(DecodeError::Serde)
+}
+
+#[cfg(test)]
+#[test]
+fn decode_bitswap() {
+    let data = b"ledger chunk".to_vec();
+    // cid version 1, raw codec, blake2b-256
+    let prefix = vec![0x01, 0x55, 0xa0, 0xe4, 0x02, 0x20];
+    let cid = block_cid(&prefix, &data).unwrap();
+    assert_eq!(&cid[..6], &prefix);
+    assert_eq!(cid.len(), 38);
+
+    let want = pb::Message {
+        wantlist: Some(pb::message::Wantlist {
+            entries: vec![pb::message::wantlist::Entry {
+                block: cid.clone(),
+                priority: 1,
+                cancel: false,
+                want_type: WantType::Block as i32,
+                send_dont_have: true,
+            }],
+            full: false,
+        }),
+        ..Default::default()
+    };
+    let reply = pb::Message {
+        payload: vec![pb::message::Block { prefix, data }],
+        block_presences: vec![pb::message::BlockPresence {
+            cid: vec![0x01, 0x55, 0x12, 0x20, 1, 2, 3],
+            r#type: BlockPresenceType::DontHave as i32,
+        }],
+        ..Default::default()
+    };
+
+    let cid = cid_to_string(&cid);
+    assert!(cid.starts_with('b'));
+
+    let want = want.encode_length_delimited_to_vec();
+    let (tys, cids) = parse_types(&want).unwrap();
+    assert!(tys == [MessageType::BitswapWantBlock]);
+    assert_eq!(cids, [cid.as_str()]);
+
+    let reply = reply.encode_length_delimited_to_vec();
+    let (tys, cids) = parse_types(&reply).unwrap();
+    assert!(tys == [MessageType::BitswapBlock, MessageType::BitswapDontHave]);
+    assert_eq!(cids[0], cid);
+    assert!(find(&reply, &cid).unwrap() == [MessageType::BitswapBlock]);
+
+    let v = parse(reply, false).unwrap();
+    assert_eq!(v["blocks"][0]["cid"], cid);
+    assert_eq!(v["block_presences"][0]["kind"], "bitswap_dont_have");
+
+    let v0 = [&[SHA2_256 as u8, 32][..], &[0; 32]].concat();
+    assert!(cid_to_string(&v0).starts_with("Qm"));
+}
@@ -1,6 +1,7 @@
 use serde::Serialize;
 
 use prost::{bytes::Bytes, Message};
+use libp2p_core::{PublicKey, PeerId};
 
 use crate::database::StreamKind;
 
@@ -11,6 +12,38 @@ mod pb {
     include!(concat!(env!("OUT_DIR"), "/identify.proto.rs"));
 }
 
+/// What the sender advertises, the whole set or the change
+pub enum Protocols {
+    Full {
+        peer_id: Option<String>,
+        protocols: Vec<String>,
+    },
+    Delta {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+}
+
+pub fn protocols(bytes: &[u8]) -> Result<Protocols, DecodeError> {
+    let buf = Bytes::from(bytes.to_vec());
+    let msg = pb::Identify::decode_length_delimited(buf).map_err(DecodeError::Protobuf)?;
+    Ok(match msg.delta {
+        Some(delta) => Protocols::Delta {
+            added: delta.added_protocols,
+            removed: delta.rm_protocols,
+        },
+        None => Protocols::Full {
+            peer_id: msg.public_key.as_deref().and_then(peer_id),
+            protocols: msg.protocols,
+        },
+    })
+}
+
+fn peer_id(public_key: &[u8]) -> Option<String> {
+    let pk = PublicKey::from_protobuf_encoding(public_key).ok()?;
+    Some(PeerId::from_public_key(&pk).to_base58())
+}
+
 pub fn parse(
     bytes: Vec<u8>,
     preview: bool,
@@ -26,12 +59,29 @@ pub fn parse(
         protocols: Vec<String>,
     }
 
+    #[derive(Serialize)]
+    struct D {
+        added_protocols: Vec<String>,
+        rm_protocols: Vec<String>,
+    }
+
     if preview {
-        if matches!(stream_kind, StreamKind::IpfsId) {
-            Ok(serde_json::Value::String("identify".to_string()))
-        } else {
-            Ok(serde_json::Value::String("identify_push".to_string()))
+        match stream_kind {
+            StreamKind::IpfsId => Ok(serde_json::Value::String("identify".to_string())),
+            StreamKind::IpfsDelta => Ok(serde_json::Value::String("identify_delta".to_string())),
+            _ => Ok(serde_json::Value::String("identify_push".to_string())),
         }
+    } else if let StreamKind::IpfsDelta = stream_kind {
+        let buf = Bytes::from(bytes);
+        let delta = pb::Identify::decode_length_delimited(buf)
+            .map_err(DecodeError::Protobuf)?
+            .delta
+            .unwrap_or_default();
+        let d = D {
+            added_protocols: delta.added_protocols,
+            rm_protocols: delta.rm_protocols,
+        };
+        serde_json::to_value(&d).map_err(DecodeError::Serde)
     } else {
         let buf = Bytes::from(bytes);
         let pb::Identify {
@@ -41,6 +91,7 @@ pub fn parse(
             listen_addrs,
             observed_addr,
             protocols,
+            ..
         } = pb::Identify::decode_length_delimited(buf).map_err(DecodeError::Protobuf)?;
 
         let t = T {
@@ -66,3 +117,34 @@ fn decode_identify() {
     let msg = parse(bytes, false, StreamKind::IpfsId).unwrap();
     dbg!(msg);
 }
+
+#[cfg(test)]
+#[test]
+fn decode_identify_delta() {
+    let msg = pb::Identify {
+        delta: Some(pb::Delta {
+            added_protocols: vec!["/meshsub/1.1.0".to_owned()],
+            rm_protocols: vec!["/meshsub/1.0.0".to_owned()],
+        }),
+        ..Default::default()
+    };
+    let bytes = msg.encode_length_delimited_to_vec();
+    match protocols(&bytes).unwrap() {
+        Protocols::Delta { added, removed } => {
+            assert_eq!(added, ["/meshsub/1.1.0"]);
+            assert_eq!(removed, ["/meshsub/1.0.0"]);
+        }
+        Protocols::Full { .. } => panic!("must be delta"),
+    }
+    let v = parse(bytes, false, StreamKind::IpfsDelta).unwrap();
+    assert_eq!(v["added_protocols"][0], "/meshsub/1.1.0");
+
+    let bytes = hex::decode(include_str!("identify.hex")).unwrap();
+    match protocols(&bytes).unwrap() {
+        Protocols::Full { peer_id, protocols } => {
+            assert!(peer_id.is_some());
+            assert!(!protocols.is_empty());
+        }
+        Protocols::Delta { .. } => panic!("must be full"),
+    }
+}
@@ -198,10 +198,12 @@ impl SnarkWithHash {
     }
 }
 
+/// The types of the message, the ledger hashes if `index_ledger_hash`,
+/// and the ids of the gossip messages it publishes
 pub fn parse_types(
     bytes: &[u8],
     index_ledger_hash: bool,
-) -> Result<(Vec<MessageType>, Vec<LedgerHash>), DecodeError> {
+) -> Result<(Vec<MessageType>, Vec<LedgerHash>, Vec<Hash>), DecodeError> {
     let buf = Bytes::from(bytes.to_vec());
     let pb::Rpc {
         subscriptions,
@@ -216,9 +218,14 @@ pub fn parse_types(
         }
     });
     let mut ledger_hashes = vec![];
+    let mut gossip_hashes = vec![];
     let publish = publish
         .into_iter()
-        .filter_map(|msg| msg.data)
+        .filter_map(|msg| {
+            let data = msg.data?;
+            gossip_hashes.push(Hash(gossip_hash(&data, &msg.topic)));
+            Some(data)
+        })
         .filter_map(|data| Some((data.get(8).cloned()?, data)))
         .filter_map(|(tag, data)| match tag {
             0 => {
@@ -353,7 +360,28 @@ pub fn parse_types(
 
     let tys = subscriptions.chain(control_types).chain(publish).collect();
 
-    Ok((tys, ledger_hashes))
+    Ok((tys, ledger_hashes, gossip_hashes))
+}
+
+/// The id of the gossip message, the same the libp2p_helper computes,
+/// the topic is the key of the mac
+pub fn gossip_hash(data: &[u8], topic: &str) -> [u8; 32] {
+    use blake2::digest::{Mac, Update, FixedOutput, typenum};
+
+    let key;
+    let key = if topic.as_bytes().len() <= 64 {
+        topic.as_bytes()
+    } else {
+        key = blake2::Blake2b::<typenum::U32>::default()
+            .chain(topic.as_bytes())
+            .finalize_fixed();
+        key.as_slice()
+    };
+    blake2::Blake2bMac::<typenum::U32>::new_from_slice(key)
+        .expect("cannot fail, length is statically known")
+        .chain(data)
+        .finalize_fixed()
+        .into()
 }
 
 pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
@@ -418,22 +446,7 @@ pub fn parse_it(
                         return Some(Event::PublishPreview { topic, message });
                     } else {
                         let hash = if calc_hash {
-                            use blake2::digest::{Mac, Update, FixedOutput, typenum};
-
-                            let key;
-                            let key = if topic.as_bytes().len() <= 64 {
-                                topic.as_bytes()
-                            } else {
-                                key = blake2::Blake2b::<typenum::U32>::default()
-                                    .chain(topic.as_bytes())
-                                    .finalize_fixed();
-                                key.as_slice()
-                            };
-                            blake2::Blake2bMac::<typenum::U32>::new_from_slice(key)
-                                .expect("cannot fail, length is statically known")
-                                .chain(data)
-                                .finalize_fixed()
-                                .into()
+                            gossip_hash(&data, &topic)
                         } else {
                             [0; 32]
                         };
@@ -3,9 +3,11 @@ pub mod meshsub;
 pub mod kademlia;
 pub mod rpc;
 pub mod identify;
-pub mod json_string;
+pub mod peer_exchange;
 pub mod yamux;
 pub mod meshsub_stats;
+pub mod node_status;
+pub mod bitswap;
 
 mod utils;
 
@@ -103,17 +105,30 @@ pub enum MessageType {
     GetTransitionKnowledge,
     GetEpochLedger,
     BanNotify,
+    // snark worker
+    GetWork,
+    SubmitWork,
+    FailedToGenerateSnark,
     // identify
     #[tag(0x0500)]
     Identify,
     IdentifyPush,
+    IdentifyDelta,
     // peer exchange
     #[tag(0x0600)]
     PeerExchange,
     #[tag(0x0700)]
     BitswapExchange,
+    BitswapWantHave,
+    BitswapWantBlock,
+    BitswapCancel,
+    BitswapHave,
+    BitswapDontHave,
+    BitswapBlock,
     #[tag(0x0800)]
     NodeStatus,
+    NodeStatusRequest,
+    NodeStatusResponse,
     #[tag(0x0900)]
     Select,
     #[tag(0x0a00)]
@@ -156,11 +171,23 @@ impl fmt::Display for MessageType {
             MessageType::GetTransitionKnowledge => write!(f, "get_transition_knowledge"),
             MessageType::GetEpochLedger => write!(f, "get_epoch_ledger"),
             MessageType::BanNotify => write!(f, "ban_notify"),
+            MessageType::GetWork => write!(f, "get_work"),
+            MessageType::SubmitWork => write!(f, "submit_work"),
+            MessageType::FailedToGenerateSnark => write!(f, "failed_to_generate_snark"),
             MessageType::Identify => write!(f, "identify"),
             MessageType::IdentifyPush => write!(f, "identify_push"),
+            MessageType::IdentifyDelta => write!(f, "identify_delta"),
             MessageType::PeerExchange => write!(f, "peer_exchange"),
             MessageType::BitswapExchange => write!(f, "bitswap_exchange"),
+            MessageType::BitswapWantHave => write!(f, "bitswap_want_have"),
+            MessageType::BitswapWantBlock => write!(f, "bitswap_want_block"),
+            MessageType::BitswapCancel => write!(f, "bitswap_cancel"),
+            MessageType::BitswapHave => write!(f, "bitswap_have"),
+            MessageType::BitswapDontHave => write!(f, "bitswap_dont_have"),
+            MessageType::BitswapBlock => write!(f, "bitswap_block"),
             MessageType::NodeStatus => write!(f, "node_status"),
+            MessageType::NodeStatusRequest => write!(f, "node_status_request"),
+            MessageType::NodeStatusResponse => write!(f, "node_status_response"),
             MessageType::Select => write!(f, "select"),
             MessageType::Mplex => write!(f, "mplex"),
             MessageType::Yamux => write!(f, "yamux"),
@@ -224,11 +251,23 @@ impl FromStr for MessageType {
             "get_transition_knowledge" => Ok(MessageType::GetTransitionKnowledge),
             "get_epoch_ledger" => Ok(MessageType::GetEpochLedger),
             "ban_notify" => Ok(MessageType::BanNotify),
+            "get_work" => Ok(MessageType::GetWork),
+            "submit_work" => Ok(MessageType::SubmitWork),
+            "failed_to_generate_snark" => Ok(MessageType::FailedToGenerateSnark),
             "identify" => Ok(MessageType::Identify),
             "identify_push" => Ok(MessageType::IdentifyPush),
+            "identify_delta" => Ok(MessageType::IdentifyDelta),
             "peer_exchange" => Ok(MessageType::PeerExchange),
             "bitswap_exchange" => Ok(MessageType::BitswapExchange),
+            "bitswap_want_have" => Ok(MessageType::BitswapWantHave),
+            "bitswap_want_block" => Ok(MessageType::BitswapWantBlock),
+            "bitswap_cancel" => Ok(MessageType::BitswapCancel),
+            "bitswap_have" => Ok(MessageType::BitswapHave),
+            "bitswap_dont_have" => Ok(MessageType::BitswapDontHave),
+            "bitswap_block" => Ok(MessageType::BitswapBlock),
             "node_status" => Ok(MessageType::NodeStatus),
+            "node_status_request" => Ok(MessageType::NodeStatusRequest),
+            "node_status_response" => Ok(MessageType::NodeStatusResponse),
             "select" => Ok(MessageType::Select),
             "mplex" => Ok(MessageType::Mplex),
             "yamux" => Ok(MessageType::Yamux),
@@ -0,0 +1,117 @@
+use serde::{Serialize, Deserialize, Deserializer};
+
+use super::{DecodeError, MessageType};
+
+/// The JSON the node reports about itself on `/mina/node-status`,
+/// every field is optional, so the status of other versions is decoded too
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeStatus {
+    pub node_ip_addr: String,
+    pub node_peer_id: String,
+    #[serde(deserialize_with = "sync_status")]
+    pub sync_status: String,
+    pub peers: Vec<Peer>,
+    pub block_producers: Vec<String>,
+    pub protocol_state_hash: String,
+    pub ban_statuses: Vec<(Peer, serde_json::Value)>,
+    pub k_block_hashes_and_timestamps: Vec<(String, String)>,
+    pub git_commit: String,
+    pub uptime_minutes: u64,
+    pub block_height_opt: Option<u32>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Peer {
+    pub host: String,
+    pub libp2p_port: u16,
+    pub peer_id: String,
+}
+
+/// OCaml variant is either `"Synced"` or `["Synced"]`
+fn sync_status<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Variant {
+        String(String),
+        Array((String,)),
+    }
+
+    Ok(match Variant::deserialize(deserializer)? {
+        Variant::String(s) => s,
+        Variant::Array((s,)) => s,
+    })
+}
+
+/// The requester only opens the stream, the responder writes the JSON and closes it
+fn is_request(bytes: &[u8]) -> bool {
+    bytes.iter().all(u8::is_ascii_whitespace)
+}
+
+/// Whether the `bytes` is a complete JSON, the response may come in many chunks
+pub fn is_complete(bytes: &[u8]) -> bool {
+    match serde_json::from_slice::<serde::de::IgnoredAny>(bytes) {
+        Ok(_) => true,
+        Err(err) => !err.is_eof(),
+    }
+}
+
+pub fn parse_types(bytes: &[u8]) -> Result<(Vec<MessageType>, Option<NodeStatus>), DecodeError> {
+    if is_request(bytes) {
+        Ok((vec![MessageType::NodeStatusRequest], None))
+    } else {
+        let status = serde_json::from_slice(bytes).map_err(DecodeError::Serde)?;
+        Ok((vec![MessageType::NodeStatusResponse], Some(status)))
+    }
+}
+
+pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
+    let (types, status) = parse_types(&bytes)?;
+    match status {
+        Some(status) if !preview => serde_json::to_value(status).map_err(DecodeError::Serde),
+        _ => serde_json::to_value(types).map_err(DecodeError::Serde),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn decode_node_status() {
+    let json = r#"{
+        "node_ip_addr": "10.0.0.1",
+        "node_peer_id": "12D3KooWKG1ZakzGNUNqbNgPfUFmMHt7vDbmVgnMyFbdHFJVwJzt",
+        "sync_status": "Synced",
+        "peers": [{"host": "10.0.0.2", "libp2p_port": 8302, "peer_id": "12D3KooWAdgYL6hv18M3iDBdaK1dRygPivSfAfBNDzie6YqydVbs"}],
+        "block_producers": [],
+        "protocol_state_hash": "3NKmpZ3xYbqDqnSyChmxjvmRJd6yCv3MbPJ5qQxp8fsRTi9ftVoH",
+        "ban_statuses": [],
+        "k_block_hashes_and_timestamps": [["3NKmpZ3xYbqDqnSyChmxjvmRJd6yCv3MbPJ5qQxp8fsRTi9ftVoH", "2023-05-01 10:00:00.000000Z"]],
+        "git_commit": "55b78189c46e1811b8bdb78864cfa95409aeb96a",
+        "uptime_minutes": 42,
+        "block_height_opt": 1024
+    }"#;
+
+    assert!(!is_complete(&json.as_bytes()[..100]));
+    assert!(is_complete(json.as_bytes()));
+
+    let (types, status) = parse_types(json.as_bytes()).unwrap();
+    assert!(types == [MessageType::NodeStatusResponse]);
+    let status = status.unwrap();
+    assert_eq!(status.sync_status, "Synced");
+    assert_eq!(
+        status.git_commit,
+        "55b78189c46e1811b8bdb78864cfa95409aeb96a"
+    );
+    assert_eq!(status.peers[0].libp2p_port, 8302);
+    assert_eq!(status.block_height_opt, Some(1024));
+
+    let (_, status) = parse_types(br#"{"sync_status": ["Bootstrap"]}"#).unwrap();
+    assert_eq!(status.unwrap().sync_status, "Bootstrap");
+
+    let (types, status) = parse_types(b"").unwrap();
+    assert!(types == [MessageType::NodeStatusRequest]);
+    assert!(status.is_none());
+}
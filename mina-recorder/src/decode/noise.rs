@@ -33,6 +33,25 @@ pub fn payload(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
     Ok(msg.payload)
 }
 
+fn libp2p_pk(pk: &keys_proto::PublicKey) -> Result<PublicKey, DecodeError> {
+    Ok(match pk.r#type() {
+        keys_proto::KeyType::Rsa => return Err(DecodeError::Rsa),
+        keys_proto::KeyType::Ed25519 => PublicKey::Ed25519(ed25519::PublicKey::decode(&pk.data)?),
+        keys_proto::KeyType::Secp256k1 => {
+            PublicKey::Secp256k1(secp256k1::PublicKey::decode(&pk.data)?)
+        }
+        keys_proto::KeyType::Ecdsa => PublicKey::Ecdsa(ecdsa::PublicKey::from_bytes(&pk.data)?),
+    })
+}
+
+/// The peer id of the sender of the handshake payload, if any
+pub fn peer_id(bytes: &[u8]) -> Option<String> {
+    let buf = Bytes::from(bytes.to_vec());
+    let pk = pb::Envelope::decode(buf).ok()?.public_key?;
+    let id = PeerId::from_public_key(&libp2p_pk(&pk).ok()?);
+    Some(id.to_base58())
+}
+
 pub fn parse(bytes: Vec<u8>, _: bool) -> Result<serde_json::Value, DecodeError> {
     #[derive(Serialize)]
     struct T {
@@ -65,19 +84,7 @@ pub fn parse(bytes: Vec<u8>, _: bool) -> Result<serde_json::Value, DecodeError>
     let (r#type, public_key, peer_id) = match msg.public_key {
         None => ("".to_string(), "".to_string(), "".to_string()),
         Some(pk) => {
-            let libp2p_pk = match pk.r#type() {
-                keys_proto::KeyType::Rsa => return Err(DecodeError::Rsa),
-                keys_proto::KeyType::Ed25519 => {
-                    PublicKey::Ed25519(ed25519::PublicKey::decode(&pk.data)?)
-                }
-                keys_proto::KeyType::Secp256k1 => {
-                    PublicKey::Secp256k1(secp256k1::PublicKey::decode(&pk.data)?)
-                }
-                keys_proto::KeyType::Ecdsa => {
-                    PublicKey::Ecdsa(ecdsa::PublicKey::from_bytes(&pk.data)?)
-                }
-            };
-            let id = PeerId::from_public_key(&libp2p_pk);
+            let id = PeerId::from_public_key(&libp2p_pk(&pk)?);
             (
                 pk.r#type().as_str_name().to_string(),
                 hex::encode(pk.data),
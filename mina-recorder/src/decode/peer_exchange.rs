@@ -0,0 +1,110 @@
+use std::net::IpAddr;
+
+use serde::{Serialize, Deserialize};
+
+use super::{DecodeError, MessageType};
+
+/// The `peer.AddrInfo` of go-libp2p, the responder writes the JSON array of them
+#[derive(Deserialize)]
+pub struct AddrInfo {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Addrs", default)]
+    pub addrs: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Peer {
+    pub peer_id: String,
+    pub addrs: Vec<String>,
+    /// The addresses which cannot be reached from the internet:
+    /// private, loopback, link local or unspecified ip
+    pub unreachable_addrs: Vec<String>,
+}
+
+impl From<AddrInfo> for Peer {
+    fn from(v: AddrInfo) -> Self {
+        Peer {
+            peer_id: v.id,
+            unreachable_addrs: v
+                .addrs
+                .iter()
+                .filter(|addr| !is_reachable(addr))
+                .cloned()
+                .collect(),
+            addrs: v.addrs,
+        }
+    }
+}
+
+/// Checks the ip of the multiaddr, the name (`dns4`, etc.) is considered reachable
+pub fn is_reachable(addr: &str) -> bool {
+    let mut components = addr.split('/').skip(1);
+    let ip = match (components.next(), components.next()) {
+        (Some("ip4" | "ip6"), Some(ip)) => ip,
+        _ => return true,
+    };
+    match ip.parse::<IpAddr>() {
+        Err(_) => false,
+        Ok(IpAddr::V4(ip)) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let segment = ip.segments()[0];
+            // unique local `fc00::/7` and link local `fe80::/10`
+            let local = (segment & 0xfe00) == 0xfc00 || (segment & 0xffc0) == 0xfe80;
+            !(local || ip.is_loopback() || ip.is_unspecified())
+        }
+    }
+}
+
+/// The requester only opens the stream, the responder writes the JSON and closes it
+fn is_request(bytes: &[u8]) -> bool {
+    bytes.iter().all(u8::is_ascii_whitespace)
+}
+
+pub fn parse_types(bytes: &[u8]) -> Result<(Vec<MessageType>, Vec<Peer>), DecodeError> {
+    if is_request(bytes) {
+        return Ok((vec![MessageType::PeerExchange], vec![]));
+    }
+    let peers = serde_json::from_slice::<Vec<AddrInfo>>(bytes).map_err(DecodeError::Serde)?;
+    Ok((
+        vec![MessageType::PeerExchange],
+        peers.into_iter().map(Peer::from).collect(),
+    ))
+}
+
+pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
+    let (types, peers) = parse_types(&bytes)?;
+    if preview {
+        serde_json::to_value(types).map_err(DecodeError::Serde)
+    } else {
+        serde_json::to_value(peers).map_err(DecodeError::Serde)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn decode_peer_exchange() {
+    let json = r#"[
+        {"ID": "12D3KooWKG1ZakzGNUNqbNgPfUFmMHt7vDbmVgnMyFbdHFJVwJzt", "Addrs": ["/ip4/34.135.63.47/tcp/10001", "/ip4/10.0.0.5/tcp/8302"]},
+        {"ID": "12D3KooWAdgYL6hv18M3iDBdaK1dRygPivSfAfBNDzie6YqydVbs", "Addrs": ["/dns4/seed.minaprotocol.network/tcp/10003", "/ip6/::1/tcp/8302"]}
+    ]"#;
+
+    let (types, peers) = parse_types(json.as_bytes()).unwrap();
+    assert!(types == [MessageType::PeerExchange]);
+    assert_eq!(peers.len(), 2);
+    assert_eq!(
+        peers[0].peer_id,
+        "12D3KooWKG1ZakzGNUNqbNgPfUFmMHt7vDbmVgnMyFbdHFJVwJzt"
+    );
+    assert_eq!(peers[0].unreachable_addrs, ["/ip4/10.0.0.5/tcp/8302"]);
+    assert_eq!(peers[1].unreachable_addrs, ["/ip6/::1/tcp/8302"]);
+
+    let (_, peers) = parse_types(b"").unwrap();
+    assert!(peers.is_empty());
+}
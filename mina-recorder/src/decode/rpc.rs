@@ -21,6 +21,47 @@ pub fn parse_types(bytes: &[u8]) -> Result<Vec<MessageType>, DecodeError> {
     Ok(tag.parse().ok().into_iter().collect())
 }
 
+/// Tag, version and id of the response, and whether the result is `Ok`,
+/// the recorder puts the header of the query in front of the response
+pub fn parse_response_header(bytes: &[u8]) -> Result<(String, i32, i64, bool), DecodeError> {
+    let mut stream = Cursor::new(&bytes);
+
+    let _len = utils::stream_decode_size(&mut stream)?;
+    let Nat0(_) = BinProtRead::binprot_read(&mut stream)?;
+    let msg = QueryHeader::binprot_read(&mut stream)?;
+    // `Rpc_result.t`, the tag of `Ok` is zero, the tag of `Error` is one
+    let mut result = [0];
+    stream
+        .read_exact(&mut result)
+        .map_err(|err| DecodeError::BinProt(err.into()))?;
+
+    Ok((
+        msg.tag.to_string_lossy(),
+        msg.version,
+        msg.id,
+        result[0] == 0,
+    ))
+}
+
+/// The payload of the response, `None` if the response is an error
+pub fn response_payload(bytes: &[u8]) -> Result<Option<&[u8]>, DecodeError> {
+    let mut stream = Cursor::new(&bytes);
+
+    let _len = utils::stream_decode_size(&mut stream)?;
+    let Nat0(_) = BinProtRead::binprot_read(&mut stream)?;
+    let _ = QueryHeader::binprot_read(&mut stream)?;
+    let mut result = [0];
+    stream
+        .read_exact(&mut result)
+        .map_err(|err| DecodeError::BinProt(err.into()))?;
+    if result[0] != 0 {
+        return Ok(None);
+    }
+    let Nat0(len) = BinProtRead::binprot_read(&mut stream)?;
+    let payload = &bytes[(stream.position() as usize).min(bytes.len())..];
+    Ok(Some(&payload[..(len as usize).min(payload.len())]))
+}
+
 pub fn parse(bytes: Vec<u8>, preview: bool) -> Result<serde_json::Value, DecodeError> {
     #[derive(Serialize)]
     #[serde(rename_all = "snake_case")]
@@ -137,3 +178,16 @@ fn decode_v2() {
     let value = msg.as_object().unwrap().get("value").unwrap();
     assert!(value.is_object());
 }
+
+#[cfg(test)]
+#[test]
+fn decode_response_header() {
+    let bytes = include_bytes!("../test_data/rpc_7843");
+    let (tag, version, id, ok) = parse_response_header(bytes).unwrap();
+    assert_eq!(tag, "get_transition_chain");
+    assert_eq!(version, 2);
+    assert_eq!(id, 0x11b);
+    assert!(ok);
+    let payload = response_payload(bytes).unwrap().unwrap();
+    assert_eq!(payload.len(), 0xf48d);
+}
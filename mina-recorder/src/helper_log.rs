@@ -0,0 +1,105 @@
+use std::{mem, time::SystemTime};
+
+use super::database::{DbCore, HelperLog, HelperLogDbKey};
+
+/// A line longer than that is stored in pieces
+const MAX_LINE: usize = 0x10000;
+
+/// The stderr of one libp2p_helper process, split in lines
+#[derive(Default)]
+pub struct HelperLogState {
+    buf: Vec<u8>,
+    // distinguish the lines written at the same time
+    seq: u32,
+}
+
+impl HelperLogState {
+    fn lines(&mut self, data: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(data);
+        let mut lines = vec![];
+        while let Some(pos) = self.buf.iter().position(|c| *c == b'\n') {
+            let rest = self.buf.split_off(pos + 1);
+            let line = mem::replace(&mut self.buf, rest);
+            lines.push(String::from_utf8_lossy(&line).trim_end().to_owned());
+        }
+        if self.buf.len() >= MAX_LINE {
+            lines.push(String::from_utf8_lossy(&mem::take(&mut self.buf)).into_owned());
+        }
+        lines.retain(|line| !line.is_empty());
+        lines
+    }
+
+    pub fn handle_data(
+        &mut self,
+        db: &DbCore,
+        pid: u32,
+        node: String,
+        time: SystemTime,
+        data: &[u8],
+    ) {
+        for line in self.lines(data) {
+            let key = HelperLogDbKey {
+                time,
+                pid,
+                seq: self.seq,
+            };
+            self.seq = self.seq.wrapping_add(1);
+            let v = parse(pid, node.clone(), time, line);
+            if let Err(err) = db.put_helper_log(key, v) {
+                log::error!("helper log {pid}: {err}");
+            }
+        }
+    }
+}
+
+/// The helper writes JSON lines of the go-log, `level`, `logger` and `msg` are extracted,
+/// the line which is not JSON is stored as the message
+fn parse(pid: u32, node: String, timestamp: SystemTime, line: String) -> HelperLog {
+    let field = |v: &serde_json::Value, keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| v.get(key).and_then(serde_json::Value::as_str))
+            .unwrap_or_default()
+            .to_owned()
+    };
+    match serde_json::from_str::<serde_json::Value>(&line) {
+        Ok(v) if v.is_object() => HelperLog {
+            timestamp,
+            pid,
+            node,
+            level: field(&v, &["level"]).to_lowercase(),
+            module: field(&v, &["logger", "module"]),
+            message: field(&v, &["msg", "message"]),
+            line,
+        },
+        _ => HelperLog {
+            timestamp,
+            pid,
+            node,
+            level: String::new(),
+            module: String::new(),
+            message: line.clone(),
+            line,
+        },
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn helper_log_lines() {
+    let mut st = HelperLogState::default();
+    let json = r#"{"level":"warn","ts":"2023-05-01T10:00:00.000Z","logger":"codanet","msg":"gating peer"}"#;
+    assert!(st.lines(&json.as_bytes()[..20]).is_empty());
+    let data = [&json.as_bytes()[20..], b"\nplain text\n\npartial"].concat();
+    let lines = st.lines(&data);
+    assert_eq!(lines, [json, "plain text"]);
+
+    let log = parse(1, String::new(), SystemTime::UNIX_EPOCH, lines[0].clone());
+    assert_eq!(log.level, "warn");
+    assert_eq!(log.module, "codanet");
+    assert_eq!(log.message, "gating peer");
+    let log = parse(1, String::new(), SystemTime::UNIX_EPOCH, lines[1].clone());
+    assert_eq!(log.level, "");
+    assert_eq!(log.message, "plain text");
+
+    assert_eq!(st.lines(b"\n"), ["partial"]);
+}
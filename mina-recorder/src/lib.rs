@@ -8,12 +8,16 @@ pub use self::chunk::{ChunkHeader, EncryptionStatus, ChunkParser};
 
 /// State machine that manages debuggee processes and their TCP connections.
 mod recorder;
-pub use self::recorder::P2pRecorder;
+pub use self::recorder::{P2pRecorder, Aggregator};
 
 /// State machine that manages snark worker processes.
 mod snark_worker;
 pub use self::snark_worker::*;
 
+/// Collects the log the libp2p_helper writes to stderr.
+mod helper_log;
+pub use self::helper_log::HelperLogState;
+
 pub mod tester;
 
 /// State machine that manages the state of one TCP connection.
@@ -0,0 +1,942 @@
+use std::fmt;
+
+use radiation::{Absorb, Emit};
+use serde::Serialize;
+
+use crate::libp2p_ipc_capnp::{
+    self as schema, gating_config, libp2p_config, peer_info, libp2p_helper_interface,
+    daemon_interface,
+};
+
+/// Every message of `libp2p_ipc.capnp`, the daemon sends the requests and the validation
+/// results to the helper, the helper sends the responses and the notifications to the daemon
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum IpcMessage {
+    RpcRequest {
+        seqno: u64,
+        /// Unix timestamp in nanoseconds
+        time_sent: i64,
+        request: RpcRequest,
+    },
+    RpcResponse {
+        seqno: u64,
+        time_sent: i64,
+        response: RpcResponse,
+    },
+    /// From the daemon to the helper
+    HelperPush { time_sent: i64, push: HelperPush },
+    /// From the helper to the daemon
+    DaemonPush { time_sent: i64, push: DaemonPush },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "kind")]
+pub enum RpcRequest {
+    /// Everything except the private key
+    Configure {
+        statedir: String,
+        network_id: String,
+        listen_on: Vec<String>,
+        metrics_port: u16,
+        external_multiaddr: String,
+        unsafe_no_trust_ip: bool,
+        seed_peers: Vec<String>,
+        direct_peers: Vec<String>,
+        flood: bool,
+        peer_exchange: bool,
+        max_connections: u32,
+        min_connections: u32,
+        validation_queue_size: u32,
+        peer_protection_ratio: f32,
+        known_private_ip_nets: Vec<String>,
+        /// The topics by the level
+        topic_config: Vec<Vec<String>>,
+        gating_config: GatingConfig,
+    },
+    SetGatingConfig {
+        gating_config: GatingConfig,
+    },
+    Listen {
+        iface: String,
+    },
+    GetListeningAddrs,
+    BeginAdvertising,
+    AddPeer {
+        multiaddr: String,
+        is_seed: bool,
+    },
+    ListPeers,
+    GenerateKeypair,
+    Publish {
+        topic: String,
+        size: usize,
+        /// The id of the gossip message, hex
+        hash: String,
+    },
+    Subscribe {
+        topic: String,
+        subscription_id: u64,
+    },
+    Unsubscribe {
+        subscription_id: u64,
+    },
+    AddStreamHandler {
+        protocol: String,
+    },
+    RemoveStreamHandler {
+        protocol: String,
+    },
+    OpenStream {
+        peer_id: String,
+        protocol: String,
+    },
+    CloseStream {
+        stream_id: u64,
+    },
+    ResetStream {
+        stream_id: u64,
+    },
+    SendStream {
+        stream_id: u64,
+        size: usize,
+    },
+    SetNodeStatus {
+        size: usize,
+    },
+    GetPeerNodeStatus {
+        peer: String,
+    },
+    BandwidthInfo,
+    TestDecodeBitswapBlocks {
+        blocks: u32,
+    },
+    TestEncodeBitswapBlocks {
+        size: usize,
+        max_block_size: i64,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "kind")]
+pub enum RpcResponse {
+    Error {
+        error: String,
+    },
+    Configure,
+    SetGatingConfig,
+    Listen {
+        result: Vec<String>,
+    },
+    GetListeningAddrs {
+        result: Vec<String>,
+    },
+    BeginAdvertising,
+    AddPeer,
+    ListPeers {
+        result: Vec<PeerInfo>,
+    },
+    GenerateKeypair {
+        peer_id: String,
+    },
+    Publish,
+    Subscribe,
+    Unsubscribe,
+    AddStreamHandler,
+    RemoveStreamHandler,
+    OpenStream {
+        stream_id: u64,
+        peer: PeerInfo,
+    },
+    CloseStream,
+    ResetStream,
+    SendStream,
+    SetNodeStatus,
+    GetPeerNodeStatus {
+        size: usize,
+    },
+    BandwidthInfo {
+        input_bandwidth: f64,
+        output_bandwidth: f64,
+        cpu_usage: f64,
+    },
+    TestDecodeBitswapBlocks {
+        size: usize,
+    },
+    TestEncodeBitswapBlocks {
+        blocks: u32,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "kind")]
+pub enum HelperPush {
+    Validation {
+        validation_id: u64,
+        result: ValidationResult,
+    },
+    AddResource {
+        tag: u8,
+        size: usize,
+    },
+    DeleteResource {
+        ids: Vec<String>,
+    },
+    DownloadResource {
+        tag: u8,
+        ids: Vec<String>,
+    },
+    HeartbeatPeer {
+        peer_id: String,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "kind")]
+pub enum DaemonPush {
+    PeerConnected {
+        peer_id: String,
+    },
+    PeerDisconnected {
+        peer_id: String,
+    },
+    GossipReceived {
+        sender: PeerInfo,
+        seen_at: i64,
+        expiration: i64,
+        subscription_id: u64,
+        validation_id: u64,
+        size: usize,
+    },
+    IncomingStream {
+        stream_id: u64,
+        peer: PeerInfo,
+        protocol: String,
+    },
+    StreamLost {
+        stream_id: u64,
+        reason: String,
+    },
+    StreamComplete {
+        stream_id: u64,
+    },
+    StreamMessageReceived {
+        stream_id: u64,
+        size: usize,
+    },
+    ResourceUpdated {
+        update: ResourceUpdateType,
+        ids: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+pub struct GatingConfig {
+    pub banned_ips: Vec<String>,
+    pub banned_peer_ids: Vec<String>,
+    pub trusted_ips: Vec<String>,
+    pub trusted_peer_ids: Vec<String>,
+    pub isolate: bool,
+}
+
+#[derive(Serialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub host: String,
+    pub libp2p_port: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Absorb, Emit, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationResult {
+    Accept,
+    Reject,
+    Ignore,
+}
+
+impl From<schema::ValidationResult> for ValidationResult {
+    fn from(v: schema::ValidationResult) -> Self {
+        match v {
+            schema::ValidationResult::Accept => ValidationResult::Accept,
+            schema::ValidationResult::Reject => ValidationResult::Reject,
+            schema::ValidationResult::Ignore => ValidationResult::Ignore,
+        }
+    }
+}
+
+impl fmt::Display for ValidationResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationResult::Accept => write!(f, "accept"),
+            ValidationResult::Reject => write!(f, "reject"),
+            ValidationResult::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceUpdateType {
+    Added,
+    Removed,
+    Broken,
+}
+
+impl IpcMessage {
+    /// The name of the rpc or of the push message, e.g. `add_peer`, `validation`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IpcMessage::RpcRequest { request, .. } => request.kind(),
+            IpcMessage::RpcResponse { response, .. } => response.kind(),
+            IpcMessage::HelperPush { push, .. } => push.kind(),
+            IpcMessage::DaemonPush { push, .. } => push.kind(),
+        }
+    }
+}
+
+impl RpcRequest {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RpcRequest::Configure { .. } => "configure",
+            RpcRequest::SetGatingConfig { .. } => "set_gating_config",
+            RpcRequest::Listen { .. } => "listen",
+            RpcRequest::GetListeningAddrs => "get_listening_addrs",
+            RpcRequest::BeginAdvertising => "begin_advertising",
+            RpcRequest::AddPeer { .. } => "add_peer",
+            RpcRequest::ListPeers => "list_peers",
+            RpcRequest::GenerateKeypair => "generate_keypair",
+            RpcRequest::Publish { .. } => "publish",
+            RpcRequest::Subscribe { .. } => "subscribe",
+            RpcRequest::Unsubscribe { .. } => "unsubscribe",
+            RpcRequest::AddStreamHandler { .. } => "add_stream_handler",
+            RpcRequest::RemoveStreamHandler { .. } => "remove_stream_handler",
+            RpcRequest::OpenStream { .. } => "open_stream",
+            RpcRequest::CloseStream { .. } => "close_stream",
+            RpcRequest::ResetStream { .. } => "reset_stream",
+            RpcRequest::SendStream { .. } => "send_stream",
+            RpcRequest::SetNodeStatus { .. } => "set_node_status",
+            RpcRequest::GetPeerNodeStatus { .. } => "get_peer_node_status",
+            RpcRequest::BandwidthInfo => "bandwidth_info",
+            RpcRequest::TestDecodeBitswapBlocks { .. } => "test_decode_bitswap_blocks",
+            RpcRequest::TestEncodeBitswapBlocks { .. } => "test_encode_bitswap_blocks",
+        }
+    }
+}
+
+impl RpcResponse {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RpcResponse::Error { .. } => "error",
+            RpcResponse::Configure => "configure",
+            RpcResponse::SetGatingConfig => "set_gating_config",
+            RpcResponse::Listen { .. } => "listen",
+            RpcResponse::GetListeningAddrs { .. } => "get_listening_addrs",
+            RpcResponse::BeginAdvertising => "begin_advertising",
+            RpcResponse::AddPeer => "add_peer",
+            RpcResponse::ListPeers { .. } => "list_peers",
+            RpcResponse::GenerateKeypair { .. } => "generate_keypair",
+            RpcResponse::Publish => "publish",
+            RpcResponse::Subscribe => "subscribe",
+            RpcResponse::Unsubscribe => "unsubscribe",
+            RpcResponse::AddStreamHandler => "add_stream_handler",
+            RpcResponse::RemoveStreamHandler => "remove_stream_handler",
+            RpcResponse::OpenStream { .. } => "open_stream",
+            RpcResponse::CloseStream => "close_stream",
+            RpcResponse::ResetStream => "reset_stream",
+            RpcResponse::SendStream => "send_stream",
+            RpcResponse::SetNodeStatus => "set_node_status",
+            RpcResponse::GetPeerNodeStatus { .. } => "get_peer_node_status",
+            RpcResponse::BandwidthInfo { .. } => "bandwidth_info",
+            RpcResponse::TestDecodeBitswapBlocks { .. } => "test_decode_bitswap_blocks",
+            RpcResponse::TestEncodeBitswapBlocks { .. } => "test_encode_bitswap_blocks",
+        }
+    }
+}
+
+impl HelperPush {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HelperPush::Validation { .. } => "validation",
+            HelperPush::AddResource { .. } => "add_resource",
+            HelperPush::DeleteResource { .. } => "delete_resource",
+            HelperPush::DownloadResource { .. } => "download_resource",
+            HelperPush::HeartbeatPeer { .. } => "heartbeat_peer",
+        }
+    }
+}
+
+impl DaemonPush {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DaemonPush::PeerConnected { .. } => "peer_connected",
+            DaemonPush::PeerDisconnected { .. } => "peer_disconnected",
+            DaemonPush::GossipReceived { .. } => "gossip_received",
+            DaemonPush::IncomingStream { .. } => "incoming_stream",
+            DaemonPush::StreamLost { .. } => "stream_lost",
+            DaemonPush::StreamComplete { .. } => "stream_complete",
+            DaemonPush::StreamMessageReceived { .. } => "stream_message_received",
+            DaemonPush::ResourceUpdated { .. } => "resource_updated",
+        }
+    }
+}
+
+/// `incoming` is the message the helper reads, it is sent by the daemon
+pub fn decode(incoming: bool, bytes: &[u8]) -> capnp::Result<IpcMessage> {
+    let mut slice = bytes;
+    let reader = capnp::serialize::read_message(&mut slice, Default::default())?;
+    if incoming {
+        let message = reader.get_root::<libp2p_helper_interface::message::Reader>()?;
+        decode_helper_interface(message)
+    } else {
+        let message = reader.get_root::<daemon_interface::message::Reader>()?;
+        decode_daemon_interface(message)
+    }
+}
+
+fn decode_helper_interface(
+    message: libp2p_helper_interface::message::Reader,
+) -> capnp::Result<IpcMessage> {
+    use libp2p_helper_interface::{message, rpc_request, push_message};
+
+    match message.which()? {
+        message::RpcRequest(msg) => {
+            let msg = msg?;
+            let header = msg.get_header()?;
+            let request = match msg.which()? {
+                rpc_request::Configure(v) => {
+                    let config = v?.get_config()?;
+                    configure(config)?
+                }
+                rpc_request::SetGatingConfig(v) => RpcRequest::SetGatingConfig {
+                    gating_config: gating(v?.get_gating_config()?)?,
+                },
+                rpc_request::Listen(v) => RpcRequest::Listen {
+                    iface: v?.get_iface()?.get_representation()?.to_owned(),
+                },
+                rpc_request::GetListeningAddrs(_) => RpcRequest::GetListeningAddrs,
+                rpc_request::BeginAdvertising(_) => RpcRequest::BeginAdvertising,
+                rpc_request::AddPeer(v) => {
+                    let v = v?;
+                    RpcRequest::AddPeer {
+                        multiaddr: v.get_multiaddr()?.get_representation()?.to_owned(),
+                        is_seed: v.get_is_seed(),
+                    }
+                }
+                rpc_request::ListPeers(_) => RpcRequest::ListPeers,
+                rpc_request::GenerateKeypair(_) => RpcRequest::GenerateKeypair,
+                rpc_request::Publish(v) => {
+                    let v = v?;
+                    let topic = v.get_topic()?;
+                    let data = v.get_data()?;
+                    RpcRequest::Publish {
+                        topic: topic.to_owned(),
+                        size: data.len(),
+                        hash: hex::encode(super::calc_hash(data, topic)),
+                    }
+                }
+                rpc_request::Subscribe(v) => {
+                    let v = v?;
+                    RpcRequest::Subscribe {
+                        topic: v.get_topic()?.to_owned(),
+                        subscription_id: v.get_subscription_id()?.get_id(),
+                    }
+                }
+                rpc_request::Unsubscribe(v) => RpcRequest::Unsubscribe {
+                    subscription_id: v?.get_subscription_id()?.get_id(),
+                },
+                rpc_request::AddStreamHandler(v) => RpcRequest::AddStreamHandler {
+                    protocol: v?.get_protocol()?.to_owned(),
+                },
+                rpc_request::RemoveStreamHandler(v) => RpcRequest::RemoveStreamHandler {
+                    protocol: v?.get_protocol()?.to_owned(),
+                },
+                rpc_request::OpenStream(v) => {
+                    let v = v?;
+                    RpcRequest::OpenStream {
+                        peer_id: v.get_peer()?.get_id()?.to_owned(),
+                        protocol: v.get_protocol_id()?.to_owned(),
+                    }
+                }
+                rpc_request::CloseStream(v) => RpcRequest::CloseStream {
+                    stream_id: v?.get_stream_id()?.get_id(),
+                },
+                rpc_request::ResetStream(v) => RpcRequest::ResetStream {
+                    stream_id: v?.get_stream_id()?.get_id(),
+                },
+                rpc_request::SendStream(v) => {
+                    let msg = v?.get_msg()?;
+                    RpcRequest::SendStream {
+                        stream_id: msg.get_stream_id()?.get_id(),
+                        size: msg.get_data()?.len(),
+                    }
+                }
+                rpc_request::SetNodeStatus(v) => RpcRequest::SetNodeStatus {
+                    size: v?.get_status()?.len(),
+                },
+                rpc_request::GetPeerNodeStatus(v) => RpcRequest::GetPeerNodeStatus {
+                    peer: v?.get_peer()?.get_representation()?.to_owned(),
+                },
+                rpc_request::BandwidthInfo(_) => RpcRequest::BandwidthInfo,
+                rpc_request::TestDecodeBitswapBlocks(v) => RpcRequest::TestDecodeBitswapBlocks {
+                    blocks: v?.get_blocks()?.len(),
+                },
+                rpc_request::TestEncodeBitswapBlocks(v) => {
+                    let v = v?;
+                    RpcRequest::TestEncodeBitswapBlocks {
+                        size: v.get_data()?.len(),
+                        max_block_size: v.get_max_block_size(),
+                    }
+                }
+            };
+            Ok(IpcMessage::RpcRequest {
+                seqno: header.get_sequence_number()?.get_seqno(),
+                time_sent: header.get_time_sent()?.get_nano_sec(),
+                request,
+            })
+        }
+        message::PushMessage(msg) => {
+            let msg = msg?;
+            let push = match msg.which()? {
+                push_message::Validation(v) => {
+                    let v = v?;
+                    HelperPush::Validation {
+                        validation_id: v.get_validation_id()?.get_id(),
+                        result: v.get_result()?.into(),
+                    }
+                }
+                push_message::AddResource(v) => {
+                    let v = v?;
+                    HelperPush::AddResource {
+                        tag: v.get_tag(),
+                        size: v.get_data()?.len(),
+                    }
+                }
+                push_message::DeleteResource(v) => HelperPush::DeleteResource {
+                    ids: root_block_ids(v?.get_ids()?)?,
+                },
+                push_message::DownloadResource(v) => {
+                    let v = v?;
+                    HelperPush::DownloadResource {
+                        tag: v.get_tag(),
+                        ids: root_block_ids(v.get_ids()?)?,
+                    }
+                }
+                push_message::HeartbeatPeer(v) => HelperPush::HeartbeatPeer {
+                    peer_id: v?.get_id()?.get_id()?.to_owned(),
+                },
+            };
+            Ok(IpcMessage::HelperPush {
+                time_sent: msg.get_header()?.get_time_sent()?.get_nano_sec(),
+                push,
+            })
+        }
+    }
+}
+
+fn decode_daemon_interface(
+    message: daemon_interface::message::Reader,
+) -> capnp::Result<IpcMessage> {
+    use daemon_interface::{message, push_message};
+    use libp2p_helper_interface::{rpc_response, rpc_response_success};
+
+    match message.which()? {
+        message::RpcResponse(msg) => {
+            let msg = msg?;
+            let header = msg.get_header()?;
+            let response = match msg.which()? {
+                rpc_response::Error(error) => RpcResponse::Error {
+                    error: error?.to_owned(),
+                },
+                rpc_response::Success(v) => match v?.which()? {
+                    rpc_response_success::Configure(_) => RpcResponse::Configure,
+                    rpc_response_success::SetGatingConfig(_) => RpcResponse::SetGatingConfig,
+                    rpc_response_success::Listen(v) => RpcResponse::Listen {
+                        result: multiaddrs(v?.get_result()?)?,
+                    },
+                    rpc_response_success::GetListeningAddrs(v) => RpcResponse::GetListeningAddrs {
+                        result: multiaddrs(v?.get_result()?)?,
+                    },
+                    rpc_response_success::BeginAdvertising(_) => RpcResponse::BeginAdvertising,
+                    rpc_response_success::AddPeer(_) => RpcResponse::AddPeer,
+                    rpc_response_success::ListPeers(v) => RpcResponse::ListPeers {
+                        result: v?
+                            .get_result()?
+                            .iter()
+                            .map(peer)
+                            .collect::<capnp::Result<_>>()?,
+                    },
+                    rpc_response_success::GenerateKeypair(v) => RpcResponse::GenerateKeypair {
+                        peer_id: v?.get_result()?.get_peer_id()?.get_id()?.to_owned(),
+                    },
+                    rpc_response_success::Publish(_) => RpcResponse::Publish,
+                    rpc_response_success::Subscribe(_) => RpcResponse::Subscribe,
+                    rpc_response_success::Unsubscribe(_) => RpcResponse::Unsubscribe,
+                    rpc_response_success::AddStreamHandler(_) => RpcResponse::AddStreamHandler,
+                    rpc_response_success::RemoveStreamHandler(_) => {
+                        RpcResponse::RemoveStreamHandler
+                    }
+                    rpc_response_success::OpenStream(v) => {
+                        let v = v?;
+                        RpcResponse::OpenStream {
+                            stream_id: v.get_stream_id()?.get_id(),
+                            peer: peer(v.get_peer()?)?,
+                        }
+                    }
+                    rpc_response_success::CloseStream(_) => RpcResponse::CloseStream,
+                    rpc_response_success::ResetStream(_) => RpcResponse::ResetStream,
+                    rpc_response_success::SendStream(_) => RpcResponse::SendStream,
+                    rpc_response_success::SetNodeStatus(_) => RpcResponse::SetNodeStatus,
+                    rpc_response_success::GetPeerNodeStatus(v) => RpcResponse::GetPeerNodeStatus {
+                        size: v?.get_result()?.len(),
+                    },
+                    rpc_response_success::BandwidthInfo(v) => {
+                        let v = v?;
+                        RpcResponse::BandwidthInfo {
+                            input_bandwidth: v.get_input_bandwidth(),
+                            output_bandwidth: v.get_output_bandwidth(),
+                            cpu_usage: v.get_cpu_usage(),
+                        }
+                    }
+                    rpc_response_success::TestDecodeBitswapBlocks(v) => {
+                        RpcResponse::TestDecodeBitswapBlocks {
+                            size: v?.get_decoded_data()?.len(),
+                        }
+                    }
+                    rpc_response_success::TestEncodeBitswapBlocks(v) => {
+                        RpcResponse::TestEncodeBitswapBlocks {
+                            blocks: v?.get_blocks()?.len(),
+                        }
+                    }
+                },
+            };
+            Ok(IpcMessage::RpcResponse {
+                seqno: header.get_sequence_number()?.get_seqno(),
+                time_sent: header.get_time_sent()?.get_nano_sec(),
+                response,
+            })
+        }
+        message::PushMessage(msg) => {
+            let msg = msg?;
+            let push = match msg.which()? {
+                push_message::PeerConnected(v) => DaemonPush::PeerConnected {
+                    peer_id: v?.get_peer_id()?.get_id()?.to_owned(),
+                },
+                push_message::PeerDisconnected(v) => DaemonPush::PeerDisconnected {
+                    peer_id: v?.get_peer_id()?.get_id()?.to_owned(),
+                },
+                push_message::GossipReceived(v) => {
+                    let v = v?;
+                    DaemonPush::GossipReceived {
+                        sender: peer(v.get_sender()?)?,
+                        seen_at: v.get_seen_at()?.get_nano_sec(),
+                        expiration: v.get_expiration()?.get_nano_sec(),
+                        subscription_id: v.get_subscription_id()?.get_id(),
+                        validation_id: v.get_validation_id()?.get_id(),
+                        size: v.get_data()?.len(),
+                    }
+                }
+                push_message::IncomingStream(v) => {
+                    let v = v?;
+                    DaemonPush::IncomingStream {
+                        stream_id: v.get_stream_id()?.get_id(),
+                        peer: peer(v.get_peer()?)?,
+                        protocol: v.get_protocol()?.to_owned(),
+                    }
+                }
+                push_message::StreamLost(v) => {
+                    let v = v?;
+                    DaemonPush::StreamLost {
+                        stream_id: v.get_stream_id()?.get_id(),
+                        reason: v.get_reason()?.to_owned(),
+                    }
+                }
+                push_message::StreamComplete(v) => DaemonPush::StreamComplete {
+                    stream_id: v?.get_stream_id()?.get_id(),
+                },
+                push_message::StreamMessageReceived(v) => {
+                    let msg = v?.get_msg()?;
+                    DaemonPush::StreamMessageReceived {
+                        stream_id: msg.get_stream_id()?.get_id(),
+                        size: msg.get_data()?.len(),
+                    }
+                }
+                push_message::ResourceUpdated(v) => {
+                    let v = v?;
+                    let update = match v.get_type()? {
+                        schema::ResourceUpdateType::Added => ResourceUpdateType::Added,
+                        schema::ResourceUpdateType::Removed => ResourceUpdateType::Removed,
+                        schema::ResourceUpdateType::Broken => ResourceUpdateType::Broken,
+                    };
+                    DaemonPush::ResourceUpdated {
+                        update,
+                        ids: root_block_ids(v.get_ids()?)?,
+                    }
+                }
+            };
+            Ok(IpcMessage::DaemonPush {
+                time_sent: msg.get_header()?.get_time_sent()?.get_nano_sec(),
+                push,
+            })
+        }
+    }
+}
+
+fn configure(config: libp2p_config::Reader) -> capnp::Result<RpcRequest> {
+    Ok(RpcRequest::Configure {
+        statedir: config.get_statedir()?.to_owned(),
+        network_id: config.get_network_id()?.to_owned(),
+        listen_on: multiaddrs(config.get_listen_on()?)?,
+        metrics_port: config.get_metrics_port(),
+        external_multiaddr: config
+            .get_external_multiaddr()?
+            .get_representation()?
+            .to_owned(),
+        unsafe_no_trust_ip: config.get_unsafe_no_trust_ip(),
+        seed_peers: multiaddrs(config.get_seed_peers()?)?,
+        direct_peers: multiaddrs(config.get_direct_peers()?)?,
+        flood: config.get_flood(),
+        peer_exchange: config.get_peer_exchange(),
+        max_connections: config.get_max_connections(),
+        min_connections: config.get_min_connections(),
+        validation_queue_size: config.get_validation_queue_size(),
+        peer_protection_ratio: config.get_peer_protection_ratio(),
+        known_private_ip_nets: texts(config.get_known_private_ip_nets()?)?,
+        topic_config: config
+            .get_topic_config()?
+            .iter()
+            .map(|level| texts(level.get_topics()?))
+            .collect::<capnp::Result<_>>()?,
+        gating_config: gating(config.get_gating_config()?)?,
+    })
+}
+
+fn gating(config: gating_config::Reader) -> capnp::Result<GatingConfig> {
+    let peer_ids = |list: capnp::struct_list::Reader<schema::peer_id::Owned>| {
+        list.iter()
+            .map(|p| p.get_id().map(str::to_owned))
+            .collect::<capnp::Result<Vec<_>>>()
+    };
+    Ok(GatingConfig {
+        banned_ips: texts(config.get_banned_ips()?)?,
+        banned_peer_ids: peer_ids(config.get_banned_peer_ids()?)?,
+        trusted_ips: texts(config.get_trusted_ips()?)?,
+        trusted_peer_ids: peer_ids(config.get_trusted_peer_ids()?)?,
+        isolate: config.get_isolate(),
+    })
+}
+
+fn texts(list: capnp::text_list::Reader) -> capnp::Result<Vec<String>> {
+    list.iter().map(|s| s.map(str::to_owned)).collect()
+}
+
+fn multiaddrs(
+    list: capnp::struct_list::Reader<schema::multiaddr::Owned>,
+) -> capnp::Result<Vec<String>> {
+    list.iter()
+        .map(|addr| addr.get_representation().map(str::to_owned))
+        .collect()
+}
+
+fn root_block_ids(
+    list: capnp::struct_list::Reader<schema::root_block_id::Owned>,
+) -> capnp::Result<Vec<String>> {
+    list.iter()
+        .map(|id| id.get_blake2b_hash().map(hex::encode))
+        .collect()
+}
+
+fn peer(info: peer_info::Reader) -> capnp::Result<PeerInfo> {
+    Ok(PeerInfo {
+        peer_id: info.get_peer_id()?.get_id()?.to_owned(),
+        host: info.get_host()?.to_owned(),
+        libp2p_port: info.get_libp2p_port(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use capnp::message::{Builder, HeapAllocator};
+    use serde_json::json;
+
+    use crate::libp2p_ipc_capnp::{self as schema, libp2p_helper_interface, daemon_interface};
+
+    fn decode(incoming: bool, message: &Builder<HeapAllocator>) -> serde_json::Value {
+        let mut bytes = vec![];
+        capnp::serialize::write_message(&mut bytes, message).unwrap();
+        let message = super::decode(incoming, &bytes).unwrap();
+        serde_json::to_value(&message).unwrap()
+    }
+
+    #[test]
+    fn rpc_request() {
+        let mut message = Builder::new_default();
+        let root = message.init_root::<libp2p_helper_interface::message::Builder>();
+        let mut request = root.init_rpc_request();
+        let mut header = request.reborrow().init_header();
+        header.reborrow().init_time_sent().set_nano_sec(1000);
+        header.init_sequence_number().set_seqno(7);
+        let mut config = request.init_configure().init_config();
+        config.set_statedir("/root/.mina-config/mina_net2");
+        config.set_private_key(&[1, 2, 3]);
+        config.set_network_id("/coda/0.0.1/abc");
+        config
+            .reborrow()
+            .init_listen_on(1)
+            .get(0)
+            .set_representation("/ip4/0.0.0.0/tcp/8302");
+        config.set_metrics_port(9001);
+        config
+            .reborrow()
+            .init_external_multiaddr()
+            .set_representation("/ip4/1.2.3.4/tcp/8302");
+        config.set_unsafe_no_trust_ip(true);
+        config.set_max_connections(50);
+        config.set_peer_protection_ratio(0.5);
+        config
+            .reborrow()
+            .init_known_private_ip_nets(1)
+            .set(0, "10.0.0.0/8");
+        let mut topics = config.init_topic_config(1).get(0).init_topics(2);
+        topics.set(0, "coda/consensus-messages/0.0.1");
+        topics.set(1, "mina/block/1.0.0");
+
+        let value = decode(true, &message);
+        assert_eq!(value["type"], "rpc_request");
+        assert_eq!(value["seqno"], 7);
+        assert_eq!(value["time_sent"], 1000);
+        let request = &value["request"];
+        assert_eq!(request["kind"], "configure");
+        assert_eq!(request["statedir"], "/root/.mina-config/mina_net2");
+        assert_eq!(request["network_id"], "/coda/0.0.1/abc");
+        assert_eq!(request["listen_on"], json!(["/ip4/0.0.0.0/tcp/8302"]));
+        assert_eq!(request["metrics_port"], 9001);
+        assert_eq!(request["external_multiaddr"], "/ip4/1.2.3.4/tcp/8302");
+        assert_eq!(request["unsafe_no_trust_ip"], true);
+        assert_eq!(request["max_connections"], 50);
+        assert_eq!(request["peer_protection_ratio"], 0.5);
+        assert_eq!(request["known_private_ip_nets"], json!(["10.0.0.0/8"]));
+        assert_eq!(
+            request["topic_config"],
+            json!([["coda/consensus-messages/0.0.1", "mina/block/1.0.0"]])
+        );
+        // the secret must not be stored
+        assert!(request.get("private_key").is_none());
+    }
+
+    #[test]
+    fn rpc_response() {
+        let mut message = Builder::new_default();
+        let root = message.init_root::<daemon_interface::message::Builder>();
+        let mut response = root.init_rpc_response();
+        response
+            .reborrow()
+            .init_header()
+            .init_sequence_number()
+            .set_seqno(7);
+        let mut result = response.init_success().init_listen().init_result(2);
+        result
+            .reborrow()
+            .get(0)
+            .set_representation("/ip4/127.0.0.1/tcp/8302");
+        result.get(1).set_representation("/ip4/1.2.3.4/tcp/8302");
+
+        let value = decode(false, &message);
+        assert_eq!(
+            value,
+            json!({
+                "type": "rpc_response",
+                "seqno": 7,
+                "time_sent": 0,
+                "response": {
+                    "kind": "listen",
+                    "result": ["/ip4/127.0.0.1/tcp/8302", "/ip4/1.2.3.4/tcp/8302"],
+                },
+            })
+        );
+
+        let mut message = Builder::new_default();
+        let root = message.init_root::<daemon_interface::message::Builder>();
+        let mut response = root.init_rpc_response();
+        response
+            .reborrow()
+            .init_header()
+            .init_sequence_number()
+            .set_seqno(8);
+        response.set_error("no such stream");
+
+        let value = decode(false, &message);
+        assert_eq!(
+            value["response"],
+            json!({"kind": "error", "error": "no such stream"})
+        );
+        assert_eq!(value["seqno"], 8);
+    }
+
+    #[test]
+    fn helper_push() {
+        let mut message = Builder::new_default();
+        let root = message.init_root::<libp2p_helper_interface::message::Builder>();
+        let mut push = root.init_push_message();
+        push.reborrow()
+            .init_header()
+            .init_time_sent()
+            .set_nano_sec(1000);
+        let mut validation = push.init_validation();
+        validation.reborrow().init_validation_id().set_id(42);
+        validation.set_result(schema::ValidationResult::Reject);
+
+        let value = decode(true, &message);
+        assert_eq!(
+            value,
+            json!({
+                "type": "helper_push",
+                "time_sent": 1000,
+                "push": {"kind": "validation", "validation_id": 42, "result": "reject"},
+            })
+        );
+    }
+
+    #[test]
+    fn daemon_push() {
+        let mut message = Builder::new_default();
+        let root = message.init_root::<daemon_interface::message::Builder>();
+        let mut push = root.init_push_message();
+        push.reborrow()
+            .init_header()
+            .init_time_sent()
+            .set_nano_sec(1000);
+        let mut gossip = push.init_gossip_received();
+        let mut sender = gossip.reborrow().init_sender();
+        sender.set_host("1.2.3.4");
+        sender.set_libp2p_port(8302);
+        sender.init_peer_id().set_id("peer");
+        gossip.reborrow().init_seen_at().set_nano_sec(900);
+        gossip.reborrow().init_expiration().set_nano_sec(2000);
+        gossip.reborrow().init_subscription_id().set_id(3);
+        gossip.reborrow().init_validation_id().set_id(42);
+        gossip.set_data(&[0; 16]);
+
+        let value = decode(false, &message);
+        assert_eq!(
+            value,
+            json!({
+                "type": "daemon_push",
+                "time_sent": 1000,
+                "push": {
+                    "kind": "gossip_received",
+                    "sender": {"peer_id": "peer", "host": "1.2.3.4", "libp2p_port": 8302},
+                    "seen_at": 900,
+                    "expiration": 2000,
+                    "subscription_id": 3,
+                    "validation_id": 42,
+                    "size": 16,
+                },
+            })
+        );
+    }
+}
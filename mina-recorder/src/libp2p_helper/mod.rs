@@ -3,11 +3,23 @@ use std::{io, net::SocketAddr, time::SystemTime, collections::BTreeMap};
 use mina_p2p_messages::{binprot::BinProtRead, gossip::GossipNetMessageV2};
 use radiation::{Absorb, Emit};
 
-use crate::database::{DbCore, CapnpEventWithMetadataKey, CapnpEventWithMetadata};
+use crate::{
+    database::{
+        DbCore, CapnpEventWithMetadataKey, CapnpEventWithMetadata, IpcMessageKey,
+        IpcMessageWithMetadata, GossipValidationKey, GossipValidation,
+    },
+    decode::{MessageType, meshsub_stats::Hash},
+};
+
+/// Decodes every message of the interface, the messages are stored as captured
+pub mod ipc;
+use self::ipc::ValidationResult;
 
 #[derive(Default)]
 pub struct CapnpReader {
     buffer: Vec<u8>,
+    // distinguish the messages read at the same time
+    seq: u32,
 }
 
 #[derive(Absorb, Emit)]
@@ -25,6 +37,36 @@ pub enum CapnpEvent {
     },
 }
 
+/// The gossip the helper passed to the daemon, waits for the verdict
+pub struct PendingValidation {
+    time: SystemTime,
+    peer_id: String,
+    peer_host: String,
+    peer_port: u16,
+    topic: String,
+    kind: MessageType,
+    hash: [u8; 32],
+}
+
+/// The verdicts which never come are dropped when there are too many pending
+const MAX_PENDING_VALIDATIONS: usize = 0x10000;
+
+pub enum ValidationEvent {
+    Received {
+        validation_id: u64,
+        peer_id: String,
+        peer_host: String,
+        peer_port: u16,
+        topic: String,
+        kind: MessageType,
+        hash: [u8; 32],
+    },
+    Verdict {
+        validation_id: u64,
+        result: ValidationResult,
+    },
+}
+
 impl CapnpReader {
     pub fn extend_from_slice(&mut self, other: &[u8]) {
         self.buffer.extend_from_slice(other);
@@ -40,23 +82,53 @@ impl CapnpReader {
         db: &DbCore,
         subscriptions: &mut BTreeMap<u64, String>,
         chain_id: &mut String,
+        validations: &mut BTreeMap<u64, PendingValidation>,
     ) -> bool {
         let mut events = vec![];
+        let mut validation_events = vec![];
         let should_continue = loop {
             if !self.buffer.is_empty() {
                 let mut slice = self.buffer.as_slice();
 
                 let r = if incoming {
-                    process_request(pid, "<-", &mut slice, &mut events, subscriptions, chain_id)
+                    process_request(
+                        pid,
+                        "<-",
+                        &mut slice,
+                        &mut events,
+                        &mut validation_events,
+                        subscriptions,
+                        chain_id,
+                    )
                 } else {
-                    process_response(pid, "->", &mut slice, &mut events, subscriptions)
+                    process_response(
+                        pid,
+                        "->",
+                        &mut slice,
+                        &mut events,
+                        &mut validation_events,
+                        subscriptions,
+                    )
                 };
                 match r {
                     Ok(()) => {
-                        log::debug!(
-                            "capnp {pid} {incoming} consumed: {}",
-                            self.buffer.len() - slice.len()
-                        );
+                        let consumed = self.buffer.len() - slice.len();
+                        log::debug!("capnp {pid} {incoming} consumed: {consumed}");
+                        let key = IpcMessageKey {
+                            time,
+                            pid,
+                            incoming,
+                            seq: self.seq,
+                        };
+                        self.seq = self.seq.wrapping_add(1);
+                        let value = IpcMessageWithMetadata {
+                            real_time,
+                            node_address,
+                            bytes: self.buffer[..consumed].to_vec(),
+                        };
+                        if let Err(err) = db.put_ipc(key, value) {
+                            log::error!("writing capnp message in database {err}");
+                        }
                         self.buffer = slice.to_vec();
                     }
                     Err(err) if err.description == "failed to fill the whole buffer" => {
@@ -129,6 +201,68 @@ impl CapnpReader {
             }
         }
 
+        for event in validation_events {
+            match event {
+                ValidationEvent::Received {
+                    validation_id,
+                    peer_id,
+                    peer_host,
+                    peer_port,
+                    topic,
+                    kind,
+                    hash,
+                } => {
+                    if validations.len() >= MAX_PENDING_VALIDATIONS {
+                        if let Some(oldest) = validations.keys().next().cloned() {
+                            validations.remove(&oldest);
+                        }
+                    }
+                    let pending = PendingValidation {
+                        time,
+                        peer_id,
+                        peer_host,
+                        peer_port,
+                        topic,
+                        kind,
+                        hash,
+                    };
+                    validations.insert(validation_id, pending);
+                }
+                ValidationEvent::Verdict {
+                    validation_id,
+                    result,
+                } => {
+                    let Some(pending) = validations.remove(&validation_id) else {
+                        log::debug!("capnp {pid} unknown validation {validation_id}");
+                        continue;
+                    };
+                    let key = GossipValidationKey {
+                        time,
+                        pid,
+                        validation_id,
+                    };
+                    let value = GossipValidation {
+                        validation_id,
+                        received_time: pending.time,
+                        time,
+                        latency: time.duration_since(pending.time).unwrap_or_default(),
+                        pid,
+                        node_address,
+                        peer_id: pending.peer_id,
+                        peer_host: pending.peer_host,
+                        peer_port: pending.peer_port,
+                        topic: pending.topic,
+                        kind: pending.kind,
+                        hash: Hash(pending.hash),
+                        result,
+                    };
+                    if let Err(err) = db.put_gossip_validation(key, value) {
+                        log::error!("writing gossip validation in database {err}");
+                    }
+                }
+            }
+        }
+
         should_continue
     }
 }
@@ -158,6 +292,7 @@ pub fn process_request<R>(
     incoming: &str,
     reader: R,
     events: &mut Vec<CapnpEvent>,
+    validation_events: &mut Vec<ValidationEvent>,
     subscriptions: &mut BTreeMap<u64, String>,
     chain_id: &mut String,
 ) -> capnp::Result<()>
@@ -224,6 +359,12 @@ where
             Ok(push_message::AddResource(Ok(resource))) => {
                 let _ = resource;
             }
+            Ok(push_message::Validation(Ok(validation))) => {
+                validation_events.push(ValidationEvent::Verdict {
+                    validation_id: validation.get_validation_id()?.get_id(),
+                    result: validation.get_result()?.into(),
+                });
+            }
             _ => (),
         },
         _ => (),
@@ -237,6 +378,7 @@ pub fn process_response<R>(
     incoming: &str,
     reader: R,
     events: &mut Vec<CapnpEvent>,
+    validation_events: &mut Vec<ValidationEvent>,
     subscriptions: &mut BTreeMap<u64, String>,
 ) -> capnp::Result<()>
 where
@@ -288,12 +430,36 @@ where
                     .cloned()
                     .unwrap_or("coda/consensus-messages/0.0.1".to_owned());
 
+                let hash = calc_hash(data, &topic);
+                let kind = match data.get(8) {
+                    Some(0) => Some(MessageType::PublishNewState),
+                    Some(1) => Some(MessageType::PublishSnarkPoolDiff),
+                    Some(2) => Some(MessageType::PublishTransactionPoolDiff),
+                    kind => {
+                        log::warn!(
+                            "capnp message {pid} {incoming} gossip of unknown kind {kind:?} from {peer_id}"
+                        );
+                        None
+                    }
+                };
+                if let Some(kind) = kind {
+                    validation_events.push(ValidationEvent::Received {
+                        validation_id: msg.get_validation_id()?.get_id(),
+                        peer_id: peer_id.clone(),
+                        peer_host: peer_host.clone(),
+                        peer_port,
+                        topic,
+                        kind,
+                        hash,
+                    });
+                }
+
                 events.push(CapnpEvent::ReceivedGossip {
                     peer_id,
                     peer_host,
                     peer_port,
                     msg: data[8..].to_vec(),
-                    hash: calc_hash(data, &topic),
+                    hash,
                 });
             }
             _ => (),
@@ -315,3 +481,166 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+#[test]
+fn validation_verdicts() {
+    use std::time::Duration;
+    use temp_dir::TempDir;
+    use crate::{
+        database::GossipValidationParams,
+        libp2p_ipc_capnp::{self as schema, daemon_interface, libp2p_helper_interface},
+    };
+
+    // the helper passes the gossip to the daemon
+    fn gossip_received(validation_id: u64, kind: u8) -> Vec<u8> {
+        let mut message = capnp::message::Builder::new_default();
+        let root = message.init_root::<daemon_interface::message::Builder>();
+        let mut gossip = root.init_push_message().init_gossip_received();
+        let mut sender = gossip.reborrow().init_sender();
+        sender.set_host("1.2.3.4");
+        sender.set_libp2p_port(8302);
+        sender.init_peer_id().set_id("peer_a");
+        gossip.reborrow().init_subscription_id().set_id(0);
+        gossip.reborrow().init_validation_id().set_id(validation_id);
+        gossip.set_data(&[0, 0, 0, 0, 0, 0, 0, 0, kind]);
+        let mut bytes = vec![];
+        capnp::serialize::write_message(&mut bytes, &message).unwrap();
+        bytes
+    }
+
+    // the daemon sends the verdict to the helper
+    fn verdict(validation_id: u64, result: schema::ValidationResult) -> Vec<u8> {
+        let mut message = capnp::message::Builder::new_default();
+        let root = message.init_root::<libp2p_helper_interface::message::Builder>();
+        let mut validation = root.init_push_message().init_validation();
+        validation
+            .reborrow()
+            .init_validation_id()
+            .set_id(validation_id);
+        validation.set_result(result);
+        let mut bytes = vec![];
+        capnp::serialize::write_message(&mut bytes, &message).unwrap();
+        bytes
+    }
+
+    let d = TempDir::new().expect("cannot create temporary directory");
+    let db = DbCore::open(d.path()).unwrap();
+
+    let node_address = "0.0.0.0:0".parse().unwrap();
+    let mut subscriptions = BTreeMap::new();
+    let mut chain_id = String::new();
+    let mut helper = CapnpReader::default();
+    let mut daemon = CapnpReader::default();
+    let mut feed = |reader: &mut CapnpReader,
+                    incoming,
+                    time,
+                    bytes: Vec<u8>,
+                    validations: &mut BTreeMap<u64, PendingValidation>| {
+        reader.extend_from_slice(&bytes);
+        assert!(reader.process(
+            1,
+            incoming,
+            node_address,
+            time,
+            time,
+            &db,
+            &mut subscriptions,
+            &mut chain_id,
+            validations,
+        ));
+    };
+
+    let mut validations = BTreeMap::new();
+    let now = SystemTime::now();
+    let ms = Duration::from_millis;
+    feed(
+        &mut daemon,
+        false,
+        now,
+        gossip_received(1, 1),
+        &mut validations,
+    );
+    feed(
+        &mut daemon,
+        false,
+        now + ms(1),
+        gossip_received(2, 2),
+        &mut validations,
+    );
+    // the verdicts come in other order than the gossip
+    let rejected = verdict(2, schema::ValidationResult::Reject);
+    feed(&mut helper, true, now + ms(3), rejected, &mut validations);
+    let accepted = verdict(1, schema::ValidationResult::Accept);
+    feed(&mut helper, true, now + ms(7), accepted, &mut validations);
+    // nothing received with this id
+    let unknown = verdict(3, schema::ValidationResult::Ignore);
+    feed(&mut helper, true, now + ms(8), unknown, &mut validations);
+    assert!(validations.is_empty());
+
+    let stored = db
+        .fetch_gossip_validations(&GossipValidationParams::default())
+        .unwrap();
+    let stored = stored
+        .iter()
+        .map(|e| &e.validation)
+        .map(|v| {
+            (
+                v.validation_id,
+                v.latency,
+                v.kind.clone(),
+                v.result,
+                v.peer_id.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(
+        stored
+            == [
+                (
+                    2,
+                    ms(2),
+                    MessageType::PublishTransactionPoolDiff,
+                    ValidationResult::Reject,
+                    "peer_a",
+                ),
+                (
+                    1,
+                    ms(7),
+                    MessageType::PublishSnarkPoolDiff,
+                    ValidationResult::Accept,
+                    "peer_a",
+                ),
+            ]
+    );
+
+    // the oldest pending gossip is dropped, its verdict is unknown then
+    for validation_id in 0x100..(0x100 + MAX_PENDING_VALIDATIONS as u64) {
+        let pending = PendingValidation {
+            time: now,
+            peer_id: "peer_b".to_owned(),
+            peer_host: String::new(),
+            peer_port: 0,
+            topic: String::new(),
+            kind: MessageType::PublishSnarkPoolDiff,
+            hash: [0; 32],
+        };
+        validations.insert(validation_id, pending);
+    }
+    feed(
+        &mut daemon,
+        false,
+        now + ms(10),
+        gossip_received(0x100000, 1),
+        &mut validations,
+    );
+    assert_eq!(validations.len(), MAX_PENDING_VALIDATIONS);
+    assert!(!validations.contains_key(&0x100));
+    assert!(validations.contains_key(&0x100000));
+    let evicted = verdict(0x100, schema::ValidationResult::Reject);
+    feed(&mut helper, true, now + ms(11), evicted, &mut validations);
+    let stored = db
+        .fetch_gossip_validations(&GossipValidationParams::default())
+        .unwrap();
+    assert_eq!(stored.len(), 2);
+}
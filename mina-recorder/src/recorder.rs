@@ -12,7 +12,8 @@ use parking_lot::Mutex;
 use super::{
     event::{EventMetadata, ConnectionInfo, DirectedId},
     connection::{HandleData, pnet, multistream_select, noise, mux, mina_protocol},
-    database::{DbFacade, DbGroup},
+    database::{DbFacade, DbGroup, ConnectionId, ConnectionStats, TcpInfoSample},
+    chunk::EncryptionStatus,
     tester::Tester,
     stats::{Stats, StatsState},
 };
@@ -26,12 +27,15 @@ pub struct P2pRecorder {
     tester: Option<Tester>,
     cns: BTreeMap<ConnectionInfo, ThreadContext>,
     cns_main_thread: BTreeMap<ConnectionInfo, ConnectionContext>,
+    // supplied by the user, tried after the guessed chain id
+    chain_ids: Vec<String>,
     // this is used by capnp reader
     // TODO: split
     pub cx: Arc<Cx>,
 }
 
 pub struct ThreadContext {
+    id: ConnectionId,
     handle: JoinHandle<()>,
     tx: mpsc::Sender<NetworkChunk>,
 }
@@ -39,6 +43,7 @@ pub struct ThreadContext {
 pub struct ConnectionContext {
     cn: Cn,
     db: DbGroup,
+    mid_stream: bool,
 }
 
 pub struct NetworkChunk {
@@ -46,6 +51,8 @@ pub struct NetworkChunk {
     pub data: Vec<u8>,
     pub incoming: bool,
     pub buffered: usize,
+    // the data is the plaintext of the Noise transport message
+    pub plaintext: bool,
 }
 
 // my local sandbox
@@ -67,6 +74,8 @@ const CHAINS: [(&str, &str); 3] = [
 
 pub struct Cx {
     pub apps: Mutex<BTreeMap<u32, (String, SocketAddr)>>,
+    // pid -> the container or pod of the process
+    pub nodes: Mutex<BTreeMap<u32, String>>,
     pub stats_state: Mutex<BTreeMap<SocketAddr, StatsState>>,
     pub db: DbFacade,
     pub stats: Stats,
@@ -82,6 +91,10 @@ impl Cx {
             .map(|(_, addr)| addr.clone())
             .unwrap_or(SocketAddr::new(IpAddr::V4(0.into()), 0))
     }
+
+    pub fn pid_to_node(&self, pid: u32) -> String {
+        self.nodes.lock().get(&pid).cloned().unwrap_or_default()
+    }
 }
 
 #[derive(Clone)]
@@ -92,6 +105,15 @@ pub struct Aggregator {
 }
 
 impl Aggregator {
+    /// `url` is the full url where the events are posted
+    pub fn new(url: reqwest::Url, debugger_name: String) -> Self {
+        Aggregator {
+            client: reqwest::blocking::Client::new(),
+            url,
+            debugger_name,
+        }
+    }
+
     pub fn post_event<T>(&self, event: T)
     where
         T: Serialize,
@@ -115,39 +137,19 @@ impl Aggregator {
 }
 
 impl P2pRecorder {
-    pub fn new(db: DbFacade, test: bool) -> Self {
-        use std::env;
-
-        let aggregator = if let Ok(aggregator_str) = env::var("AGGREGATOR") {
-            log::info!("use aggregator {aggregator_str}");
-            if let Ok(aggregator) = aggregator_str.parse::<reqwest::Url>() {
-                let debugger_name = env::var("DEBUGGER_NAME").unwrap_or("noname".to_owned());
-                let client = reqwest::blocking::Client::new();
-                let url = aggregator.join("new").expect("url is valid");
-                // let body = format!("{{\"alias\": {hostname:?}, \"port\": {port} }}");
-                // match client.post(url).body(body).send() {
-                //     Ok(_) => (),
-                //     Err(err) => log::error!("cannot register at aggregator: {err}"),
-                // }
-                Some(Aggregator {
-                    client,
-                    url,
-                    debugger_name,
-                })
-            } else {
-                log::error!("cannot parse aggregator url {aggregator_str}");
-                None
-            }
-        } else {
-            None
-        };
+    pub fn new(db: DbFacade, test: bool, aggregator: Option<Aggregator>) -> Self {
+        if let Some(aggregator) = &aggregator {
+            log::info!("use aggregator {}", aggregator.url);
+        }
 
         P2pRecorder {
             tester: if test { Some(Tester::default()) } else { None },
             cns: BTreeMap::default(),
             cns_main_thread: BTreeMap::default(),
+            chain_ids: vec![],
             cx: Arc::new(Cx {
                 apps: Mutex::default(),
+                nodes: Mutex::default(),
                 db,
                 stats: Stats::default(),
                 stats_state: Mutex::default(),
@@ -164,6 +166,15 @@ impl P2pRecorder {
             .map(|(_, addr)| addr.set_port(port));
     }
 
+    /// More chain ids the pnet tries if the guessed one does not match
+    pub fn set_chain_ids(&mut self, chain_ids: Vec<String>) {
+        self.chain_ids = chain_ids;
+    }
+
+    pub fn set_node(&mut self, pid: u32, node: String) {
+        self.cx.nodes.lock().insert(pid, node);
+    }
+
     pub fn on_alias(&mut self, pid: u32, alias: String) {
         let ip = alias
             .split('-')
@@ -177,6 +188,30 @@ impl P2pRecorder {
             .insert(pid, (alias, SocketAddr::new(ip, 8302)));
     }
 
+    /// The chain ids the pnet tries, the suggested or guessed by the alias first,
+    /// then the user supplied, then every known chain
+    fn chain_id_candidates(&self, alias: &str, suggested_chain_id: String) -> Vec<String> {
+        let chain_id = if !suggested_chain_id.is_empty() {
+            suggested_chain_id
+        } else {
+            let mut it = alias.split('-');
+            let network = it.next().expect("`split` must yield at least one");
+            CHAINS
+                .iter()
+                .find_map(|(k, v)| if *k == network { Some(*v) } else { None })
+                .unwrap_or(network)
+                .to_owned()
+        };
+        let mut chain_ids = vec![chain_id];
+        let known = CHAINS.iter().map(|(_, v)| (*v).to_owned());
+        for candidate in self.chain_ids.iter().cloned().chain(known) {
+            if !chain_ids.contains(&candidate) {
+                chain_ids.push(candidate);
+            }
+        }
+        chain_ids
+    }
+
     pub fn on_connect<const MAIN_THREAD: bool>(
         &mut self,
         incoming: bool,
@@ -195,17 +230,7 @@ impl P2pRecorder {
                 .map(|(a, _)| a)
                 .unwrap_or_default()
         };
-        let chain_id = if !suggested_chain_id.is_empty() {
-            suggested_chain_id
-        } else {
-            let mut it = alias.split('-');
-            let network = it.next().expect("`split` must yield at least one");
-            CHAINS
-                .iter()
-                .find_map(|(k, v)| if *k == network { Some(*v) } else { None })
-                .unwrap_or(network)
-                .to_owned()
-        };
+        let chain_ids = self.chain_id_candidates(&alias, suggested_chain_id);
         let id = DirectedId {
             metadata,
             alias: alias.clone(),
@@ -216,22 +241,25 @@ impl P2pRecorder {
             id.metadata.id.clone(),
             incoming,
             id.alias.clone(),
+            self.cx.pid_to_node(id.metadata.id.pid),
             id.metadata.time,
         ) {
             Ok(group) => {
                 log::debug!("{id} {} new connection", group.id());
                 let info = id.metadata.id.clone();
+                let cn_id = group.id();
 
                 let (tx, rx) = mpsc::channel();
                 let cx = self.cx.clone();
-                let mut cn = Cn::new(chain_id.as_bytes());
+                let mut cn = Cn::new(chain_ids.clone());
 
                 if MAIN_THREAD {
                     self.cns_main_thread.insert(
                         id.metadata.id,
                         ConnectionContext {
-                            cn: Cn::new(chain_id.as_bytes()),
+                            cn: Cn::new(chain_ids),
                             db: group,
+                            mid_stream: false,
                         },
                     );
 
@@ -244,6 +272,7 @@ impl P2pRecorder {
                         mut data,
                         incoming,
                         buffered,
+                        plaintext,
                     }) = rx.recv()
                     {
                         let alias = {
@@ -259,13 +288,22 @@ impl P2pRecorder {
                             incoming,
                             buffered,
                         };
-                        if let Err(err) = cn.on_data(id.clone(), &mut data, &cx, &group) {
+                        let result = if plaintext {
+                            cn.on_plaintext(id.clone(), &mut data, &cx, &group)
+                        } else {
+                            cn.on_data(id.clone(), &mut data, &cx, &group)
+                        };
+                        if let Err(err) = result {
                             log::error!("{id}: {err}");
                         }
                     }
                     log::debug!("{id} {} disconnect", group.id());
                 });
-                let t_cx = ThreadContext { handle, tx };
+                let t_cx = ThreadContext {
+                    id: cn_id,
+                    handle,
+                    tx,
+                };
 
                 self.cns.insert(info, t_cx);
             }
@@ -275,6 +313,48 @@ impl P2pRecorder {
         }
     }
 
+    /// The connection existed before the process was tracked, the beginning is lost,
+    /// so the data is stored raw and only counted
+    pub fn on_connect_mid_stream(&mut self, incoming: bool, metadata: EventMetadata) {
+        if self.tester.is_some() {
+            return;
+        }
+        let alias = {
+            let lock = self.cx.apps.lock();
+            lock.get(&metadata.id.pid)
+                .cloned()
+                .map(|(a, _)| a)
+                .unwrap_or_default()
+        };
+        // the pnet state is never fed, but it must be valid
+        let chain_ids = self.chain_id_candidates(&alias, String::new());
+        let node = self.cx.pid_to_node(metadata.id.pid);
+        let group = self
+            .cx
+            .db
+            .add(metadata.id.clone(), incoming, alias, node, metadata.time)
+            .and_then(|group| {
+                self.cx.db.mark_joined_mid_stream(group.id())?;
+                Ok(group)
+            });
+        match group {
+            Ok(group) => {
+                log::info!("{metadata} {} joined mid-stream", group.id());
+                self.cns_main_thread.insert(
+                    metadata.id,
+                    ConnectionContext {
+                        cn: Cn::new(chain_ids),
+                        db: group,
+                        mid_stream: true,
+                    },
+                );
+            }
+            Err(err) => {
+                log::error!("{metadata} joined mid-stream, cannot write in db {err}");
+            }
+        }
+    }
+
     pub fn on_disconnect(&mut self, metadata: EventMetadata, buffered: usize) {
         if let Some(tester) = &mut self.tester {
             tester.on_disconnect(metadata);
@@ -305,16 +385,40 @@ impl P2pRecorder {
         }
     }
 
-    #[rustfmt::skip]
     pub fn on_data(
+        &mut self,
+        incoming: bool,
+        metadata: EventMetadata,
+        buffered: usize,
+        bytes: Vec<u8>,
+    ) {
+        self.on_chunk(incoming, metadata, buffered, bytes, false)
+    }
+
+    /// The plaintext of the Noise transport message captured from the process
+    pub fn on_plaintext(
+        &mut self,
+        incoming: bool,
+        metadata: EventMetadata,
+        buffered: usize,
+        bytes: Vec<u8>,
+    ) {
+        self.on_chunk(incoming, metadata, buffered, bytes, true)
+    }
+
+    #[rustfmt::skip]
+    fn on_chunk(
         &mut self,
         incoming: bool,
         metadata: EventMetadata,
         buffered: usize,
         mut bytes: Vec<u8>,
+        plaintext: bool,
     ) {
         if let Some(tester) = &mut self.tester {
-            tester.on_data(incoming, metadata, bytes);
+            if !plaintext {
+                tester.on_data(incoming, metadata, bytes);
+            }
             return;
         }
         if let Some(t_cx) = self.cns.get_mut(&metadata.id) {
@@ -323,8 +427,27 @@ impl P2pRecorder {
                 data: bytes,
                 incoming,
                 buffered,
+                plaintext,
             }).unwrap_or_default();
         } else if let Some(cn_cx) = self.cns_main_thread.get_mut(&metadata.id) {
+            if cn_cx.mid_stream {
+                // the state of the layers above the Noise is lost too
+                if plaintext {
+                    return;
+                }
+                let stats = ConnectionStats {
+                    total_bytes: bytes.len() as u64,
+                    ..Default::default()
+                };
+                let result = cn_cx
+                    .db
+                    .add_raw(EncryptionStatus::Raw, incoming, metadata.time, &bytes)
+                    .and_then(|_| cn_cx.db.update(stats, incoming));
+                if let Err(err) = result {
+                    log::error!("{metadata}: {err}");
+                }
+                return;
+            }
             let alias = {
                 let lock = self.cx.apps.lock();
                 lock.get(&metadata.id.pid)
@@ -338,12 +461,44 @@ impl P2pRecorder {
                 incoming,
                 buffered,
             };
-            if let Err(err) = cn_cx.cn.on_data(id.clone(), &mut bytes, &self.cx, &cn_cx.db) {
+            let result = if plaintext {
+                cn_cx.cn.on_plaintext(id.clone(), &mut bytes, &self.cx, &cn_cx.db)
+            } else {
+                cn_cx.cn.on_data(id.clone(), &mut bytes, &self.cx, &cn_cx.db)
+            };
+            if let Err(err) = result {
                 log::error!("{id}: {err}");
             }
         }
     }
 
+    pub fn on_capture_incomplete(&mut self, info: &ConnectionInfo, time: SystemTime) {
+        let id = if let Some(t_cx) = self.cns.get(info) {
+            t_cx.id
+        } else if let Some(cn_cx) = self.cns_main_thread.get(info) {
+            cn_cx.db.id()
+        } else {
+            return;
+        };
+        log::warn!("{id} capture incomplete");
+        if let Err(err) = self.cx.db.mark_capture_incomplete(id, time) {
+            log::error!("{id} failed to mark capture incomplete: {err}");
+        }
+    }
+
+    pub fn on_tcp_info(&mut self, info: &ConnectionInfo, sample: TcpInfoSample) {
+        let id = if let Some(t_cx) = self.cns.get(info) {
+            t_cx.id
+        } else if let Some(cn_cx) = self.cns_main_thread.get(info) {
+            cn_cx.db.id()
+        } else {
+            return;
+        };
+        if let Err(err) = self.cx.db.add_tcp_info(id, sample) {
+            log::error!("{id} failed to store tcp info: {err}");
+        }
+    }
+
     pub fn on_randomness(&mut self, pid: u32, bytes: Vec<u8>, time: SystemTime) {
         use time::OffsetDateTime;
 
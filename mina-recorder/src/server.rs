@@ -8,7 +8,10 @@ use warp::{
 
 use crate::{meshsub_stats::BlockStat, application::Application};
 
-use super::database::{DbCore, DbFacade, Params};
+use super::database::{
+    DbCore, DbFacade, Params, ConnectionId, Direction, RpcParams, HelperLogParams, IpcParams,
+    GossipValidationParams,
+};
 
 fn connection(
     db: DbCore,
@@ -16,7 +19,11 @@ fn connection(
     warp::path!("connection" / u64).map(move |id: u64| -> reply::WithStatus<Json> {
         match db.fetch_connection(id) {
             Ok(v) => {
-                let v = v.post_process(None);
+                let mut v = v.post_process(None);
+                let tcp_info = db.fetch_tcp_info(ConnectionId(id));
+                v.as_object_mut()
+                    .expect("connection must be a structure")
+                    .insert("tcp_info".to_owned(), serde_json::json!(tcp_info));
                 reply::with_status(reply::json(&v), StatusCode::OK)
             }
             Err(err) => reply::with_status(
@@ -27,6 +34,153 @@ fn connection(
     })
 }
 
+fn connection_diagnostics(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("connection" / u64 / "diagnostics").map(move |id: u64| -> WithStatus<Json> {
+        match db.fetch_noise_diagnostics(ConnectionId(id)) {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn bitswap(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("bitswap" / String).map(move |cid: String| -> WithStatus<Json> {
+        match db.fetch_bitswap(&cid) {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn rpc(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("rpc")
+        .and(warp::query::query())
+        .map(move |params: RpcParams| -> WithStatus<Json> {
+            let v = db.fetch_rpc_calls(&params);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn rpc_latency(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("rpc" / "latency")
+        .and(warp::query::query())
+        .map(move |params: RpcParams| -> WithStatus<Json> {
+            let v = db.fetch_rpc_latency(&params);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn helper_logs(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("helper_logs").and(warp::query::query()).map(
+        move |params: HelperLogParams| -> WithStatus<Json> {
+            let v = db.fetch_helper_logs(&params);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        },
+    )
+}
+
+fn ipc(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("ipc")
+        .and(warp::query::query())
+        .map(move |params: IpcParams| -> WithStatus<Json> {
+            let v = db.fetch_ipc(&params);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn gossip_validations(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("gossip_validations")
+        .and(warp::query::query())
+        .map(move |params: GossipValidationParams| -> WithStatus<Json> {
+            match db.fetch_gossip_validations(&params) {
+                Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+                Err(err) => reply::with_status(
+                    reply::json(&err.to_string()),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }
+        })
+}
+
+fn gossip_validations_rejected(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("gossip_validations" / "rejected")
+        .and(warp::query::query())
+        .map(move |params: GossipValidationParams| -> WithStatus<Json> {
+            let v = db.fetch_rejected_gossip(&params);
+            reply::with_status(reply::json(&v), StatusCode::OK)
+        })
+}
+
+fn snark_workers(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("snark_workers").map(move || -> WithStatus<Json> {
+        match db.fetch_snark_workers() {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn peer_exchange(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peer_exchange" / String).map(move |peer_id: String| -> WithStatus<Json> {
+        match db.fetch_peer_exchange(&peer_id) {
+            Ok(v) => reply::with_status(reply::json(&v), StatusCode::OK),
+            Err(err) => reply::with_status(
+                reply::json(&err.to_string()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }
+    })
+}
+
+fn peer_protocols(
+    db: DbCore,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("peer" / String / "protocols").map(move |peer_id: String| -> WithStatus<Json> {
+        let changes = db
+            .fetch_peer_protocols(&peer_id, Direction::Forward)
+            .collect::<Vec<_>>();
+        let protocols = changes
+            .last()
+            .map(|v| v.protocols.clone())
+            .unwrap_or_default();
+        let v = serde_json::json!({
+            "peer_id": peer_id,
+            "protocols": protocols,
+            "changes": changes,
+        });
+        reply::with_status(reply::json(&v), StatusCode::OK)
+    })
+}
+
 fn connections(
     db: DbCore,
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
@@ -272,8 +426,10 @@ fn firewall_whitelist_set(
         .and(warp::post())
         .map(move |enable_whitelist| -> WithStatus<Json> {
             if let Some(app) = &app {
-                app.enable_firewall(enable_whitelist);
-                reply::with_status(reply::json(&()), StatusCode::OK)
+                match app.enable_firewall(enable_whitelist) {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
             } else {
                 reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
             }
@@ -287,8 +443,64 @@ fn firewall_whitelist_clear(
         .and(warp::post())
         .map(move || -> WithStatus<Json> {
             if let Some(app) = &app {
-                app.disable_firewall();
-                reply::with_status(reply::json(&()), StatusCode::OK)
+                match app.disable_firewall() {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
+            } else {
+                reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+            }
+        })
+}
+
+fn firewall_blacklist_set(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("firewall" / "blacklist")
+        .and(warp::body::json())
+        .and(warp::post())
+        .map(move |blacklist| -> WithStatus<Json> {
+            if let Some(app) = &app {
+                match app.set_firewall_blacklist(blacklist) {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
+            } else {
+                reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+            }
+        })
+}
+
+fn firewall_rate_limit_set(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("firewall" / "rate_limit")
+        .and(warp::body::json())
+        .and(warp::post())
+        .map(move |limits| -> WithStatus<Json> {
+            if let Some(app) = &app {
+                match app.set_firewall_rate_limits(limits) {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
+            } else {
+                reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+            }
+        })
+}
+
+fn firewall_packet_loss_set(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("firewall" / "packet_loss")
+        .and(warp::body::json())
+        .and(warp::post())
+        .map(move |rules| -> WithStatus<Json> {
+            if let Some(app) = &app {
+                match app.set_firewall_packet_loss(rules) {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
             } else {
                 reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
             }
@@ -300,7 +512,8 @@ fn firewall_stats(
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("firewall" / "stats").map(move || -> WithStatus<Json> {
         if let Some(app) = &app {
-            let list = app.get_firewall_stats();
+            // json object keys must be strings, so the list of pairs
+            let list = app.get_firewall_stats().into_iter().collect::<Vec<_>>();
             reply::with_status(reply::json(&list), StatusCode::OK)
         } else {
             reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
@@ -308,6 +521,71 @@ fn firewall_stats(
     })
 }
 
+fn capture_drops(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("capture" / "drops").map(move || -> WithStatus<Json> {
+        if let Some(app) = &app {
+            let list = app.get_capture_drops();
+            reply::with_status(reply::json(&list), StatusCode::OK)
+        } else {
+            reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+        }
+    })
+}
+
+fn capture_reorder(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("capture" / "reorder").map(move || -> WithStatus<Json> {
+        if let Some(app) = &app {
+            let stats = app.get_reorder_stats();
+            reply::with_status(reply::json(&stats), StatusCode::OK)
+        } else {
+            reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+        }
+    })
+}
+
+fn process_matching(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("process_matching").map(move || -> WithStatus<Json> {
+        if let Some(app) = &app {
+            let matching = app.get_process_matching();
+            reply::with_status(reply::json(&matching), StatusCode::OK)
+        } else {
+            reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+        }
+    })
+}
+
+fn process_matching_set(
+    app: Option<Application>,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("process_matching")
+        .and(warp::body::json())
+        .and(warp::post())
+        .map(move |matching| -> WithStatus<Json> {
+            if let Some(app) = &app {
+                match app.set_process_matching(matching) {
+                    Ok(()) => reply::with_status(reply::json(&()), StatusCode::OK),
+                    Err(err) => reply::with_status(reply::json(&err), StatusCode::BAD_REQUEST),
+                }
+            } else {
+                reply::with_status(reply::json(&()), StatusCode::NOT_FOUND)
+            }
+        })
+}
+
+fn config(
+    config: serde_json::Value,
+) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
+    warp::path!("config").map(move || -> WithStatus<Json> {
+        reply::with_status(reply::json(&config), StatusCode::OK)
+    })
+}
+
 fn version(
 ) -> impl Filter<Extract = (WithStatus<Json>,), Error = Rejection> + Clone + Sync + Send + 'static {
     warp::path!("version")
@@ -332,6 +610,7 @@ fn openapi(
 fn routes(
     db: DbCore,
     app: Option<Application>,
+    config_json: serde_json::Value,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Sync + Send + 'static {
     use warp::reply::with;
 
@@ -364,10 +643,21 @@ fn routes(
 
     let gets = warp::get().and(
         connection(db.clone())
+            .or(connection_diagnostics(db.clone()))
             .or(connections(db.clone()))
             .or(message(db.clone()))
             .or(message_hex(db.clone()))
             .or(messages(db.clone()))
+            .or(bitswap(db.clone()))
+            .or(peer_protocols(db.clone()))
+            .or(peer_exchange(db.clone()))
+            .or(rpc(db.clone()))
+            .or(rpc_latency(db.clone()))
+            .or(snark_workers(db.clone()))
+            .or(helper_logs(db.clone()))
+            .or(ipc(db.clone()))
+            .or(gossip_validations(db.clone()))
+            .or(gossip_validations_rejected(db.clone()))
             .or(stats(db.clone()))
             .or(stats_last(db.clone()))
             .or(stats_latest(db.clone()))
@@ -382,10 +672,20 @@ fn routes(
             .or(libp2p_ipc_latest(db.clone()))
             .or(libp2p_ipc_all(db))
             .or(firewall_stats(app.clone()))
+            .or(capture_drops(app.clone()))
+            .or(capture_reorder(app.clone()))
+            .or(process_matching(app.clone()))
+            .or(config(config_json))
             .or(version().or(openapi())),
     );
-    let posts =
-        warp::post().and(firewall_whitelist_set(app.clone()).or(firewall_whitelist_clear(app)));
+    let posts = warp::post().and(
+        firewall_whitelist_set(app.clone())
+            .or(firewall_whitelist_clear(app.clone()))
+            .or(firewall_blacklist_set(app.clone()))
+            .or(firewall_rate_limit_set(app.clone()))
+            .or(firewall_packet_loss_set(app.clone()))
+            .or(process_matching_set(app)),
+    );
 
     gets.or(posts)
         .with(with::header("Content-Type", "application/json"))
@@ -400,6 +700,7 @@ pub fn spawn<P, Q, R>(
     app: Option<Application>,
     key_path: Option<Q>,
     cert_path: Option<R>,
+    config_json: serde_json::Value,
 ) -> (DbFacade, impl FnOnce(), thread::JoinHandle<()>)
 where
     P: AsRef<Path>,
@@ -428,7 +729,7 @@ where
     };
     log::info!("using db {}", path.as_ref().display());
     let addr = ([0, 0, 0, 0], port);
-    let routes = routes(db.core(), app);
+    let routes = routes(db.core(), app, config_json);
     let shutdown = async move {
         rx.await.expect("corresponding sender should exist");
         log::info!("terminating http server...");
@@ -1,17 +1,85 @@
+use std::collections::BTreeMap;
+
+use super::{
+    event::{EventMetadata, DirectedId},
+    connection::mina_protocol::{rpc, rpc_sink},
+    database::{DbFacade, DbGroup, StreamId, StreamKind},
+};
+
+/// The snark worker talks to the daemon by the async rpc over tcp, without libp2p
 #[derive(Default)]
 pub struct SnarkWorkerState {
+    // fd -> the rpc connection, `None` if the fd is not rpc (logs, etc.)
+    fds: BTreeMap<u32, Option<Rpc>>,
+}
 
+struct Rpc {
+    state: rpc::State,
+    db: DbGroup,
+}
+
+/// The first message of the async rpc is the handshake, the list of ints,
+/// the first is the magic number, `"RPC\0"` in little endian
+fn is_handshake(bytes: &[u8]) -> bool {
+    bytes.len() >= 14 && bytes[9] == 0xfd && &bytes[10..14] == b"RPC\0"
 }
 
 impl SnarkWorkerState {
-    pub fn handle_data(&mut self, incoming: bool, fd: u32, data: Vec<u8>) {
-        let arrow = if incoming {
-            "<-"
-        } else {
-            "->"
+    /// The worker opens a new connection for every rpc, the fd is reused
+    pub fn on_close(&mut self, fd: u32) {
+        self.fds.remove(&fd);
+    }
+
+    /// `incoming` is the data the worker reads, the address of the metadata is unknown
+    pub fn handle_data(
+        &mut self,
+        db: &DbFacade,
+        node: String,
+        metadata: EventMetadata,
+        incoming: bool,
+        mut data: Vec<u8>,
+    ) {
+        let fd = metadata.id.fd;
+        let rpc = self.fds.entry(fd).or_insert_with(|| {
+            if !is_handshake(&data) {
+                return None;
+            }
+            let info = metadata.id.clone();
+            let alias = "snark_worker".to_owned();
+            match db.add(info, false, alias, node, metadata.time) {
+                Ok(db) => Some(Rpc {
+                    state: rpc::State::default(),
+                    db,
+                }),
+                Err(err) => {
+                    log::error!("snark worker {fd}: {err}");
+                    None
+                }
+            }
+        });
+        let Some(Rpc { state, db: group }) = rpc else {
+            return;
         };
-        let _ = (fd, arrow, data);
-        // log::info!("snark worker {fd} {arrow} {}", hex::encode(data));
+
+        let id = DirectedId {
+            metadata,
+            alias: "snark_worker".to_owned(),
+            incoming,
+            buffered: 0,
+        };
+        let stream = group.get(StreamId::Forward(0));
+        let kind = StreamKind::SnarkWorker;
+        match state.extend(&mut data) {
+            Err(err) => log::error!("{id} {}: {err}", group.id()),
+            Ok(None) => loop {
+                match state.next_msg() {
+                    Err(err) => log::error!("{id} {}: {err}", group.id()),
+                    Ok(None) => break,
+                    Ok(Some((msg, call))) => rpc_sink(&id, group, &stream, kind, state, &msg, call),
+                }
+            },
+            Ok(Some((msg, call))) => rpc_sink(&id, group, &stream, kind, state, &msg, call),
+        }
     }
 }
 
@@ -23,3 +91,12 @@ fn snark_worked_rpc() {
     let msg = crate::decode::rpc::parse(data, false).unwrap();
     println!("{msg}");
 }
+
+#[cfg(test)]
+#[test]
+fn snark_worker_handshake() {
+    let data = hex::decode("0700000000000000 02 fd52504300 01".replace(' ', "")).unwrap();
+    assert!(is_handshake(&data));
+    let data = hex::decode("250000000000000002020021003d0e4c640f07d941").unwrap();
+    assert!(!is_handshake(&data));
+}